@@ -0,0 +1,180 @@
+use serde_json::json;
+use std::sync::RwLock;
+
+use crossbeam_channel::{ Sender };
+use hyper::{ Body, Method, Response, StatusCode };
+use hyper::header::{ AUTHORIZATION, HeaderMap };
+use lazy_static::lazy_static;
+
+use super::{ get_404_res, get_400_res, get_query_param };
+
+use crate::CONFIG;
+use crate::data::process::{ LCD_SCREENS };
+use crate::hardware::events::{ Event, EventType };
+
+// Where admin actions end up, the main loop owns the sensors and the data manager owns the daily stats and LCD
+struct AdminSenders {
+	station: Sender<Event>,
+	manager: Sender<Event>
+}
+
+lazy_static! {
+	static ref ADMIN_SENDERS: RwLock<Option<AdminSenders>> = RwLock::new(None);
+}
+
+pub fn register_senders(station: Sender<Event>, manager: Sender<Event>) {
+	*ADMIN_SENDERS.write().unwrap() = Some(AdminSenders { station, manager });
+}
+
+fn send_events(to_manager: bool, event_types: &[EventType]) -> bool {
+	let senders = ADMIN_SENDERS.read().unwrap();
+
+	let sender = match senders.as_ref() {
+		Some(senders) if to_manager => &senders.manager,
+		Some(senders) => &senders.station,
+		None => return false
+	};
+
+	event_types.iter().all(|event_type| sender.send(Event::new(*event_type)).is_ok())
+}
+
+// Compares every byte so the response time doesn't give away how much of the token matched
+fn is_authorized(header: Option<&str>, token: &str) -> bool {
+	let given = match header.and_then(|header| header.strip_prefix("Bearer ")) {
+		Some(given) => given.trim().as_bytes(),
+		None => return false
+	};
+
+	let expected = token.as_bytes();
+
+	if given.len() != expected.len() {
+		return false;
+	}
+
+	given.iter().zip(expected.iter()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+// ?sensor= for /admin/read, the same events the scheduler sends
+fn get_read_events(sensor: &str) -> Option<Vec<EventType>> {
+	let event_types = match sensor {
+		"temp" => vec![ EventType::UpdateTemp ],
+		"wind" => vec![ EventType::UpdateWind ],
+		"rain" => vec![ EventType::UpdateRain ],
+		"pressure" => vec![ EventType::UpdatePressure ],
+		"solar" => vec![ EventType::UpdateSolar ],
+		"particulate" => vec![ EventType::UpdateParticulate ],
+		"probes" => vec![ EventType::UpdateProbes ],
+		"analog" => vec![ EventType::UpdateAnalog ],
+		"all" => vec![
+			EventType::UpdateTemp,
+			EventType::UpdateWind,
+			EventType::UpdateRain,
+			EventType::UpdatePressure,
+			EventType::UpdateSolar,
+			EventType::UpdateParticulate,
+			EventType::UpdateProbes,
+			EventType::UpdateAnalog
+		],
+		_ => return None
+	};
+
+	Some(event_types)
+}
+
+fn get_accepted_res(action: &str) -> Response<Body> {
+	Response::builder()
+		.status(StatusCode::ACCEPTED)
+		.header("Content-Type", "application/json")
+		.body(json!({ "status": "accepted", "action": action }).to_string().into())
+		.unwrap()
+}
+
+fn get_unavailable_res() -> Response<Body> {
+	Response::builder()
+		.status(StatusCode::SERVICE_UNAVAILABLE)
+		.header("Content-Type", "application/json")
+		.body(json!({ "error": "The station isn't accepting commands" }).to_string().into())
+		.unwrap()
+}
+
+fn queue(to_manager: bool, event_types: &[EventType], action: &str) -> Response<Body> {
+	if send_events(to_manager, event_types) {
+		println!("Admin: {}", action);
+
+		get_accepted_res(action)
+	} else {
+		get_unavailable_res()
+	}
+}
+
+// Everything under /api/admin, path has the /admin prefix stripped
+pub fn get_admin_res(method: &Method, path: &str, query: Option<&str>, headers: &HeaderMap) -> Response<Body> {
+	// Without a token the endpoints don't exist
+	let token = match &CONFIG.admin.token {
+		Some(token) => token,
+		None => return get_404_res()
+	};
+
+	let header = headers.get(AUTHORIZATION).and_then(|header| header.to_str().ok());
+
+	if !is_authorized(header, token) {
+		return Response::builder()
+			.status(StatusCode::UNAUTHORIZED)
+			.header("WWW-Authenticate", "Bearer")
+			.header("Content-Type", "application/json")
+			.body(json!({ "error": "Missing or invalid admin token" }).to_string().into())
+			.unwrap();
+	}
+
+	if method != Method::POST {
+		return Response::builder()
+			.status(StatusCode::METHOD_NOT_ALLOWED)
+			.header("Allow", "POST")
+			.body("Method Not Allowed!".into())
+			.unwrap();
+	}
+
+	match path {
+		"/reset-daily" => queue(true, &[ EventType::ResetDaily ], "reset daily stats"),
+		"/read" => {
+			let sensor = get_query_param(query, "sensor").unwrap_or("all");
+
+			match get_read_events(sensor) {
+				Some(event_types) => queue(false, &event_types, &format!("read {}", sensor)),
+				None => get_400_res(&format!("Unknown sensor \"{}\", use temp, wind, rain, pressure, solar, particulate, probes, analog or all", sensor))
+			}
+		},
+		"/lcd" => {
+			match get_query_param(query, "screen").map(|screen| screen.parse::<i32>()) {
+				Some(Ok(screen)) if (0..LCD_SCREENS).contains(&screen) => queue(true, &[ EventType::ShowScreen(screen) ], &format!("show LCD screen {}", screen)),
+				_ => get_400_res(&format!("screen must be a number from 0 to {}", LCD_SCREENS - 1))
+			}
+		},
+		"/exit" => queue(false, &[ EventType::Exit ], "exit"),
+		_ => get_404_res()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::api::admin::{ is_authorized, get_read_events };
+
+	#[test]
+	fn test_is_authorized() {
+		let token = "c0rrect-h0rse-battery-staple";
+
+		assert!(is_authorized(Some("Bearer c0rrect-h0rse-battery-staple"), token));
+		assert!(!is_authorized(Some("Bearer c0rrect-h0rse-battery-stapl"), token));
+		assert!(!is_authorized(Some("Bearer c0rrect-h0rse-battery-staplf"), token));
+		assert!(!is_authorized(Some("Basic c0rrect-h0rse-battery-staple"), token));
+		assert!(!is_authorized(Some("Bearer "), token));
+		assert!(!is_authorized(None, token));
+	}
+
+	#[test]
+	fn test_read_events() {
+		assert_eq!(get_read_events("wind").map(|events| events.len()), Some(1));
+		assert_eq!(get_read_events("all").map(|events| events.len()), Some(8));
+		assert!(get_read_events("humidity").is_none());
+	}
+}
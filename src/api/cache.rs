@@ -1,7 +1,6 @@
 use crate::data::process::{ DataPoint, DaytimeData };
 use std::sync::RwLock;
 use lazy_static::lazy_static;
-use serde::{ Serialize, Deserialize };
 
 struct ApiCache {
 	daytime: DaytimeData,
@@ -25,7 +24,7 @@ impl ApiCache {
 	}
 
 	pub fn get_daytime_data(&self) -> DaytimeData {
-		self.daytime.clone()
+		self.daytime
 	}
 
 	pub fn get_latest_data(&self) -> DataPoint {
@@ -40,12 +39,12 @@ lazy_static! {
 pub fn update_api_cache(daytime: Option<DaytimeData>, latest: Option<DataPoint>) {
 	let mut cache_update = API_CACHE.write().unwrap();
 
-	if daytime.is_some() {
-		cache_update.update_daytime_data(daytime.unwrap());
+	if let Some(daytime) = daytime {
+		cache_update.update_daytime_data(daytime);
 	}
 
-	if latest.is_some() {
-		cache_update.update_latest_data(latest.unwrap());
+	if let Some(latest) = latest {
+		cache_update.update_latest_data(latest);
 	}
 }
 
@@ -0,0 +1,251 @@
+use std::collections::hash_map::{ DefaultHasher };
+use std::hash::{ Hash, Hasher };
+use std::io::{ self, Write };
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+use hyper::{ Body, Method, Response, StatusCode };
+use hyper::header::{ self, HeaderMap, HeaderValue };
+use httpdate::{ fmt_http_date, parse_http_date };
+use flate2::{ Compression };
+use flate2::write::{ GzEncoder };
+
+// Anything smaller isn't worth the Pi's CPU time
+pub const GZIP_MIN_BYTES: usize = 1024;
+const GZIP_LEVEL: u32 = 6;
+
+// How long browsers can cache a preflight
+const PREFLIGHT_MAX_AGE_SECS: u32 = 600;
+
+// "*" lets any origin in, otherwise the request's Origin has to be listed exactly
+pub fn get_allowed_origin<'a>(origin: &'a str, allowed: &[String]) -> Option<&'a str> {
+	if allowed.iter().any(|allowed| allowed == "*") {
+		Some("*")
+	} else if allowed.iter().any(|allowed| allowed == origin) {
+		Some(origin)
+	} else {
+		None
+	}
+}
+
+pub fn add_cors_headers(res: &mut Response<Body>, origin: Option<&str>, allowed: &[String]) {
+	if allowed.is_empty() {
+		return;
+	}
+
+	let headers = res.headers_mut();
+
+	// The answer depends on who's asking unless every origin is allowed
+	if !allowed.iter().any(|allowed| allowed == "*") {
+		headers.append(header::VARY, HeaderValue::from_static("Origin"));
+	}
+
+	if let Some(allowed_origin) = origin.and_then(|origin| get_allowed_origin(origin, allowed)) {
+		if let Ok(value) = HeaderValue::from_str(allowed_origin) {
+			headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, value);
+			headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, HeaderValue::from_static("ETag, Last-Modified"));
+		}
+	}
+}
+
+pub fn is_preflight(method: &Method, headers: &HeaderMap) -> bool {
+	method == Method::OPTIONS && headers.contains_key(header::ORIGIN) && headers.contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+}
+
+pub fn get_preflight_res(origin: Option<&str>, allowed: &[String]) -> Response<Body> {
+	let mut res = Response::builder()
+		.status(StatusCode::NO_CONTENT)
+		.header(header::ACCESS_CONTROL_ALLOW_METHODS, "GET, POST, OPTIONS")
+		.header(header::ACCESS_CONTROL_ALLOW_HEADERS, "Authorization, Content-Type, If-None-Match, If-Modified-Since")
+		.header(header::ACCESS_CONTROL_MAX_AGE, PREFLIGHT_MAX_AGE_SECS)
+		.body(Body::empty())
+		.unwrap();
+
+	add_cors_headers(&mut res, origin, allowed);
+
+	res
+}
+
+// Weak since the body differs byte for byte once it's gzipped
+pub fn get_etag(version: &str) -> String {
+	format!("W/\"{}\"", version)
+}
+
+pub fn get_time_version(time: SystemTime) -> String {
+	format!("{:x}", time.duration_since(UNIX_EPOCH).map(|since| since.as_millis()).unwrap_or(0))
+}
+
+// For responses with parts worked out per request (staleness, strikes in the last hour), only the body itself says whether it changed
+pub fn get_body_version(body: &[u8]) -> String {
+	let mut hasher = DefaultHasher::new();
+	body.hash(&mut hasher);
+
+	format!("{:x}-{:x}", body.len(), hasher.finish())
+}
+
+// If-None-Match wins over If-Modified-Since when a client sends both, matching is weak either way.
+// Without a Last-Modified only the ETag can match
+pub fn is_not_modified(headers: &HeaderMap, etag: &str, last_modified: Option<SystemTime>) -> bool {
+	let get_header = |name| headers.get(name).and_then(|value: &HeaderValue| value.to_str().ok());
+
+	if let Some(if_none_match) = get_header(header::IF_NONE_MATCH) {
+		let etag = etag.trim_start_matches("W/");
+
+		return if_none_match.split(',')
+			.map(|tag| tag.trim())
+			.any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag);
+	}
+
+	match (last_modified, get_header(header::IF_MODIFIED_SINCE).and_then(|since| parse_http_date(since).ok())) {
+		// HTTP dates only go down to the second
+		(Some(last_modified), Some(since)) => get_unix_secs(last_modified) <= get_unix_secs(since),
+		_ => false
+	}
+}
+
+fn get_unix_secs(time: SystemTime) -> u64 {
+	time.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0)
+}
+
+pub fn add_cache_headers(res: &mut Response<Body>, cache_control: &'static str, etag: &str, last_modified: Option<SystemTime>) {
+	let headers = res.headers_mut();
+
+	headers.insert(header::CACHE_CONTROL, HeaderValue::from_static(cache_control));
+
+	if let Ok(value) = HeaderValue::from_str(etag) {
+		headers.insert(header::ETAG, value);
+	}
+
+	if let Some(Ok(value)) = last_modified.map(|last_modified| HeaderValue::from_str(&fmt_http_date(last_modified))) {
+		headers.insert(header::LAST_MODIFIED, value);
+	}
+}
+
+pub fn get_not_modified_res(cache_control: &'static str, etag: &str, last_modified: Option<SystemTime>) -> Response<Body> {
+	let mut res = Response::builder()
+		.status(StatusCode::NOT_MODIFIED)
+		.body(Body::empty())
+		.unwrap();
+
+	add_cache_headers(&mut res, cache_control, etag, last_modified);
+
+	res
+}
+
+// gzip with any q other than 0, or a bare *
+pub fn accepts_gzip(headers: &HeaderMap) -> bool {
+	let accept_encoding = match headers.get(header::ACCEPT_ENCODING).and_then(|value| value.to_str().ok()) {
+		Some(accept_encoding) => accept_encoding,
+		None => return false
+	};
+
+	accept_encoding.split(',').any(|encoding| {
+		let mut parts = encoding.split(';').map(|part| part.trim());
+		let name = parts.next().unwrap_or("");
+
+		let rejected = parts.any(|param| param.strip_prefix("q=").and_then(|q| q.parse::<f32>().ok()) == Some(0.0));
+
+		(name.eq_ignore_ascii_case("gzip") || name == "*") && !rejected
+	})
+}
+
+pub fn is_compressible(content_type: &str) -> bool {
+	content_type.starts_with("text/") || content_type == "application/javascript" || content_type == "application/json"
+}
+
+pub fn gzip(data: &[u8]) -> io::Result<Vec<u8>> {
+	let mut encoder = GzEncoder::new(Vec::with_capacity(data.len() / 2), Compression::new(GZIP_LEVEL));
+	encoder.write_all(data)?;
+
+	encoder.finish()
+}
+
+#[cfg(test)]
+mod test {
+	use crate::api::headers::{ get_allowed_origin, is_not_modified, accepts_gzip, gzip, get_etag, get_body_version };
+
+	use hyper::header::{ HeaderMap, HeaderValue };
+	use httpdate::{ fmt_http_date };
+	use flate2::read::{ GzDecoder };
+	use std::io::{ Read };
+	use std::time::{ Duration, UNIX_EPOCH };
+
+	fn get_headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+		let mut headers = HeaderMap::new();
+
+		for (name, value) in pairs.iter() {
+			headers.insert(*name, HeaderValue::from_static(value));
+		}
+
+		headers
+	}
+
+	#[test]
+	fn test_allowed_origin() {
+		let allowed = vec![ "https://dashboard.example.com".to_string() ];
+
+		assert_eq!(get_allowed_origin("https://dashboard.example.com", &allowed), Some("https://dashboard.example.com"));
+		assert_eq!(get_allowed_origin("https://evil.example.com", &allowed), None);
+		assert_eq!(get_allowed_origin("https://evil.example.com", &[ "*".to_string() ]), Some("*"));
+	}
+
+	#[test]
+	fn test_not_modified() {
+		let etag = get_etag("17c4b2");
+		let last_modified = UNIX_EPOCH + Duration::from_millis(1_630_000_000_500);
+
+		assert!(!is_not_modified(&HeaderMap::new(), &etag, Some(last_modified)));
+		assert!(is_not_modified(&get_headers(&[ ("if-none-match", "W/\"17c4b2\"") ]), &etag, Some(last_modified)));
+		assert!(is_not_modified(&get_headers(&[ ("if-none-match", "\"abc\", \"17c4b2\"") ]), &etag, Some(last_modified)));
+		assert!(!is_not_modified(&get_headers(&[ ("if-none-match", "W/\"17c4b1\"") ]), &etag, Some(last_modified)));
+
+		let mut headers = HeaderMap::new();
+		headers.insert("if-modified-since", HeaderValue::from_str(&fmt_http_date(last_modified)).unwrap());
+		assert!(is_not_modified(&headers, &etag, Some(last_modified)));
+		assert!(!is_not_modified(&headers, &etag, Some(last_modified + Duration::from_secs(1))));
+
+		// A stale ETag means modified even when the date would match
+		headers.insert("if-none-match", HeaderValue::from_static("W/\"17c4b1\""));
+		assert!(!is_not_modified(&headers, &etag, Some(last_modified)));
+
+		// Body versioned responses have no date to fall back on
+		headers.remove("if-none-match");
+		assert!(!is_not_modified(&headers, &etag, None));
+	}
+
+	#[test]
+	fn test_body_version() {
+		let body = b"{\"sensors\":{\"dht\":\"ok\"}}";
+
+		assert_eq!(get_body_version(body), get_body_version(body));
+		assert_ne!(get_body_version(body), get_body_version(b"{\"sensors\":{\"dht\":\"stale\"}}"));
+
+		let etag = get_etag(&get_body_version(body));
+		let mut headers = HeaderMap::new();
+		headers.insert("if-none-match", HeaderValue::from_str(&etag).unwrap());
+
+		assert!(is_not_modified(&headers, &etag, None));
+	}
+
+	#[test]
+	fn test_accepts_gzip() {
+		assert!(accepts_gzip(&get_headers(&[ ("accept-encoding", "gzip, deflate, br") ])));
+		assert!(accepts_gzip(&get_headers(&[ ("accept-encoding", "br;q=1.0, *;q=0.5") ])));
+		assert!(!accepts_gzip(&get_headers(&[ ("accept-encoding", "gzip;q=0, br") ])));
+		assert!(!accepts_gzip(&get_headers(&[ ("accept-encoding", "identity") ])));
+		assert!(!accepts_gzip(&HeaderMap::new()));
+	}
+
+	#[test]
+	fn test_gzip() {
+		let data = "{\"temperature\":21.5}".repeat(100);
+		let compressed = gzip(data.as_bytes()).unwrap();
+
+		assert!(compressed.len() < data.len());
+		assert_eq!(&compressed[..3], &[ 0x1f, 0x8b, 0x08 ]);
+
+		let mut decompressed = String::new();
+		GzDecoder::new(&compressed[..]).read_to_string(&mut decompressed).unwrap();
+
+		assert_eq!(decompressed, data);
+	}
+}
@@ -1,23 +1,40 @@
 pub mod cache;
+pub mod admin;
+pub mod tls;
+pub mod headers;
 
 use serde_json::json;
 use tokio::fs::File;
 use std::ffi::OsStr;
-use std::io::Read;
-use std::time::SystemTime;
-use std::path::Path;
+use std::time::{ Duration, SystemTime, UNIX_EPOCH };
+use std::path::{ Component, Path, PathBuf };
 
 use hyper::{Body, Method, Request, Response, StatusCode};
+use hyper::body::{ HttpBody };
+use hyper::header::{ self, HeaderMap, HeaderValue };
 use chrono::{ DateTime, Local };
 use tokio_util::codec::{BytesCodec, FramedRead};
 use serde::{ Serialize, Deserialize };
-use serde_json::{ Value };
 
-use cache::{ get_daytime_data, get_latest_data };
+use cache::{ get_latest_data, get_daytime_data };
+use headers::{ get_etag, get_time_version, get_body_version, is_not_modified, add_cache_headers, get_not_modified_res, add_cors_headers, is_preflight, get_preflight_res, accepts_gzip, is_compressible, gzip, GZIP_MIN_BYTES };
 
-const FORMAT: &'static str = "%Y-%m-%d %H:%M:%S";
+use crate::CONFIG;
+use crate::schedule::{ Trigger };
+use crate::units::{ Units, UnitSystem, celsius_to_fahrenheit, kph_to_mph };
+use crate::data::wind::{ Beaufort, get_wind_summary };
+
+const FORMAT: &str = "%Y-%m-%d %H:%M:%S";
 const STATIC_LOC: &str = "static";
 
+// Pages are always revalidated so a new version shows up straight away, scripts and styles can sit for a bit
+const HTML_CACHE_CONTROL: &str = "no-cache";
+const STATIC_CACHE_CONTROL: &str = "public, max-age=300";
+const API_CACHE_CONTROL: &str = "no-cache";
+
+// These change with the clock as much as with the readings, so they're never cached
+const UNCACHED_API_PATHS: [&str; 3] = [ "/schedule", "/sensors", "/health" ];
+
 fn get_404_res() -> Response<Body> {
 	Response::builder()
 		.status(StatusCode::NOT_FOUND)
@@ -25,17 +42,78 @@ fn get_404_res() -> Response<Body> {
 		.unwrap()
 }
 
-async fn get_static_file(path: &str) -> Response<Body> {
-	let body = match File::open(STATIC_LOC.to_owned() + path).await {
-        Ok(f) => {
-			let stream = FramedRead::new(f, BytesCodec::new());
-    		Body::wrap_stream(stream)
-		},
+fn get_500_res() -> Response<Body> {
+	Response::builder()
+		.status(StatusCode::INTERNAL_SERVER_ERROR)
+		.body("Internal Server Error!".into())
+		.unwrap()
+}
+
+fn get_400_res(message: &str) -> Response<Body> {
+	Response::builder()
+		.status(StatusCode::BAD_REQUEST)
+		.header("Content-Type", "application/json")
+		.body(json!({ "error": message }).to_string().into())
+		.unwrap()
+}
+
+fn get_query_param<'a>(query: Option<&'a str>, name: &str) -> Option<&'a str> {
+	query?.split('&')
+		.filter_map(|pair| pair.split_once('='))
+		.find(|(key, _)| *key == name)
+		.map(|(_, value)| value)
+}
+
+// ?units=metric|imperial|si, otherwise whatever Config.toml asks for
+fn get_units(query: Option<&str>) -> Result<Units, String> {
+	match get_query_param(query, "units") {
+		Some(system) => UnitSystem::parse(system)
+			.map(|system| system.get_units())
+			.ok_or_else(|| format!("Unknown unit system \"{}\", use metric, imperial or si", system)),
+		None => Ok(CONFIG.units.get_units())
+	}
+}
+
+fn get_units_json(units: &Units) -> serde_json::Value {
+	let mut json_data = json!({});
+
+	for (quantity, symbol) in units.get_symbols().iter() {
+		json_data[quantity] = json!(symbol);
+	}
+
+	json_data
+}
+
+// hyper passes ".." through as-is, anything but plain names would let a request climb out of STATIC_LOC
+// and read Config.toml with its admin token, database password and TLS key path
+fn get_static_path(path: &str) -> Option<PathBuf> {
+	let relative = path.trim_start_matches('/');
+
+	if relative.is_empty() || !Path::new(relative).components().all(|component| matches!(component, Component::Normal(_))) {
+		return None;
+	}
+
+	Some(Path::new(STATIC_LOC).join(relative))
+}
+
+async fn get_static_file(path: &str, req_headers: &HeaderMap) -> Response<Body> {
+	let static_path = match get_static_path(path) {
+		Some(static_path) => static_path,
+		None => return get_404_res()
+	};
+
+	let file = match File::open(static_path).await {
+        Ok(f) => f,
         Err(_) => {
             return get_404_res();
         },
     };
 
+	let metadata = match file.metadata().await {
+		Ok(metadata) if metadata.is_file() => metadata,
+		_ => return get_404_res()
+	};
+
 	const UNKNOWN_CONTENT_TYPE: &str = "text/plain";
 	let content_type = match Path::new(path).extension().and_then(OsStr::to_str) {
 		Some(ext) => match ext {
@@ -47,10 +125,25 @@ async fn get_static_file(path: &str) -> Response<Body> {
 		None => UNKNOWN_CONTENT_TYPE,
 	};
 
-	Response::builder()
+	let cache_control = if content_type == "text/html" { HTML_CACHE_CONTROL } else { STATIC_CACHE_CONTROL };
+	let last_modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+	let etag = get_etag(&format!("{:x}-{}", metadata.len(), get_time_version(last_modified)));
+
+	if is_not_modified(req_headers, &etag, Some(last_modified)) {
+		return get_not_modified_res(cache_control, &etag, Some(last_modified));
+	}
+
+	let stream = FramedRead::new(file, BytesCodec::new());
+
+	let mut res = Response::builder()
 		.header("Content-Type", content_type)
-		.body(body)
-		.unwrap()
+		.header("Content-Length", metadata.len())
+		.body(Body::wrap_stream(stream))
+		.unwrap();
+
+	add_cache_headers(&mut res, cache_control, &etag, Some(last_modified));
+
+	res
 }
 
 fn get_local_time_from_system_time(time: SystemTime) -> String {
@@ -64,7 +157,12 @@ struct ApiInfo {
 	version: String
 }
 
-async fn get_api_data(method: &Method, path: &str) -> Response<Body> {
+async fn get_api_data(method: &Method, path: &str, query: Option<&str>) -> Response<Body> {
+	let units = match get_units(query) {
+		Ok(units) => units,
+		Err(e) => return get_400_res(&e)
+	};
+
 	match (method, path) {
 		(_, "") | (_, "/") => {
 			let string = serde_json::to_string(&ApiInfo {
@@ -81,14 +179,23 @@ async fn get_api_data(method: &Method, path: &str) -> Response<Body> {
 
 			// wind speed
 			let wind_spd = data.get_anemometer_data();
+			let wind_dir = data.get_directional_data();
+			let wind_dir_label = if wind_dir.is_valid() { Some(wind_dir.get_dir_as_string()) } else { None };
 			let wind_spd_data = if wind_spd.is_valid() { json!({
 				"mph": wind_spd.get_mph(),
 				"kph": wind_spd.get_kph(),
+				"speed": units.speed.convert_kph(wind_spd.get_kph()),
+				"gust": units.speed.convert_kph(wind_spd.get_gust_kph()),
+				"gust_mph": kph_to_mph(wind_spd.get_gust_kph()),
+				"gust_kph": wind_spd.get_gust_kph(),
+				"beaufort": Beaufort::from_kph(wind_spd.get_kph()),
+				"gust_beaufort": Beaufort::from_kph(wind_spd.get_gust_kph()),
+				"calm": Beaufort::from_kph(wind_spd.get_kph()).is_calm(),
+				"summary": get_wind_summary(wind_spd.get_kph(), Some(wind_spd.get_gust_kph()), wind_dir_label.as_deref(), &units),
 				"last_updated": get_local_time_from_system_time(wind_spd.get_last_updated().unwrap())
 			}) } else { json!(null) };
 
 			// wind dir
-			let wind_dir = data.get_directional_data();
 			let wind_dir_data = if wind_dir.is_valid() { json!({
 				"dir": wind_dir.get_direction(),
 				"label": wind_dir.get_dir_as_string(),
@@ -100,6 +207,8 @@ async fn get_api_data(method: &Method, path: &str) -> Response<Body> {
 			let rain_data = if rain.is_valid() { json!({
 				"amnt_in": rain.get_amount_in(),
 				"amnt_cm": rain.get_amount_cm(),
+				"rate_per_hour": units.rain.convert_mm(rain.get_rate_mm_per_hour()),
+				"total_today": units.rain.convert_mm(get_daytime_data().rain_total),
 				"last_updated": get_local_time_from_system_time(rain.get_last_updated().unwrap())
 			}) } else { json!(null) };
 
@@ -108,16 +217,243 @@ async fn get_api_data(method: &Method, path: &str) -> Response<Body> {
 			let temp_data = if temp.is_valid() { json!({
 				"temp_f": temp.get_temp_farenheit(),
 				"temp_c": temp.get_temp_celsius(),
+				"temp": units.temperature.convert_celsius(temp.get_temp_celsius()),
 				"humidity": temp.get_humidity(),
 				"last_updated": get_local_time_from_system_time(temp.get_last_updated().unwrap())
 			})} else { json!(null) };
 
+			// temperature picked from the configured sources
+			let temp_readings = data.get_temperature_readings(&CONFIG.temperature, &CONFIG.health);
+			let temp_spread = data.get_temperature_spread(&CONFIG.temperature, &CONFIG.health);
+			let temp_selected_data = match data.get_selected_temperature(&CONFIG.temperature, &CONFIG.health) {
+				Some(selected) => json!({
+					"temp_c": selected.temperature,
+					"temp_f": celsius_to_fahrenheit(selected.temperature),
+					"temp": units.temperature.convert_celsius(selected.temperature),
+					"policy": CONFIG.temperature.policy,
+					"used": selected.sources,
+					"readings": temp_readings,
+					"spread_c": temp_spread,
+					"disagreement": temp_spread.is_some_and(|spread| spread > CONFIG.temperature.disagreement_threshold_c)
+				}),
+				None => json!(null)
+			};
+
+			// barometric pressure
+			let pressure = data.get_pressure_data();
+			let pressure_data = if pressure.is_valid() { json!({
+				"station_hpa": pressure.get_station_pressure_hpa(),
+				"sea_level_hpa": pressure.get_sea_level_pressure_hpa(),
+				"station": units.pressure.convert_hpa(pressure.get_station_pressure_hpa()),
+				"sea_level": units.pressure.convert_hpa(pressure.get_sea_level_pressure_hpa()),
+				"temp_c": pressure.get_temp_celsius(),
+				"humidity": pressure.get_humidity(),
+				"last_updated": get_local_time_from_system_time(pressure.get_last_updated().unwrap())
+			})} else { json!(null) };
+
+			// uv index
+			let uv = data.get_uv_data();
+			let uv_data = if uv.is_valid() { json!({
+				"index": uv.get_uv_index(),
+				"uva": uv.get_uva(),
+				"uvb": uv.get_uvb(),
+				"daily_max": get_daytime_data().uv_max,
+				"last_updated": get_local_time_from_system_time(uv.get_last_updated().unwrap())
+			})} else { json!(null) };
+
+			// solar radiation
+			let solar = data.get_solar_data();
+			let solar_data = if solar.is_valid() { json!({
+				"radiation_wm2": solar.get_radiation(),
+				"daily_max_wm2": get_daytime_data().solar_max,
+				"last_updated": get_local_time_from_system_time(solar.get_last_updated().unwrap())
+			})} else { json!(null) };
+
+			// particulate matter and the 24 hour AQI
+			let particulate = data.get_particulate_data();
+			let air_quality_data = if particulate.is_valid() { json!({
+				"pm1_0": particulate.get_pm1_0(),
+				"pm2_5": particulate.get_pm2_5(),
+				"pm10": particulate.get_pm10(),
+				"pm2_5_24h": data.get_particulate_averages().map(|(pm25, _)| pm25),
+				"pm10_24h": data.get_particulate_averages().map(|(_, pm10)| pm10),
+				"averaging_hours": data.get_particulate_averaging_hours(),
+				"aqi": data.get_aqi(),
+				"last_updated": get_local_time_from_system_time(particulate.get_last_updated().unwrap())
+			})} else { json!(null) };
+
+			// lightning
+			let lightning = data.get_lightning_data();
+			let now = SystemTime::now();
+			let lightning_data = json!({
+				"strikes_last_hour": lightning.get_strikes_since(Duration::from_secs(60 * 60), now),
+				"strikes_today": get_daytime_data().lightning_strikes,
+				"last_strike": lightning.get_last_strike().map(get_local_time_from_system_time),
+				"last_distance_km": lightning.get_last_distance_km(),
+				"last_energy": lightning.get_last_energy(),
+				"disturbers": lightning.get_disturbers(),
+				"noise_events": lightning.get_noise_events(),
+				"alert": data.is_lightning_alert_active(&CONFIG.lightning)
+			});
+
+			// generic analog sensors
+			let mut analog_data = json!({});
+
+			for (name, reading) in data.get_analog_data() {
+				analog_data[name] = json!({
+					"value": reading.value,
+					"unit": reading.unit,
+					"voltage": reading.voltage,
+					"last_updated": get_local_time_from_system_time(reading.last_updated)
+				});
+			}
+
+			// 1-Wire temperature probes
+			let mut probe_data = json!({});
+
+			for (name, reading) in data.get_probe_data() {
+				probe_data[name] = json!({
+					"rom_id": reading.rom_id,
+					"temp_c": reading.temperature,
+					"temp_f": reading.get_temp_farenheit(),
+					"temp": units.temperature.convert_celsius(reading.temperature),
+					"last_updated": get_local_time_from_system_time(reading.last_updated)
+				});
+			}
+
 			//json obj
 			let json_data = json!({
 				"wind": wind_spd_data,
 				"wind_dir": wind_dir_data,
 				"temp": temp_data,
-				"rain": rain_data
+				"temp_selected": temp_selected_data,
+				"rain": rain_data,
+				"pressure": pressure_data,
+				"uv": uv_data,
+				"solar": solar_data,
+				"air_quality": air_quality_data,
+				"lightning": lightning_data,
+				"analog": analog_data,
+				"probes": probe_data,
+				"sensors": data.get_sensor_states(&CONFIG.health),
+				"qc": data.get_qc_flags(),
+				"alerts": data.get_active_alerts().iter().map(|alert| alert.rule.as_str()).collect::<Vec<_>>(),
+				"units": get_units_json(&units)
+			});
+
+			Response::builder()
+				.header("Content-Type", "application/json")
+				.body(json_data.to_string().into())
+				.unwrap()
+		},
+		(&Method::GET, "/schedule") => {
+			let mut json_data = json!({});
+
+			for (name, expression) in CONFIG.schedule.get_entries() {
+				json_data[name] = match Trigger::parse(expression) {
+					Ok(Trigger::Cron(schedule)) => json!({
+						"type": "cron",
+						"expression": expression,
+						"next_run": schedule.upcoming(Local).next().map(|time| time.format("%FT%T%z").to_string())
+					}),
+					Ok(Trigger::Interval(interval)) => json!({
+						"type": "interval",
+						"expression": expression,
+						"interval_secs": interval.as_secs()
+					}),
+					Err(e) => json!({
+						"type": "invalid",
+						"expression": expression,
+						"error": e
+					})
+				};
+			}
+
+			Response::builder()
+				.header("Content-Type", "application/json")
+				.body(json_data.to_string().into())
+				.unwrap()
+		},
+		(&Method::GET, "/forecast") => {
+			let data = get_latest_data();
+			let pressure = data.get_pressure_data();
+
+			let json_data = if pressure.is_valid() { json!({
+				"sea_level_hpa": pressure.get_sea_level_pressure_hpa(),
+				"tendency_hpa": data.get_pressure_tendency(),
+				"sea_level": units.pressure.convert_hpa(pressure.get_sea_level_pressure_hpa()),
+				"tendency": data.get_pressure_tendency().map(|tendency| units.pressure.convert_hpa(tendency)),
+				"units": get_units_json(&units),
+				"trend": data.get_pressure_trend(),
+				"trend_label": data.get_pressure_trend().map(|trend| trend.get_label()),
+				"zambretti": data.get_forecast(CONFIG.forecast.northern_hemisphere),
+				"last_updated": get_local_time_from_system_time(pressure.get_last_updated().unwrap())
+			}) } else { json!(null) };
+
+			Response::builder()
+				.header("Content-Type", "application/json")
+				.body(json_data.to_string().into())
+				.unwrap()
+		},
+		(&Method::GET, "/stats") => {
+			let data = get_latest_data();
+			let dht_stats = data.get_dht_stats();
+
+			let json_data = json!({
+				"dht": {
+					"reads": dht_stats.reads,
+					"successes": dht_stats.successes,
+					"checksum_errors": dht_stats.checksum_errors,
+					"timeouts": dht_stats.timeouts,
+					"invalid_values": dht_stats.invalid_values,
+					"error_rate": dht_stats.get_error_rate(),
+					"recent_error_rate": dht_stats.get_recent_error_rate()
+				}
+			});
+
+			Response::builder()
+				.header("Content-Type", "application/json")
+				.body(json_data.to_string().into())
+				.unwrap()
+		},
+		(&Method::GET, "/probes") => {
+			let data = get_latest_data();
+			let mut json_data = json!({});
+
+			// The last day of readings for each probe as [time, temp_c] pairs, for graphing
+			for (name, samples) in data.get_probe_history().get_samples() {
+				json_data[name] = json!(samples.iter()
+					.map(|(time, temperature)| json!([ get_local_time_from_system_time(*time), temperature ]))
+					.collect::<Vec<_>>());
+			}
+
+			Response::builder()
+				.header("Content-Type", "application/json")
+				.body(json_data.to_string().into())
+				.unwrap()
+		},
+		(&Method::GET, "/alerts") => {
+			let data = get_latest_data();
+
+			let active: Vec<_> = data.get_active_alerts().iter().map(|alert| {
+				let mut json_data = json!(alert);
+				json_data["raised_at"] = json!(get_local_time_from_system_time(alert.raised_at));
+
+				json_data
+			}).collect();
+
+			// Most recent first
+			let events: Vec<_> = data.get_alert_history().iter().rev().map(|event| {
+				let mut json_data = json!(event);
+				json_data["time"] = json!(get_local_time_from_system_time(event.time));
+				json_data["alert"]["raised_at"] = json!(get_local_time_from_system_time(event.alert.raised_at));
+
+				json_data
+			}).collect();
+
+			let json_data = json!({
+				"active": active,
+				"events": events
 			});
 
 			Response::builder()
@@ -125,30 +461,190 @@ async fn get_api_data(method: &Method, path: &str) -> Response<Body> {
 				.body(json_data.to_string().into())
 				.unwrap()
 		},
+		(&Method::GET, "/sensors") => {
+			let json_data = json!(get_latest_data().get_sensor_states(&CONFIG.health));
+
+			Response::builder()
+				.header("Content-Type", "application/json")
+				.body(json_data.to_string().into())
+				.unwrap()
+		},
+		(&Method::GET, "/health") => {
+			let sensors = get_latest_data().get_sensor_states(&CONFIG.health);
+
+			let unhealthy: Vec<&str> = sensors.iter()
+				.filter(|(sensor, state)| CONFIG.health.is_critical(sensor) && !state.is_healthy())
+				.map(|(sensor, _)| *sensor)
+				.collect();
+
+			// Critical sensors that never registered (e.g. the data manager hasn't started) count as unhealthy too
+			let missing: Vec<&String> = CONFIG.health.critical.iter()
+				.filter(|sensor| !sensors.contains_key(sensor.as_str()))
+				.collect();
+
+			let healthy = unhealthy.is_empty() && missing.is_empty();
+
+			let json_data = json!({
+				"status": if healthy { "ok" } else { "degraded" },
+				"unhealthy": unhealthy,
+				"missing": missing,
+				"sensors": sensors
+			});
+
+			Response::builder()
+				.status(if healthy { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE })
+				.header("Content-Type", "application/json")
+				.body(json_data.to_string().into())
+				.unwrap()
+		},
 		_ => {
 			get_404_res()
 		}
 	}
 }
 
+// Apart from the clock driven endpoints, API responses are keyed on their own body. Staleness, lightning
+// counts and alerts change without the readings changing, so the last reading time alone isn't enough
+async fn get_cached_api_data(method: &Method, path: &str, query: Option<&str>, req_headers: &HeaderMap) -> Response<Body> {
+	let mut res = get_api_data(method, path, query).await;
+
+	if method != Method::GET || UNCACHED_API_PATHS.contains(&path) {
+		res.headers_mut().insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+
+		return res;
+	}
+
+	if res.status() != StatusCode::OK {
+		return res;
+	}
+
+	let (parts, body) = res.into_parts();
+
+	let bytes = match hyper::body::to_bytes(body).await {
+		Ok(bytes) => bytes,
+		Err(e) => {
+			println!("Failed to read API response body: {}", e);
+
+			return get_500_res();
+		}
+	};
+
+	let etag = get_etag(&get_body_version(&bytes));
+	let last_reading = get_latest_data().get_last_reading_time();
+
+	// Last-Modified tells clients when the newest reading came in, but only the ETag decides a 304 since
+	// staleness and alerts can change the body without a new reading
+	if is_not_modified(req_headers, &etag, None) {
+		return get_not_modified_res(API_CACHE_CONTROL, &etag, last_reading);
+	}
+
+	let mut res = Response::from_parts(parts, Body::from(bytes));
+	add_cache_headers(&mut res, API_CACHE_CONTROL, &etag, last_reading);
+
+	res
+}
+
+// Gzips text responses over GZIP_MIN_BYTES for clients that accept it
+async fn compress_res(res: Response<Body>, req_headers: &HeaderMap) -> Response<Body> {
+	let content_type = res.headers().get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok()).unwrap_or("");
+
+	if res.status() != StatusCode::OK || !is_compressible(content_type) || res.headers().contains_key(header::CONTENT_ENCODING) {
+		return res;
+	}
+
+	let (mut parts, body) = res.into_parts();
+	parts.headers.append(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+
+	let size = parts.headers.get(header::CONTENT_LENGTH)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.parse::<u64>().ok())
+		.or_else(|| body.size_hint().exact());
+
+	if !accepts_gzip(req_headers) || size.is_none_or(|size| size < GZIP_MIN_BYTES as u64) {
+		return Response::from_parts(parts, body);
+	}
+
+	let bytes = match hyper::body::to_bytes(body).await {
+		Ok(bytes) => bytes,
+		Err(e) => {
+			println!("Failed to read response body for compression: {}", e);
+
+			return get_500_res();
+		}
+	};
+
+	let compressed = match gzip(&bytes) {
+		Ok(compressed) => compressed,
+		Err(e) => {
+			println!("Failed to gzip response, sending it uncompressed: {}", e);
+
+			return Response::from_parts(parts, bytes.into());
+		}
+	};
+
+	parts.headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+	parts.headers.insert(header::CONTENT_LENGTH, HeaderValue::from(compressed.len()));
+
+	Response::from_parts(parts, compressed.into())
+}
+
 pub async fn api_service(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
-    let res = match (req.method(), req.uri().path()) {
-        (&Method::GET, "/") => get_static_file("/index.html").await,
+	let is_api = req.uri().path().starts_with("/api");
+	let origin = req.headers().get(header::ORIGIN).and_then(|value| value.to_str().ok());
+	let cors_origins = &CONFIG.server.cors_origins;
+
+    let mut res = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/") => get_static_file("/index.html", req.headers()).await,
         (method, path) => {
-			if method == &Method::GET && Path::new(path).extension().is_some() {
-				get_static_file(path).await
+			if method == Method::GET && Path::new(path).extension().is_some() {
+				get_static_file(path, req.headers()).await
+			} else if is_api && is_preflight(method, req.headers()) {
+				get_preflight_res(origin, cors_origins)
+			} else if path.starts_with("/api/admin/") {
+				admin::get_admin_res(method, &path[10..], req.uri().query(), req.headers())
+			} else if is_api {
+				get_cached_api_data(method, &path[4..], req.uri().query(), req.headers()).await
 			} else {
-				match &path[..4] {
-					"/api" => {
-						get_api_data(method, &path[4..]).await
-					},
-					_ => get_404_res()
-				}
+				get_404_res()
 			}
         }
     };
 
+	if is_api {
+		add_cors_headers(&mut res, origin, cors_origins);
+	}
+
+	if CONFIG.server.gzip {
+		res = compress_res(res, req.headers()).await;
+	}
+
 	println!("[{}] {} \"{}\" -- {}", Local::now().format(FORMAT), req.method(), req.uri().path(), res.status().as_u16());
 
 	Ok(res)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+	use hyper::{ StatusCode };
+	use hyper::header::{ HeaderMap };
+	use crate::api::{ get_static_path, get_static_file };
+
+	#[test]
+	fn test_static_path() {
+		assert_eq!(get_static_path("/index.html").unwrap().to_str(), Some("static/index.html"));
+		assert_eq!(get_static_path("/js/main.js").unwrap().to_str(), Some("static/js/main.js"));
+
+		assert!(get_static_path("/../Config.toml").is_none());
+		assert!(get_static_path("/js/../../Config.toml").is_none());
+		assert!(get_static_path("/./index.html").is_none());
+		assert!(get_static_path("//etc/passwd").is_some_and(|path| path.starts_with("static")));
+		assert!(get_static_path("/").is_none());
+	}
+
+	#[tokio::test]
+	async fn test_static_file_traversal() {
+		assert_eq!(get_static_file("/index.html", &HeaderMap::new()).await.status(), StatusCode::OK);
+		assert_eq!(get_static_file("/../Config.toml", &HeaderMap::new()).await.status(), StatusCode::NOT_FOUND);
+		assert_eq!(get_static_file("/../example.Config.toml", &HeaderMap::new()).await.status(), StatusCode::NOT_FOUND);
+	}
+}
@@ -0,0 +1,152 @@
+use std::error::Error;
+use std::fs::{ read };
+use std::io;
+
+use hyper::server::accept::{ self, Accept };
+use openssl::pkcs12::{ Pkcs12 };
+use openssl::pkey::{ PKey };
+use openssl::stack::{ Stack };
+use openssl::x509::{ X509 };
+use tokio::net::{ TcpListener, TcpStream };
+use tokio::sync::mpsc;
+use tokio_native_tls::{ TlsAcceptor, TlsStream };
+
+// Only lives in memory, native-tls just needs the PEM files bundled up as PKCS #12
+const IDENTITY_PASSWORD: &str = "pi-weather-station";
+
+pub fn get_tls_acceptor(cert_path: &str, key_path: &str) -> Result<TlsAcceptor, Box<dyn Error + Send + Sync>> {
+	let mut certs = X509::stack_from_pem(&read(cert_path)?)?.into_iter();
+	let cert = certs.next().ok_or_else(|| format!("No certificate found in {}", cert_path))?;
+	let key = PKey::private_key_from_pem(&read(key_path)?)?;
+
+	let mut chain = Stack::new()?;
+
+	for intermediate in certs {
+		chain.push(intermediate)?;
+	}
+
+	let mut builder = Pkcs12::builder();
+	builder.ca(chain);
+
+	let der = builder.build(IDENTITY_PASSWORD, "pi-weather-station", &key, &cert)?.to_der()?;
+	let identity = native_tls::Identity::from_pkcs12(&der, IDENTITY_PASSWORD)?;
+
+	Ok(TlsAcceptor::from(native_tls::TlsAcceptor::new(identity)?))
+}
+
+// Handshakes happen on their own tasks so one slow client can't hold up everyone else
+pub fn get_tls_incoming(listener: TcpListener, acceptor: TlsAcceptor) -> impl Accept<Conn = TlsStream<TcpStream>, Error = io::Error> {
+	let (conn_tx, mut conn_rx) = mpsc::unbounded_channel();
+
+	tokio::spawn(async move {
+		loop {
+			let (stream, addr) = match listener.accept().await {
+				Ok(conn) => conn,
+				Err(e) => {
+					println!("Failed to accept connection: {}", e);
+					continue;
+				}
+			};
+
+			let acceptor = acceptor.clone();
+			let conn_tx = conn_tx.clone();
+
+			tokio::spawn(async move {
+				match acceptor.accept(stream).await {
+					Ok(tls_stream) => { let _ = conn_tx.send(Ok(tls_stream)); },
+					Err(e) => println!("TLS handshake with {} failed: {}", addr, e)
+				}
+			});
+		}
+	});
+
+	accept::poll_fn(move |cx| conn_rx.poll_recv(cx))
+}
+
+#[cfg(test)]
+mod test {
+	use crate::api::tls::{ get_tls_acceptor, get_tls_incoming };
+
+	use hyper::{ Body, Response, Server };
+	use hyper::service::{ make_service_fn, service_fn };
+	use tokio::io::{ AsyncReadExt, AsyncWriteExt };
+	use tokio::net::{ TcpListener, TcpStream };
+
+	use openssl::asn1::{ Asn1Time };
+	use openssl::hash::{ MessageDigest };
+	use openssl::pkey::{ PKey };
+	use openssl::rsa::{ Rsa };
+	use openssl::x509::{ X509, X509NameBuilder };
+	use std::fs::{ write, remove_file };
+	use std::process;
+
+	// Self-signed certificate and key written to temp files, removed again by the caller
+	fn write_identity(name: &str) -> (String, String) {
+		let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+
+		let mut subject = X509NameBuilder::new().unwrap();
+		subject.append_entry_by_text("CN", "weather.local").unwrap();
+		let subject = subject.build();
+
+		let mut cert = X509::builder().unwrap();
+		cert.set_version(2).unwrap();
+		cert.set_subject_name(&subject).unwrap();
+		cert.set_issuer_name(&subject).unwrap();
+		cert.set_pubkey(&key).unwrap();
+		cert.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+		cert.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+		cert.sign(&key, MessageDigest::sha256()).unwrap();
+
+		let dir = std::env::temp_dir();
+		let cert_path = dir.join(format!("{}-{}.crt", name, process::id())).to_str().unwrap().to_string();
+		let key_path = dir.join(format!("{}-{}.key", name, process::id())).to_str().unwrap().to_string();
+
+		write(&cert_path, cert.build().to_pem().unwrap()).unwrap();
+		write(&key_path, key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+
+		(cert_path, key_path)
+	}
+
+	#[test]
+	fn test_tls_acceptor() {
+		let (cert_path, key_path) = write_identity("acceptor");
+
+		let res = get_tls_acceptor(&cert_path, &key_path);
+		let missing = get_tls_acceptor(&cert_path, "missing.key");
+
+		remove_file(&cert_path).unwrap();
+		remove_file(&key_path).unwrap();
+
+		assert!(res.is_ok());
+		assert!(missing.is_err());
+	}
+
+	#[tokio::test]
+	async fn test_https_request() {
+		let (cert_path, key_path) = write_identity("https");
+		let acceptor = get_tls_acceptor(&cert_path, &key_path).unwrap();
+
+		remove_file(&cert_path).unwrap();
+		remove_file(&key_path).unwrap();
+
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let addr = listener.local_addr().unwrap();
+
+		let service = make_service_fn(|_| async {
+			Ok::<_, hyper::Error>(service_fn(|_| async { Ok::<_, hyper::Error>(Response::new(Body::from("secure"))) }))
+		});
+
+		tokio::spawn(Server::builder(get_tls_incoming(listener, acceptor)).serve(service));
+
+		let connector = tokio_native_tls::TlsConnector::from(native_tls::TlsConnector::builder().danger_accept_invalid_certs(true).build().unwrap());
+		let mut stream = connector.connect("weather.local", TcpStream::connect(addr).await.unwrap()).await.unwrap();
+
+		stream.write_all(b"GET / HTTP/1.1\r\nHost: weather.local\r\nConnection: close\r\n\r\n").await.unwrap();
+
+		let mut res = String::new();
+		stream.read_to_string(&mut res).await.unwrap();
+
+		assert!(res.starts_with("HTTP/1.1 200 OK"), "Unexpected response: {}", res);
+		assert!(res.ends_with("secure"));
+	}
+}
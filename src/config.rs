@@ -1,11 +1,58 @@
-use serde::{ Deserialize };
-use std::fs::read_to_string;
+use serde::{ Serialize, Deserialize };
+use std::collections::{ HashMap };
+use std::error::Error;
+use std::fs::{ read_to_string, write };
+use std::net::{ IpAddr, SocketAddr };
+use std::path::{ Path };
+use std::time::{ Duration };
+
+use rppal::spi::{ Bus, SlaveSelect };
+
+use crate::schedule::{ Trigger };
+use crate::hardware::bme280::{ get_oversampling_bits, get_filter_bits };
+use crate::hardware::dht::{ DHTModel };
+use crate::hardware::analog_sensor::{ TransferFunction };
+use crate::hardware::veml6075::{ get_integration_bits };
+use crate::hardware::particulate::{ ParticulateModel };
+use crate::hardware::as3935::{ get_min_strikes_bits };
+use crate::data::temperature::{ TemperaturePolicy };
+use crate::data::alerts::{ Comparator, is_valid_metric };
+use crate::units::{ UnitSystem, Units, TemperatureUnit, SpeedUnit, RainUnit, PressureUnit };
+
+const CONFIG_PATH: &str = "Config.toml";
+pub const VANE_CALIBRATION_PATH: &str = "vane_calibration.toml";
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Config {
     pub env: String,
     pub dev: Dev,
-    pub prod: Prod
+    pub prod: Prod,
+    #[serde(default)]
+    pub calibration: Calibration,
+    #[serde(default)]
+    pub hardware: Hardware,
+    #[serde(default)]
+    pub schedule: ScheduleConfig,
+    #[serde(default)]
+    pub forecast: ForecastConfig,
+    #[serde(default)]
+    pub qc: QcConfig,
+    #[serde(default)]
+    pub health: HealthConfig,
+    #[serde(default)]
+    pub lightning: LightningConfig,
+    #[serde(default)]
+    pub temperature: TemperatureConfig,
+    #[serde(default)]
+    pub units: UnitsConfig,
+    #[serde(default = "default_alert_rules")]
+    pub alerts: Vec<AlertRule>,
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    #[serde(default)]
+    pub admin: AdminConfig,
+    #[serde(default)]
+    pub server: ServerConfig
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -24,16 +71,1591 @@ pub struct Prod {
     pub dbname: String
 }
 
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Calibration {
+    pub anemometer: AnemometerCalibration,
+    pub rain: RainCalibration,
+    pub vane: VaneCalibration,
+    pub dht: DHTCalibration
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct AnemometerCalibration {
+    pub multiplier: f32,
+    pub cup_radius_cm: f32
+}
+
+impl Default for AnemometerCalibration {
+    fn default() -> Self {
+        Self {
+            multiplier: 1.18,
+            cup_radius_cm: 9.0
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct RainCalibration {
+    pub multiplier: f32,
+    pub bucket_size_mm: f32
+}
+
+impl Default for RainCalibration {
+    fn default() -> Self {
+        Self {
+            multiplier: 1.0,
+            bucket_size_mm: 0.2794
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct VaneCalibration {
+    pub north_offset: f32,
+    pub output_resistance: u32,
+    // Measured voltage for each of the 16 sectors (N, NNE, ...), replaces the resistor-derived table when set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub voltages: Option<[f32; 16]>
+}
+
+impl Default for VaneCalibration {
+    fn default() -> Self {
+        Self {
+            north_offset: 0.0,
+            output_resistance: 5100,
+            voltages: None
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct DHTCalibration {
+    pub temp_multiplier: f32,
+    pub temp_offset: f32,
+    pub humidity_multiplier: f32,
+    pub humidity_offset: f32
+}
+
+impl Default for DHTCalibration {
+    fn default() -> Self {
+        Self {
+            temp_multiplier: 1.0,
+            temp_offset: 0.0,
+            humidity_multiplier: 1.0,
+            humidity_offset: 0.0
+        }
+    }
+}
+
+impl DHTCalibration {
+    pub fn apply_temp(&self, temp_c: f32) -> f32 {
+        temp_c * self.temp_multiplier + self.temp_offset
+    }
+
+    pub fn apply_humidity(&self, humidity: f32) -> f32 {
+        (humidity * self.humidity_multiplier + self.humidity_offset).clamp(0.0, 100.0)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Hardware {
+    pub dht: DHTConfig,
+    pub anemometer: PinConfig,
+    pub rain: PinConfig,
+    pub display: DisplayConfig,
+    pub mcp3008: MCP3008Config,
+    pub vane: VaneConfig,
+    pub bme280: BME280Config,
+    pub veml6075: VEML6075Config,
+    pub pyranometer: PyranometerConfig,
+    pub particulate: ParticulateConfig,
+    pub as3935: AS3935Config,
+    pub ds18b20: DS18B20Config,
+    pub analog: Vec<AnalogSensorConfig>
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct PinConfig {
+    pub pin: u8,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool
+}
+
+impl PinConfig {
+    pub fn new(pin: u8) -> Self {
+        Self {
+            pin,
+            enabled: true
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct DHTConfig {
+    pub enabled: bool,
+    pub pin: u8,
+    pub model: DHTModel,
+    pub retries: u8
+}
+
+impl Default for DHTConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            pin: 4,
+            model: DHTModel::DHT22,
+            retries: 2
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct DisplayConfig {
+    pub enabled: bool,
+    pub rs_pin: u8,
+    pub en_pin: u8,
+    pub d_pins: [u8; 4],
+    pub cols: usize,
+    pub rows: usize
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            rs_pin: 13,
+            en_pin: 19,
+            d_pins: [ 12, 16, 20, 21 ],
+            cols: 16,
+            rows: 2
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct MCP3008Config {
+    pub bus: u8,
+    pub slave_select: u8,
+    pub clock_speed: u32
+}
+
+impl Default for MCP3008Config {
+    fn default() -> Self {
+        Self {
+            bus: 0,
+            slave_select: 0,
+            clock_speed: 1000000
+        }
+    }
+}
+
+impl MCP3008Config {
+    pub fn get_bus(&self) -> Bus {
+        match self.bus {
+            1 => Bus::Spi1,
+            _ => Bus::Spi0
+        }
+    }
+
+    pub fn get_slave_select(&self) -> SlaveSelect {
+        match self.slave_select {
+            1 => SlaveSelect::Ss1,
+            2 => SlaveSelect::Ss2,
+            _ => SlaveSelect::Ss0
+        }
+    }
+
+    // BCM pins claimed by the SPI bus (MISO, MOSI, SCLK and the chip select in use)
+    fn get_reserved_pins(&self) -> Vec<u8> {
+        match self.bus {
+            1 => vec![ 19, 20, 21, [ 18, 17, 16 ][self.slave_select as usize] ],
+            _ => vec![ 9, 10, 11, [ 8, 7 ][self.slave_select as usize] ]
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AnalogSensorConfig {
+    pub name: String,
+    pub channel: u8,
+    #[serde(default)]
+    pub unit: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    pub transfer: TransferFunction
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct VaneConfig {
+    pub enabled: bool,
+    pub channel: u8
+}
+
+impl Default for VaneConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            channel: 0
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct BME280Config {
+    pub enabled: bool,
+    pub bus: u8,
+    pub address: u16,
+    pub altitude_m: f32,
+    pub oversampling_temperature: u8,
+    pub oversampling_pressure: u8,
+    pub oversampling_humidity: u8,
+    pub filter: u8
+}
+
+impl Default for BME280Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bus: 1,
+            address: 0x76,
+            altitude_m: 0.0,
+            oversampling_temperature: 1,
+            oversampling_pressure: 1,
+            oversampling_humidity: 1,
+            filter: 0
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct VEML6075Config {
+    pub enabled: bool,
+    pub bus: u8,
+    pub integration_time_ms: u16
+}
+
+impl Default for VEML6075Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bus: 1,
+            integration_time_ms: 100
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PyranometerConfig {
+    pub enabled: bool,
+    pub channel: u8,
+    pub transfer: TransferFunction          // Output voltage to W/m²
+}
+
+impl Default for PyranometerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            channel: 3,
+            transfer: TransferFunction::Linear { scale: 5000.0, offset: 0.0 }       // 5 W/m² per mV
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ParticulateConfig {
+    pub enabled: bool,
+    pub model: ParticulateModel,
+    pub port: String,
+    pub work_period_mins: u32           // 0 keeps the sensor running, otherwise it sleeps between readings
+}
+
+impl Default for ParticulateConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model: ParticulateModel::SDS011,
+            port: "/dev/serial0".to_string(),
+            work_period_mins: 0
+        }
+    }
+}
+
+impl ParticulateConfig {
+    // BCM pins claimed by the primary UART (TXD, RXD), USB adapters don't use any
+    fn get_reserved_pins(&self) -> Vec<u8> {
+        if self.port.starts_with("/dev/ttyUSB") || self.port.starts_with("/dev/ttyACM") {
+            Vec::new()
+        } else {
+            vec![ 14, 15 ]
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct AS3935Config {
+    pub enabled: bool,
+    pub bus: u8,
+    pub address: u16,
+    pub irq_pin: u8,
+    pub indoor: bool,
+    pub noise_floor: u8,                // 0-7, raised automatically when the sensor reports too much noise
+    pub watchdog_threshold: u8,         // 0-15
+    pub spike_rejection: u8,            // 0-15
+    pub min_strikes: u8,                // 1, 5, 9 or 16 strikes within 15 minutes before any are reported
+    pub mask_disturbers: bool,
+    pub tuning_capacitor: u8            // 0-15, 8pF steps to tune the antenna to 500kHz
+}
+
+impl Default for AS3935Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bus: 1,
+            address: 0x03,
+            irq_pin: 17,
+            indoor: false,
+            noise_floor: 2,
+            watchdog_threshold: 2,
+            spike_rejection: 2,
+            min_strikes: 1,
+            mask_disturbers: false,
+            tuning_capacitor: 0
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DS18B20Config {
+    pub enabled: bool,
+    pub root: String,                   // sysfs directory the w1-therm driver exposes probes under
+    pub pin: u8,                        // Set by the w1-gpio overlay, only used to check for conflicts
+    pub probes: Vec<ProbeConfig>
+}
+
+impl Default for DS18B20Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            root: "/sys/bus/w1/devices".to_string(),
+            pin: 4,
+            probes: Vec::new()
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProbeConfig {
+    pub rom_id: String,
+    pub name: String
+}
+
+// BCM pins claimed by an I2C bus (SDA, SCL)
+fn get_i2c_pins(bus: u8) -> Vec<u8> {
+    match bus {
+        0 => vec![ 0, 1 ],
+        _ => vec![ 2, 3 ]
+    }
+}
+
+impl Default for Hardware {
+    fn default() -> Self {
+        Self {
+            dht: DHTConfig::default(),
+            anemometer: PinConfig::new(5),
+            rain: PinConfig::new(6),
+            display: DisplayConfig::default(),
+            mcp3008: MCP3008Config::default(),
+            vane: VaneConfig::default(),
+            bme280: BME280Config::default(),
+            veml6075: VEML6075Config::default(),
+            pyranometer: PyranometerConfig::default(),
+            particulate: ParticulateConfig::default(),
+            as3935: AS3935Config::default(),
+            ds18b20: DS18B20Config::default(),
+            analog: Vec::new()
+        }
+    }
+}
+
+impl Hardware {
+    pub fn uses_mcp3008(&self) -> bool {
+        self.vane.enabled || self.pyranometer.enabled || self.analog.iter().any(|sensor| sensor.enabled)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match (self.mcp3008.bus, self.mcp3008.slave_select) {
+            (0, 0..=1) | (1, 0..=2) => {},
+            (bus, ss) => return Err(format!("[hardware.mcp3008] SPI{} has no slave select {}", bus, ss))
+        }
+
+        if self.vane.channel > 7 {
+            return Err(format!("[hardware.vane] channel {} is out of range, the MCP3008 has channels 0-7", self.vane.channel));
+        }
+
+        if self.veml6075.enabled {
+            if self.veml6075.bus > 1 {
+                return Err(format!("[hardware.veml6075] I2C bus {} is not supported, use bus 0 or 1", self.veml6075.bus));
+            }
+
+            if get_integration_bits(self.veml6075.integration_time_ms).is_none() {
+                return Err(format!("[hardware.veml6075] integration_time_ms must be one of 50, 100, 200, 400 or 800, got {}", self.veml6075.integration_time_ms));
+            }
+        }
+
+        if self.as3935.enabled {
+            if self.as3935.bus > 1 {
+                return Err(format!("[hardware.as3935] I2C bus {} is not supported, use bus 0 or 1", self.as3935.bus));
+            }
+
+            if !(0x01..=0x03).contains(&self.as3935.address) {
+                return Err(format!("[hardware.as3935] address 0x{:02X} is invalid, the AS3935 responds on 0x01, 0x02 or 0x03", self.as3935.address));
+            }
+
+            for (name, value, max) in [ ("noise_floor", self.as3935.noise_floor, 7), ("watchdog_threshold", self.as3935.watchdog_threshold, 15), ("spike_rejection", self.as3935.spike_rejection, 15), ("tuning_capacitor", self.as3935.tuning_capacitor, 15) ].iter() {
+                if value > max {
+                    return Err(format!("[hardware.as3935] {} must be between 0 and {}, got {}", name, max, value));
+                }
+            }
+
+            if get_min_strikes_bits(self.as3935.min_strikes).is_none() {
+                return Err(format!("[hardware.as3935] min_strikes must be one of 1, 5, 9 or 16, got {}", self.as3935.min_strikes));
+            }
+        }
+
+        let mut channels: HashMap<u8, String> = HashMap::new();
+
+        if self.vane.enabled {
+            channels.insert(self.vane.channel, "hardware.vane".to_string());
+        }
+
+        if self.pyranometer.enabled {
+            if self.pyranometer.channel > 7 {
+                return Err(format!("[hardware.pyranometer] channel {} is out of range, the MCP3008 has channels 0-7", self.pyranometer.channel));
+            }
+
+            self.pyranometer.transfer.validate().map_err(|e| format!("[hardware.pyranometer] {}", e))?;
+
+            if let Some(other) = channels.get(&self.pyranometer.channel) {
+                return Err(format!("MCP3008 channel {} is assigned to both [{}] and [hardware.pyranometer]", self.pyranometer.channel, other));
+            }
+
+            channels.insert(self.pyranometer.channel, "hardware.pyranometer".to_string());
+        }
+
+        for sensor in self.analog.iter().filter(|sensor| sensor.enabled) {
+            let owner = format!("hardware.analog \"{}\"", sensor.name);
+
+            if sensor.channel > 7 {
+                return Err(format!("[{}] channel {} is out of range, the MCP3008 has channels 0-7", owner, sensor.channel));
+            }
+
+            sensor.transfer.validate().map_err(|e| format!("[{}] {}", owner, e))?;
+
+            if let Some(other) = channels.get(&sensor.channel) {
+                return Err(format!("MCP3008 channel {} is assigned to both [{}] and [{}]", sensor.channel, other, owner));
+            }
+
+            channels.insert(sensor.channel, owner);
+        }
+
+        let mut names: Vec<&str> = self.analog.iter().map(|sensor| sensor.name.as_str()).collect();
+        names.sort_unstable();
+
+        if let Some(pair) = names.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(format!("[hardware.analog] name \"{}\" is used more than once", pair[0]));
+        }
+
+        if self.bme280.enabled {
+            if self.bme280.bus > 1 {
+                return Err(format!("[hardware.bme280] I2C bus {} is not supported, use bus 0 or 1", self.bme280.bus));
+            }
+
+            if self.bme280.address != 0x76 && self.bme280.address != 0x77 {
+                return Err(format!("[hardware.bme280] address 0x{:02X} is invalid, the BME280 responds on 0x76 or 0x77", self.bme280.address));
+            }
+
+            for (name, oversampling) in [ ("temperature", self.bme280.oversampling_temperature), ("pressure", self.bme280.oversampling_pressure), ("humidity", self.bme280.oversampling_humidity) ].iter() {
+                // 0 skips the measurement, which the station always reports and pressure and humidity compensation need t_fine from
+                if *oversampling == 0 || get_oversampling_bits(*oversampling).is_none() {
+                    return Err(format!("[hardware.bme280] oversampling_{} must be one of 1, 2, 4, 8 or 16, got {}", name, oversampling));
+                }
+            }
+
+            if get_filter_bits(self.bme280.filter).is_none() {
+                return Err(format!("[hardware.bme280] filter must be one of 0, 2, 4, 8 or 16, got {}", self.bme280.filter));
+            }
+        }
+
+        if self.ds18b20.enabled {
+            for (i, probe) in self.ds18b20.probes.iter().enumerate() {
+                if probe.name.is_empty() {
+                    return Err(format!("[hardware.ds18b20] probes[{}] must have a name", i));
+                }
+
+                if !probe.rom_id.starts_with("28-") {
+                    return Err(format!("[hardware.ds18b20] \"{}\" has ROM ID \"{}\", DS18B20 ROM IDs start with 28-", probe.name, probe.rom_id));
+                }
+
+                if self.ds18b20.probes[..i].iter().any(|other| other.name == probe.name || other.rom_id == probe.rom_id) {
+                    return Err(format!("[hardware.ds18b20] probe \"{}\" ({}) is listed more than once", probe.name, probe.rom_id));
+                }
+            }
+        }
+
+        if self.display.rows == 0 || self.display.rows > 4 {
+            return Err(format!("[hardware.display] rows must be between 1 and 4, got {}", self.display.rows));
+        }
+
+        let mut pins: Vec<(u8, String)> = Vec::new();
+
+        if self.dht.enabled {
+            pins.push((self.dht.pin, "hardware.dht".to_string()));
+        }
+
+        for (name, pin_config) in [ ("hardware.anemometer", self.anemometer), ("hardware.rain", self.rain) ].iter() {
+            if pin_config.enabled {
+                pins.push((pin_config.pin, name.to_string()));
+            }
+        }
+
+        if self.display.enabled {
+            pins.push((self.display.rs_pin, "hardware.display rs_pin".to_string()));
+            pins.push((self.display.en_pin, "hardware.display en_pin".to_string()));
+
+            for (i, pin) in self.display.d_pins.iter().enumerate() {
+                pins.push((*pin, format!("hardware.display d_pins[{}]", i)));
+            }
+        }
+
+        if self.uses_mcp3008() {
+            for pin in self.mcp3008.get_reserved_pins() {
+                pins.push((pin, format!("hardware.mcp3008 (SPI{})", self.mcp3008.bus)));
+            }
+        }
+
+        if self.particulate.enabled {
+            for pin in self.particulate.get_reserved_pins() {
+                pins.push((pin, format!("hardware.particulate ({})", self.particulate.port)));
+            }
+        }
+
+        if self.ds18b20.enabled {
+            pins.push((self.ds18b20.pin, "hardware.ds18b20 (1-Wire)".to_string()));
+        }
+
+        // Devices on the same I2C bus share its pins
+        let mut i2c_buses: Vec<(u8, &str)> = Vec::new();
+
+        if self.bme280.enabled {
+            i2c_buses.push((self.bme280.bus, "hardware.bme280"));
+        }
+
+        if self.veml6075.enabled && !i2c_buses.iter().any(|(bus, _)| *bus == self.veml6075.bus) {
+            i2c_buses.push((self.veml6075.bus, "hardware.veml6075"));
+        }
+
+        if self.as3935.enabled {
+            pins.push((self.as3935.irq_pin, "hardware.as3935 irq_pin".to_string()));
+
+            if !i2c_buses.iter().any(|(bus, _)| *bus == self.as3935.bus) {
+                i2c_buses.push((self.as3935.bus, "hardware.as3935"));
+            }
+        }
+
+        for (bus, owner) in i2c_buses {
+            for pin in get_i2c_pins(bus) {
+                pins.push((pin, format!("{} (I2C{})", owner, bus)));
+            }
+        }
+
+        let mut used: HashMap<u8, String> = HashMap::new();
+
+        for (pin, owner) in pins {
+            if pin > 27 {
+                return Err(format!("[{}] GPIO {} does not exist, BCM pins range from 0 to 27", owner, pin));
+            }
+
+            if let Some(other) = used.get(&pin) {
+                return Err(format!("GPIO {} is assigned to both [{}] and [{}]", pin, other, owner));
+            }
+
+            used.insert(pin, owner);
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ScheduleConfig {
+    pub rain: String,
+    pub wind: String,
+    pub temp: String,
+    pub pressure: String,
+    pub analog: String,
+    pub solar: String,
+    pub particulate: String,
+    pub probes: String,
+    pub midnight_refresh: String
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        Self {
+            rain: "0 0/5 * * * *".to_string(),
+            wind: "0 0/5 * * * *".to_string(),
+            temp: "0 0/1 * * * *".to_string(),
+            pressure: "0 0/1 * * * *".to_string(),
+            analog: "0 0/1 * * * *".to_string(),
+            solar: "0 0/1 * * * *".to_string(),
+            particulate: "every 10s".to_string(),
+            probes: "0 0/1 * * * *".to_string(),
+            midnight_refresh: "0 0 0 * * *".to_string()
+        }
+    }
+}
+
+impl ScheduleConfig {
+    pub fn get_entries(&self) -> Vec<(&'static str, &str)> {
+        vec![
+            ("rain", &self.rain),
+            ("wind", &self.wind),
+            ("temp", &self.temp),
+            ("pressure", &self.pressure),
+            ("analog", &self.analog),
+            ("solar", &self.solar),
+            ("particulate", &self.particulate),
+            ("probes", &self.probes),
+            ("midnight_refresh", &self.midnight_refresh)
+        ]
+    }
+
+    // The schedule entry that reads a sensor, the AS3935 reports on its interrupt instead
+    pub fn get_sensor_entry(&self, sensor: &str) -> Option<(&'static str, &str)> {
+        let name = match sensor {
+            "dht" => "temp",
+            "bme280" => "pressure",
+            "anemometer" | "vane" => "wind",
+            "rain" => "rain",
+            "veml6075" | "pyranometer" => "solar",
+            "particulate" => "particulate",
+            "ds18b20" => "probes",
+            "analog" => "analog",
+            _ => return None
+        };
+
+        self.get_entries().into_iter().find(|(entry, _)| *entry == name)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, expression) in self.get_entries() {
+            Trigger::parse(expression).map_err(|e| format!("[schedule] {}: {}", name, e))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct ForecastConfig {
+    pub northern_hemisphere: bool
+}
+
+impl Default for ForecastConfig {
+    fn default() -> Self {
+        Self {
+            northern_hemisphere: true
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct QcLimits {
+    pub min: f32,
+    pub max: f32,
+    pub max_rate_per_min: Option<f32>,       // Largest believable change per minute, unset skips the step check
+    pub persistence_mins: Option<u32>        // How long a value can stay identical before it's flagged as stuck
+}
+
+impl QcLimits {
+    pub fn new(min: f32, max: f32, max_rate_per_min: Option<f32>, persistence_mins: Option<u32>) -> Self {
+        Self {
+            min,
+            max,
+            max_rate_per_min,
+            persistence_mins
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct QcConfig {
+    pub enabled: bool,
+    pub temperature: QcLimits,          // °C
+    pub humidity: QcLimits,             // %
+    pub wind_speed: QcLimits,           // kph
+    pub wind_direction: QcLimits,       // degrees
+    pub pressure: QcLimits,             // hPa, sea level
+    pub uv_index: QcLimits,
+    pub pm2_5: QcLimits,                // µg/m³
+    pub pm10: QcLimits                  // µg/m³
+}
+
+impl Default for QcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            temperature: QcLimits::new(-50.0, 60.0, Some(3.0), Some(120)),
+            humidity: QcLimits::new(0.0, 100.0, Some(10.0), Some(360)),
+            wind_speed: QcLimits::new(0.0, 300.0, None, None),
+            wind_direction: QcLimits::new(0.0, 360.0, None, None),
+            pressure: QcLimits::new(870.0, 1090.0, Some(1.0), Some(360)),
+            // Both sit at 0 for hours at night or in clean air, so there's no persistence check
+            uv_index: QcLimits::new(0.0, 20.0, None, None),
+            pm2_5: QcLimits::new(0.0, 1000.0, None, None),
+            pm10: QcLimits::new(0.0, 1000.0, None, None)
+        }
+    }
+}
+
+impl QcConfig {
+    pub fn get_entries(&self) -> Vec<(&'static str, QcLimits)> {
+        vec![
+            ("temperature", self.temperature),
+            ("humidity", self.humidity),
+            ("wind_speed", self.wind_speed),
+            ("wind_direction", self.wind_direction),
+            ("pressure", self.pressure),
+            ("uv_index", self.uv_index),
+            ("pm2_5", self.pm2_5),
+            ("pm10", self.pm10)
+        ]
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, limits) in self.get_entries() {
+            if limits.min >= limits.max {
+                return Err(format!("[qc.{}] min ({}) must be less than max ({})", name, limits.min, limits.max));
+            }
+
+            if matches!(limits.max_rate_per_min, Some(rate) if rate <= 0.0) {
+                return Err(format!("[qc.{}] max_rate_per_min must be positive", name));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HealthConfig {
+    pub stale_after_mins: HashMap<String, u64>,    // Sensor name to how long it can go without reporting
+    pub critical: Vec<String>                      // Sensors that make /api/health fail when they aren't ok
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        let stale_after_mins = [ ("dht", 5), ("bme280", 5), ("anemometer", 15), ("vane", 15), ("rain", 15), ("veml6075", 5), ("pyranometer", 5), ("particulate", 45), ("ds18b20", 5) ].iter()
+            .map(|(sensor, mins)| (sensor.to_string(), *mins))
+            .collect();
+
+        Self {
+            stale_after_mins,
+            critical: vec![ "dht".to_string() ]
+        }
+    }
+}
+
+impl HealthConfig {
+    pub fn get_stale_after(&self, sensor: &str) -> Option<Duration> {
+        self.stale_after_mins.get(sensor).map(|mins| Duration::from_secs(mins * 60))
+    }
+
+    pub fn is_critical(&self, sensor: &str) -> bool {
+        self.critical.iter().any(|name| name == sensor)
+    }
+
+    pub fn validate(&self, schedule: &ScheduleConfig) -> Result<(), String> {
+        for (sensor, mins) in self.stale_after_mins.iter() {
+            if *mins == 0 {
+                return Err(format!("[health.stale_after_mins] {} must be at least 1 minute", sensor));
+            }
+
+            // A sensor only reports when it's read, anything shorter than the gap between reads flags it as stale every time
+            let (name, expression) = match schedule.get_sensor_entry(sensor) {
+                Some(entry) => entry,
+                None => continue
+            };
+
+            let period = Trigger::parse(expression).map_err(|e| format!("[schedule] {}: {}", name, e))?.get_period();
+
+            if Duration::from_secs(mins * 60) <= period {
+                return Err(format!("[health.stale_after_mins] {} ({} min) must be longer than the [schedule] {} period ({} s)", sensor, mins, name, period.as_secs()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct LightningConfig {
+    pub alert_distance_km: u8,
+    pub alert_clear_mins: u64           // The alert stays up until there have been no close strikes for this long
+}
+
+impl Default for LightningConfig {
+    fn default() -> Self {
+        Self {
+            alert_distance_km: 10,
+            alert_clear_mins: 30
+        }
+    }
+}
+
+impl LightningConfig {
+    pub fn get_clear_after(&self) -> Duration {
+        Duration::from_secs(self.alert_clear_mins * 60)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TemperatureConfig {
+    pub policy: TemperaturePolicy,
+    pub sources: Vec<String>,               // "dht", "bme280" or "ds18b20:<probe name>", in order of preference
+    pub disagreement_threshold_c: f32,      // Alert when the sources differ by more than this
+    pub disagreement_notify: Vec<String>    // Names of [notifications.sinks] entries told about it
+}
+
+impl Default for TemperatureConfig {
+    fn default() -> Self {
+        Self {
+            policy: TemperaturePolicy::Primary,
+            sources: vec![ "dht".to_string() ],
+            disagreement_threshold_c: 2.0,
+            disagreement_notify: Vec::new()
+        }
+    }
+}
+
+impl TemperatureConfig {
+    // Only added to the [[alerts]] rules when there's more than one source to disagree
+    pub fn get_disagreement_rule(&self) -> Option<AlertRule> {
+        if self.sources.len() < 2 {
+            return None;
+        }
+
+        Some(AlertRule {
+            notify: self.disagreement_notify.clone(),
+            ..AlertRule::new("temperature_disagreement", "temperature_spread", Comparator::Above, self.disagreement_threshold_c, 0, 0.0, 0)
+        })
+    }
+
+    pub fn validate(&self, hardware: &Hardware) -> Result<(), String> {
+        if self.sources.is_empty() {
+            return Err("[temperature] sources must list at least one sensor".to_string());
+        }
+
+        if self.disagreement_threshold_c <= 0.0 {
+            return Err(format!("[temperature] disagreement_threshold_c must be above 0, got {}", self.disagreement_threshold_c));
+        }
+
+        for source in self.sources.iter() {
+            let valid = match source.split_once(':') {
+                Some(("ds18b20", probe)) => hardware.ds18b20.probes.iter().any(|config| config.name == probe),
+                Some(_) => false,
+                None => source == "dht" || source == "bme280"
+            };
+
+            if !valid {
+                return Err(format!("[temperature] unknown source \"{}\", use dht, bme280 or ds18b20:<name of a [[hardware.ds18b20.probes]] entry>", source));
+            }
+
+            if self.sources.iter().filter(|other| *other == source).count() > 1 {
+                return Err(format!("[temperature] source \"{}\" is listed more than once", source));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// The units used on the LCD, in the console and by the API when no ?units= is given,
+// each quantity can be set on its own to override the system's choice
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct UnitsConfig {
+    pub system: UnitSystem,
+    pub temperature: Option<TemperatureUnit>,
+    pub speed: Option<SpeedUnit>,
+    pub rain: Option<RainUnit>,
+    pub pressure: Option<PressureUnit>
+}
+
+impl Default for UnitsConfig {
+    fn default() -> Self {
+        Self {
+            system: UnitSystem::Imperial,
+            temperature: None,
+            speed: None,
+            rain: None,
+            pressure: None
+        }
+    }
+}
+
+impl UnitsConfig {
+    pub fn get_units(&self) -> Units {
+        let units = self.system.get_units();
+
+        Units {
+            temperature: self.temperature.unwrap_or(units.temperature),
+            speed: self.speed.unwrap_or(units.speed),
+            rain: self.rain.unwrap_or(units.rain),
+            pressure: self.pressure.unwrap_or(units.pressure)
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AlertRule {
+    pub name: String,
+    pub metric: String,                 // See data::alerts::METRICS, or "sensor:<name>"
+    pub comparator: Comparator,
+    pub threshold: f32,
+    #[serde(default)]
+    pub duration_mins: u64,             // How long the condition has to hold before the alert is raised
+    #[serde(default)]
+    pub hysteresis: f32,                // How far back past the threshold the value has to go to clear it
+    #[serde(default)]
+    pub cooldown_mins: u64,             // How long after clearing before it can be raised again
+    #[serde(default)]
+    pub notify: Vec<String>,            // Names of [notifications.sinks] entries
+    #[serde(default)]
+    pub message: Option<String>         // Replaces [notifications] message_template for this rule
+}
+
+impl AlertRule {
+    fn new(name: &str, metric: &str, comparator: Comparator, threshold: f32, duration_mins: u64, hysteresis: f32, cooldown_mins: u64) -> Self {
+        Self {
+            name: name.to_string(),
+            metric: metric.to_string(),
+            comparator,
+            threshold,
+            duration_mins,
+            hysteresis,
+            cooldown_mins,
+            notify: Vec::new(),
+            message: None
+        }
+    }
+
+    pub fn validate_all(rules: &[AlertRule]) -> Result<(), String> {
+        for (i, rule) in rules.iter().enumerate() {
+            if rule.name.is_empty() {
+                return Err(format!("[[alerts]] entry {} must have a name", i));
+            }
+
+            if rules[..i].iter().any(|other| other.name == rule.name) {
+                return Err(format!("[[alerts]] \"{}\" is defined more than once", rule.name));
+            }
+
+            if !is_valid_metric(&rule.metric) {
+                return Err(format!("[[alerts]] \"{}\" has unknown metric \"{}\"", rule.name, rule.metric));
+            }
+
+            if rule.hysteresis < 0.0 {
+                return Err(format!("[[alerts]] \"{}\" hysteresis can't be negative, got {}", rule.name, rule.hysteresis));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    None,
+    StartTls,
+    Tls
+}
+
+fn default_smtp_port() -> u16 {
+    25
+}
+
+fn default_smtp_security() -> SmtpSecurity {
+    SmtpSecurity::None
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SinkConfig {
+    // POSTs the alert as JSON
+    Webhook {
+        url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>
+    },
+    Email {
+        server: String,
+        #[serde(default = "default_smtp_port")]
+        port: u16,
+        #[serde(default = "default_smtp_security")]
+        security: SmtpSecurity,
+        username: Option<String>,
+        password: Option<String>,
+        from: String,
+        to: Vec<String>
+    },
+    // ntfy topic URL, e.g. https://ntfy.sh/my-station
+    Ntfy {
+        url: String,
+        token: Option<String>,
+        priority: Option<u8>        // 1-5
+    },
+    // Gotify server URL, the message is sent to <url>/message with an application token
+    Gotify {
+        url: String,
+        token: String,
+        priority: Option<u8>
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    pub sinks: HashMap<String, SinkConfig>,
+    pub retries: u32,                   // Extra attempts after a failed send
+    pub retry_delay_secs: u64,
+    pub rate_limit_per_hour: u32,       // Per sink, notifications over the limit are dropped
+    pub title_template: String,
+    pub message_template: String
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            sinks: HashMap::new(),
+            retries: 3,
+            retry_delay_secs: 10,
+            rate_limit_per_hour: 12,
+            title_template: "Weather alert: {rule} {state}".to_string(),
+            message_template: "{rule} {state}, {metric} is {value} ({comparator} {threshold}) at {time}\n\n{readings}".to_string()
+        }
+    }
+}
+
+impl NotificationsConfig {
+    pub fn validate(&self, rules: &[AlertRule]) -> Result<(), String> {
+        for (name, sink) in self.sinks.iter() {
+            match sink {
+                SinkConfig::Email { to, username, password, .. } => {
+                    if to.is_empty() {
+                        return Err(format!("[notifications.sinks.{}] needs at least one address in to", name));
+                    }
+
+                    if username.is_some() != password.is_some() {
+                        return Err(format!("[notifications.sinks.{}] needs both username and password, or neither", name));
+                    }
+                },
+                SinkConfig::Ntfy { priority: Some(priority), .. } if !(1..=5).contains(priority) => {
+                    return Err(format!("[notifications.sinks.{}] priority must be between 1 and 5, got {}", name, priority));
+                },
+                _ => {}
+            }
+        }
+
+        for rule in rules.iter() {
+            if let Some(sink) = rule.notify.iter().find(|sink| !self.sinks.contains_key(*sink)) {
+                return Err(format!("[[alerts]] \"{}\" notifies \"{}\", which isn't in [notifications.sinks]", rule.name, sink));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// The admin endpoints are only served when a token is set
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct AdminConfig {
+    pub token: Option<String>
+}
+
+impl AdminConfig {
+    const MIN_TOKEN_LEN: usize = 16;
+
+    pub fn is_enabled(&self) -> bool {
+        self.token.is_some()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.token {
+            Some(token) if token.chars().count() < Self::MIN_TOKEN_LEN => {
+                Err(format!("[admin] token must be at least {} characters long", Self::MIN_TOKEN_LEN))
+            },
+            Some(token) if token.chars().any(|c| c.is_whitespace() || c.is_control()) => {
+                Err("[admin] token can't contain whitespace".to_string())
+            },
+            _ => Ok(())
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub address: String,
+    pub port: Option<u16>,              // Defaults to 8080 in prod and 3000 in dev
+    pub tls_cert: Option<String>,       // PEM certificate, followed by any intermediates
+    pub tls_key: Option<String>,        // PEM private key
+    pub cors_origins: Vec<String>,      // e.g. "https://dashboard.example.com", or "*" for any origin
+    pub gzip: bool
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            address: "0.0.0.0".to_string(),
+            port: None,
+            tls_cert: None,
+            tls_key: None,
+            cors_origins: Vec::new(),
+            gzip: true
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn get_socket_addr(&self, is_prod: bool) -> Result<SocketAddr, String> {
+        let address = self.address.parse::<IpAddr>().map_err(|_| format!("[server] address \"{}\" isn't an IP address", self.address))?;
+        let port = self.port.unwrap_or(if is_prod { 8080 } else { 3000 });
+
+        Ok(SocketAddr::new(address, port))
+    }
+
+    pub fn get_tls_paths(&self) -> Option<(&str, &str)> {
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => Some((cert, key)),
+            _ => None
+        }
+    }
+
+    // Whether the private key sits somewhere under dir, resolving relative paths and symlinks
+    pub fn is_tls_key_under(&self, dir: &Path) -> bool {
+        match (self.tls_key.as_ref().and_then(|key| Path::new(key).canonicalize().ok()), dir.canonicalize()) {
+            (Some(key), Ok(dir)) => key.starts_with(dir),
+            _ => false
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.get_socket_addr(false)?;
+
+        if self.port == Some(0) {
+            return Err("[server] port can't be 0".to_string());
+        }
+
+        if self.tls_cert.is_some() != self.tls_key.is_some() {
+            return Err("[server] needs both tls_cert and tls_key, or neither".to_string());
+        }
+
+        if let Some((cert, key)) = self.get_tls_paths() {
+            for path in [ cert, key ].iter() {
+                if !Path::new(path).is_file() {
+                    return Err(format!("[server] TLS file \"{}\" doesn't exist", path));
+                }
+            }
+        }
+
+        // Browsers send the bare scheme://host[:port], so anything else would never match
+        for origin in self.cors_origins.iter().filter(|origin| *origin != "*") {
+            let host = origin.strip_prefix("https://").or_else(|| origin.strip_prefix("http://"));
+
+            if host.is_none_or(|host| host.is_empty() || host.contains('/')) {
+                return Err(format!("[server] CORS origin \"{}\" should look like https://example.com, without a path or trailing slash", origin));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Used when Config.toml has no [[alerts]] at all
+fn default_alert_rules() -> Vec<AlertRule> {
+    vec![
+        AlertRule::new("frost", "temperature", Comparator::Below, 0.0, 10, 0.5, 60),
+        AlertRule::new("high_wind_gust", "wind_gust", Comparator::Above, 62.0, 0, 10.0, 30),
+        AlertRule::new("heavy_rain", "rain_rate", Comparator::Above, 7.6, 5, 2.0, 30),
+        AlertRule::new("heat_index_danger", "heat_index", Comparator::Above, 39.4, 10, 1.0, 60),
+        AlertRule::new("dht_failure", "sensor:dht", Comparator::Above, 0.5, 5, 0.0, 60)
+    ]
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
 impl Config {
     pub fn retrieve_config() -> Self {
-        let config_str = read_to_string("Config.toml").expect("Failed to open Config.toml");
+        let config_str = read_to_string(CONFIG_PATH).expect("Failed to open Config.toml");
 
-        let config: Config = toml::from_str(&config_str).unwrap();
+        let mut config: Config = toml::from_str(&config_str).unwrap();
+
+        // Whatever calibrate-vane measured wins over [calibration.vane]
+        if let Some(vane) = Self::load_vane_calibration(VANE_CALIBRATION_PATH).expect("Failed to read vane_calibration.toml") {
+            config.calibration.vane = vane;
+        }
 
         config
     }
 
+    // [[alerts]] plus the ones other sections imply
+    pub fn get_alert_rules(&self) -> Vec<AlertRule> {
+        let mut rules = self.alerts.clone();
+        rules.extend(self.temperature.get_disagreement_rule());
+
+        rules
+    }
+
     pub fn is_prod_env(&self) -> bool {
         "prod" == &self.env
     }
-}
\ No newline at end of file
+
+    // Kept in its own file so Config.toml and its comments are never rewritten
+    pub fn save_vane_calibration(path: &str, calibration: &VaneCalibration) -> Result<(), Box<dyn Error>> {
+        let header = "# Written by `pi-weather-station calibrate-vane`, overrides [calibration.vane] in Config.toml\n";
+
+        write(path, format!("{}{}", header, toml::to_string(calibration)?))?;
+
+        Ok(())
+    }
+
+    pub fn load_vane_calibration(path: &str) -> Result<Option<VaneCalibration>, Box<dyn Error>> {
+        if !Path::new(path).exists() {
+            return Ok(None);
+        }
+
+        Ok(Some(toml::from_str(&read_to_string(path)?)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::{ Config, VaneCalibration, Hardware, PinConfig, AnalogSensorConfig, ProbeConfig, TemperatureConfig, AlertRule, NotificationsConfig, SinkConfig, AdminConfig, ServerConfig, HealthConfig, ScheduleConfig, default_alert_rules };
+    use crate::hardware::analog_sensor::{ TransferFunction };
+
+    #[test]
+    fn test_vane_calibration_file() {
+        let path = std::env::temp_dir().join(format!("vane_calibration-{}.toml", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        assert!(Config::load_vane_calibration(path).unwrap().is_none());
+
+        let mut voltages = [0.0f32; 16];
+
+        for (i, voltage) in voltages.iter_mut().enumerate() {
+            *voltage = 0.2 + i as f32 * 0.19;
+        }
+
+        let calibration = VaneCalibration { north_offset: 247.5, voltages: Some(voltages), ..VaneCalibration::default() };
+        Config::save_vane_calibration(path, &calibration).unwrap();
+
+        let saved = std::fs::read_to_string(path).unwrap();
+        let loaded = Config::load_vane_calibration(path).unwrap().unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert!(saved.starts_with("# Written by"));
+        assert_eq!(loaded.north_offset, 247.5);
+        assert_eq!(loaded.output_resistance, calibration.output_resistance);
+        assert_eq!(loaded.voltages, Some(voltages));
+    }
+
+    #[test]
+    fn test_default_hardware_is_valid() {
+        assert!(Hardware::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_conflicting_pins() {
+        let mut hardware = Hardware::default();
+        hardware.rain = PinConfig::new(hardware.anemometer.pin);
+
+        let err = hardware.validate().unwrap_err();
+
+        assert!(err.contains("hardware.anemometer") && err.contains("hardware.rain"), "Unexpected error: {}", err);
+    }
+
+    #[test]
+    fn test_spi_pin_conflict() {
+        let mut hardware = Hardware::default();
+        hardware.mcp3008.bus = 1;
+
+        assert!(hardware.validate().is_err());
+
+        hardware.display.enabled = false;
+
+        assert!(hardware.validate().is_ok());
+    }
+
+    #[test]
+    fn test_analog_channels() {
+        let mut hardware = Hardware::default();
+
+        hardware.analog.push(AnalogSensorConfig {
+            name: "battery".to_string(),
+            channel: 1,
+            unit: "V".to_string(),
+            enabled: true,
+            transfer: TransferFunction::Linear { scale: 2.0, offset: 0.0 }
+        });
+
+        assert!(hardware.validate().is_ok());
+
+        // Channel 0 already belongs to the vane
+        hardware.analog[0].channel = hardware.vane.channel;
+        assert!(hardware.validate().unwrap_err().contains("hardware.vane"));
+
+        hardware.vane.enabled = false;
+        assert!(hardware.validate().is_ok());
+    }
+
+    #[test]
+    fn test_ds18b20_probes() {
+        let mut hardware = Hardware::default();
+        hardware.dht.enabled = false;
+        hardware.ds18b20.enabled = true;
+
+        hardware.ds18b20.probes.push(ProbeConfig { rom_id: "28-0316a2796bff".to_string(), name: "soil_10cm".to_string() });
+        hardware.ds18b20.probes.push(ProbeConfig { rom_id: "28-000005e2fdc3".to_string(), name: "pond".to_string() });
+        assert!(hardware.validate().is_ok());
+
+        hardware.ds18b20.probes[1].name = "soil_10cm".to_string();
+        assert!(hardware.validate().unwrap_err().contains("more than once"));
+
+        hardware.ds18b20.probes[1].name = "pond".to_string();
+        hardware.ds18b20.probes[1].rom_id = "10-000802b4ee58".to_string();
+        assert!(hardware.validate().is_err());
+
+        // The default w1-gpio pin is the DHT's default pin too
+        hardware.ds18b20.probes.pop();
+        hardware.dht.enabled = true;
+        assert!(hardware.validate().is_err());
+    }
+
+    #[test]
+    fn test_temperature_sources() {
+        let mut hardware = Hardware::default();
+        let mut temperature = TemperatureConfig::default();
+
+        assert!(temperature.validate(&hardware).is_ok());
+
+        temperature.sources = vec![ "ds18b20:outside".to_string(), "dht".to_string() ];
+        assert!(temperature.validate(&hardware).unwrap_err().contains("ds18b20:outside"));
+
+        hardware.ds18b20.probes.push(ProbeConfig { rom_id: "28-0316a2796bff".to_string(), name: "outside".to_string() });
+        assert!(temperature.validate(&hardware).is_ok());
+
+        temperature.sources.push("dht".to_string());
+        assert!(temperature.validate(&hardware).is_err());
+    }
+
+    #[test]
+    fn test_alert_rules() {
+        let mut rules = default_alert_rules();
+        assert!(AlertRule::validate_all(&rules).is_ok());
+
+        rules[1].name = rules[0].name.clone();
+        assert!(AlertRule::validate_all(&rules).unwrap_err().contains("more than once"));
+
+        rules[1].name = "gusts".to_string();
+        rules[1].metric = "gusts".to_string();
+        assert!(AlertRule::validate_all(&rules).unwrap_err().contains("unknown metric"));
+    }
+
+    #[test]
+    fn test_disagreement_rule() {
+        let mut temperature = TemperatureConfig::default();
+        assert!(temperature.get_disagreement_rule().is_none());
+
+        temperature.sources.push("bme280".to_string());
+        temperature.disagreement_notify = vec![ "phone".to_string() ];
+
+        let rule = temperature.get_disagreement_rule().unwrap();
+        assert_eq!(rule.metric, "temperature_spread");
+        assert_eq!(rule.threshold, temperature.disagreement_threshold_c);
+        assert!(AlertRule::validate_all(std::slice::from_ref(&rule)).is_ok());
+
+        // Its sinks are checked like any other rule's
+        let err = NotificationsConfig::default().validate(&[ rule ]).unwrap_err();
+        assert!(err.contains("temperature_disagreement") && err.contains("phone"), "Unexpected error: {}", err);
+    }
+
+    #[test]
+    fn test_notification_sinks() {
+        let mut rules = default_alert_rules();
+        let mut notifications = NotificationsConfig::default();
+
+        rules[0].notify.push("phone".to_string());
+        assert!(notifications.validate(&rules).unwrap_err().contains("phone"));
+
+        notifications.sinks.insert("phone".to_string(), SinkConfig::Ntfy { url: "https://ntfy.sh/station".to_string(), token: None, priority: Some(9) });
+        assert!(notifications.validate(&rules).unwrap_err().contains("priority"));
+
+        notifications.sinks.insert("phone".to_string(), SinkConfig::Ntfy { url: "https://ntfy.sh/station".to_string(), token: None, priority: Some(4) });
+        assert!(notifications.validate(&rules).is_ok());
+    }
+
+    #[test]
+    fn test_admin_token() {
+        let mut admin = AdminConfig::default();
+        assert!(!admin.is_enabled() && admin.validate().is_ok());
+
+        admin.token = Some("hunter2".to_string());
+        assert!(admin.validate().unwrap_err().contains("at least"));
+
+        admin.token = Some("correct horse battery staple".to_string());
+        assert!(admin.validate().unwrap_err().contains("whitespace"));
+
+        admin.token = Some("c0rrect-h0rse-battery-staple".to_string());
+        assert!(admin.is_enabled() && admin.validate().is_ok());
+    }
+
+    #[test]
+    fn test_server_address() {
+        let mut server = ServerConfig::default();
+        assert!(server.validate().is_ok());
+        assert_eq!(server.get_socket_addr(true).unwrap().to_string(), "0.0.0.0:8080");
+        assert_eq!(server.get_socket_addr(false).unwrap().to_string(), "0.0.0.0:3000");
+
+        server.address = "::1".to_string();
+        server.port = Some(8443);
+        assert_eq!(server.get_socket_addr(true).unwrap().to_string(), "[::1]:8443");
+
+        server.address = "weather.local".to_string();
+        assert!(server.validate().unwrap_err().contains("IP address"));
+
+        server.address = "127.0.0.1".to_string();
+        server.tls_cert = Some("Cargo.toml".to_string());
+        assert!(server.validate().unwrap_err().contains("tls_key"));
+
+        server.tls_key = Some("missing.key".to_string());
+        assert!(server.validate().unwrap_err().contains("missing.key"));
+    }
+
+    #[test]
+    fn test_tls_key_location() {
+        let dir = std::env::current_dir().unwrap();
+        let mut server = ServerConfig::default();
+        assert!(!server.is_tls_key_under(&dir));
+
+        server.tls_key = Some("Cargo.toml".to_string());
+        assert!(server.is_tls_key_under(&dir));
+        assert!(server.is_tls_key_under(&dir.join("src").join("..")));
+        assert!(!server.is_tls_key_under(&dir.join("src")));
+
+        server.tls_key = Some(std::env::temp_dir().to_str().unwrap().to_string());
+        assert!(!server.is_tls_key_under(&dir));
+    }
+
+    #[test]
+    fn test_cors_origins() {
+        let mut server = ServerConfig {
+            cors_origins: vec![ "*".to_string(), "https://dashboard.example.com".to_string(), "http://192.168.1.20:8000".to_string() ],
+            ..ServerConfig::default()
+        };
+
+        assert!(server.validate().is_ok());
+
+        server.cors_origins.push("https://dashboard.example.com/".to_string());
+        assert!(server.validate().unwrap_err().contains("trailing slash"));
+
+        server.cors_origins = vec![ "dashboard.example.com".to_string() ];
+        assert!(server.validate().is_err());
+    }
+
+    #[test]
+    fn test_shared_i2c_bus() {
+        let mut hardware = Hardware::default();
+        hardware.bme280.enabled = true;
+        hardware.veml6075.enabled = true;
+
+        assert!(hardware.validate().is_ok());
+
+        hardware.veml6075.integration_time_ms = 150;
+        assert!(hardware.validate().is_err());
+    }
+
+    #[test]
+    fn test_stale_after_schedule() {
+        let schedule = ScheduleConfig::default();
+        let mut health = HealthConfig::default();
+
+        assert!(health.validate(&schedule).is_ok());
+
+        // Rain is only read every 5 minutes
+        health.stale_after_mins.insert("rain".to_string(), 5);
+        let err = health.validate(&schedule).unwrap_err();
+        assert!(err.contains("rain") && err.contains("[schedule] rain"), "Unexpected error: {}", err);
+
+        health.stale_after_mins.insert("rain".to_string(), 6);
+        assert!(health.validate(&schedule).is_ok());
+
+        let schedule = ScheduleConfig { particulate: "1h".to_string(), ..ScheduleConfig::default() };
+        assert!(health.validate(&schedule).is_err());
+
+        // Sensors without a schedule only need a positive threshold
+        health.stale_after_mins.insert("as3935".to_string(), 1);
+        assert!(health.validate(&ScheduleConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn test_bme280_oversampling() {
+        let mut hardware = Hardware::default();
+        hardware.bme280.enabled = true;
+        hardware.bme280.oversampling_pressure = 16;
+
+        assert!(hardware.validate().is_ok());
+
+        hardware.bme280.oversampling_temperature = 0;
+        let err = hardware.validate().unwrap_err();
+        assert!(err.contains("oversampling_temperature"), "Unexpected error: {}", err);
+
+        hardware.bme280.oversampling_temperature = 1;
+        hardware.bme280.oversampling_humidity = 0;
+        assert!(hardware.validate().is_err());
+
+        hardware.bme280.oversampling_humidity = 3;
+        assert!(hardware.validate().is_err());
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::{ VecDeque };
+use std::time::{ Duration, SystemTime };
+use serde::{ Serialize };
+
+const AVERAGING_PERIOD: Duration = Duration::from_secs(24 * 60 * 60);
+
+// US EPA breakpoints as (concentration low, concentration high, index low, index high), PM2.5 from the 2024 revision
+const PM25_BREAKPOINTS: [(f32, f32, u32, u32); 6] = [
+    (0.0, 9.0, 0, 50),
+    (9.1, 35.4, 51, 100),
+    (35.5, 55.4, 101, 150),
+    (55.5, 125.4, 151, 200),
+    (125.5, 225.4, 201, 300),
+    (225.5, 325.4, 301, 500)
+];
+
+const PM10_BREAKPOINTS: [(f32, f32, u32, u32); 6] = [
+    (0.0, 54.0, 0, 50),
+    (55.0, 154.0, 51, 100),
+    (155.0, 254.0, 101, 150),
+    (255.0, 354.0, 151, 200),
+    (355.0, 424.0, 201, 300),
+    (425.0, 604.0, 301, 500)
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pollutant {
+    Pm25,
+    Pm10
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct AirQualityIndex {
+    pub aqi: u32,
+    pub category: &'static str,
+    pub pollutant: Pollutant
+}
+
+pub fn get_aqi_category(aqi: u32) -> &'static str {
+    match aqi {
+        0..=50 => "Good",
+        51..=100 => "Moderate",
+        101..=150 => "Unhealthy for Sensitive Groups",
+        151..=200 => "Unhealthy",
+        201..=300 => "Very Unhealthy",
+        _ => "Hazardous"
+    }
+}
+
+// Concentrations are truncated to the breakpoint table's precision before the linear interpolation
+fn get_index(concentration: f32, breakpoints: &[(f32, f32, u32, u32); 6], precision: f32) -> u32 {
+    let concentration = (concentration.max(0.0) * precision).floor() / precision;
+
+    for (c_low, c_high, i_low, i_high) in breakpoints.iter() {
+        if concentration <= *c_high {
+            let c_low = c_low.min(concentration);
+
+            return ((*i_high - *i_low) as f32 / (c_high - c_low) * (concentration - c_low) + *i_low as f32).round() as u32;
+        }
+    }
+
+    500
+}
+
+pub fn get_pm25_aqi(concentration: f32) -> u32 {
+    get_index(concentration, &PM25_BREAKPOINTS, 10.0)
+}
+
+pub fn get_pm10_aqi(concentration: f32) -> u32 {
+    get_index(concentration, &PM10_BREAKPOINTS, 1.0)
+}
+
+// The reported index is the worse of the two pollutants
+pub fn get_aqi(pm25: f32, pm10: f32) -> AirQualityIndex {
+    let (aqi, pollutant) = match (get_pm25_aqi(pm25), get_pm10_aqi(pm10)) {
+        (pm25_aqi, pm10_aqi) if pm10_aqi > pm25_aqi => (pm10_aqi, Pollutant::Pm10),
+        (pm25_aqi, _) => (pm25_aqi, Pollutant::Pm25)
+    };
+
+    AirQualityIndex {
+        aqi,
+        category: get_aqi_category(aqi),
+        pollutant
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ParticulateHistory {
+    samples: VecDeque<(SystemTime, f32, f32)>
+}
+
+impl ParticulateHistory {
+    pub fn new() -> Self {
+        Self {
+            samples: VecDeque::new()
+        }
+    }
+
+    pub fn add_sample(&mut self, time: SystemTime, pm25: f32, pm10: f32) {
+        self.samples.push_back((time, pm25, pm10));
+
+        while let Some((oldest, _, _)) = self.samples.front() {
+            match time.duration_since(*oldest) {
+                Ok(age) if age > AVERAGING_PERIOD => { self.samples.pop_front(); },
+                _ => break
+            }
+        }
+    }
+
+    // (PM2.5, PM10) averaged over the last 24 hours, or whatever part of it has been collected so far
+    pub fn get_averages(&self) -> Option<(f32, f32)> {
+        if self.samples.is_empty() {
+            return None;
+        }
+
+        let count = self.samples.len() as f32;
+        let (pm25_total, pm10_total) = self.samples.iter().fold((0.0, 0.0), |(pm25, pm10), (_, sample_pm25, sample_pm10)| (pm25 + sample_pm25, pm10 + sample_pm10));
+
+        Some((pm25_total / count, pm10_total / count))
+    }
+
+    pub fn get_covered_hours(&self) -> f32 {
+        match (self.samples.front(), self.samples.back()) {
+            (Some((oldest, _, _)), Some((newest, _, _))) => newest.duration_since(*oldest).unwrap_or_default().as_secs_f32() / 3600.0,
+            _ => 0.0
+        }
+    }
+
+    pub fn get_aqi(&self) -> Option<AirQualityIndex> {
+        self.get_averages().map(|(pm25, pm10)| get_aqi(pm25, pm10))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{ Duration, SystemTime };
+    use crate::data::air_quality::{ ParticulateHistory, Pollutant, get_aqi, get_pm25_aqi, get_pm10_aqi };
+
+    #[test]
+    fn test_breakpoints() {
+        assert_eq!(get_pm25_aqi(0.0), 0);
+        assert_eq!(get_pm25_aqi(9.0), 50);
+        assert_eq!(get_pm25_aqi(9.05), 50);
+        assert_eq!(get_pm25_aqi(35.4), 100);
+        assert_eq!(get_pm25_aqi(35.5), 101);
+        assert_eq!(get_pm25_aqi(12.0), 56);
+        assert_eq!(get_pm25_aqi(400.0), 500);
+
+        assert_eq!(get_pm10_aqi(54.9), 50);
+        assert_eq!(get_pm10_aqi(100.0), 73);
+        assert_eq!(get_pm10_aqi(425.0), 301);
+    }
+
+    #[test]
+    fn test_dominant_pollutant() {
+        let aqi = get_aqi(5.0, 160.0);
+
+        assert_eq!((aqi.aqi, aqi.pollutant, aqi.category), (103, Pollutant::Pm10, "Unhealthy for Sensitive Groups"));
+        assert_eq!(get_aqi(40.0, 20.0).pollutant, Pollutant::Pm25);
+    }
+
+    #[test]
+    fn test_24_hour_average() {
+        let start = SystemTime::now();
+        let mut history = ParticulateHistory::new();
+
+        assert!(history.get_aqi().is_none());
+
+        history.add_sample(start, 100.0, 100.0);
+        history.add_sample(start + Duration::from_secs(12 * 60 * 60), 10.0, 20.0);
+        history.add_sample(start + Duration::from_secs(20 * 60 * 60), 10.0, 30.0);
+
+        assert_eq!(history.get_averages(), Some((40.0, 50.0)));
+
+        // The first sample drops out once it's more than a day old
+        history.add_sample(start + Duration::from_secs(25 * 60 * 60), 4.0, 10.0);
+
+        assert_eq!(history.get_averages(), Some((8.0, 20.0)));
+        assert!((history.get_covered_hours() - 13.0).abs() < 0.001);
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::{ BTreeMap };
+use std::time::{ Duration, SystemTime };
+use serde::{ Serialize, Deserialize };
+
+use crate::config::{ AlertRule };
+
+// Metrics a rule can watch, in the station's base units. Sensor health is "sensor:<name>", 1 while unhealthy
+pub const METRICS: [&str; 11] = [
+    "temperature",      // °C, from the sources in [temperature]
+    "temperature_spread",   // °C, between the warmest and coolest of those sources
+    "humidity",         // %
+    "heat_index",       // °C
+    "wind_speed",       // km/h
+    "wind_gust",        // km/h
+    "rain_rate",        // mm/h
+    "pressure",         // hPa, sea level
+    "pm2_5",            // µg/m³
+    "aqi",
+    "uv_index"
+];
+
+pub fn is_valid_metric(metric: &str) -> bool {
+    match metric.split_once(':') {
+        Some(("sensor", sensor)) => !sensor.is_empty(),
+        Some(_) => false,
+        None => METRICS.contains(&metric)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Comparator {
+    Above,
+    Below
+}
+
+impl Comparator {
+    pub fn is_met(&self, value: f32, threshold: f32) -> bool {
+        match self {
+            Comparator::Above => value > threshold,
+            Comparator::Below => value < threshold
+        }
+    }
+
+    pub fn get_label(&self) -> &'static str {
+        match self {
+            Comparator::Above => "above",
+            Comparator::Below => "below"
+        }
+    }
+
+    // The threshold pushed back by the hysteresis, what a value has to get past for the alert to clear
+    pub fn get_clear_threshold(&self, threshold: f32, hysteresis: f32) -> f32 {
+        match self {
+            Comparator::Above => threshold - hysteresis,
+            Comparator::Below => threshold + hysteresis
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ActiveAlert {
+    pub rule: String,
+    pub metric: String,
+    pub comparator: Comparator,
+    pub threshold: f32,
+    pub value: f32,
+    #[serde(skip)]
+    pub raised_at: SystemTime
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertEventKind {
+    Raised,
+    Cleared
+}
+
+impl AlertEventKind {
+    pub fn get_label(&self) -> &'static str {
+        match self {
+            AlertEventKind::Raised => "raised",
+            AlertEventKind::Cleared => "cleared"
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertEvent {
+    pub kind: AlertEventKind,
+    pub alert: ActiveAlert,
+    pub value: f32,                 // The reading that raised or cleared it
+    #[serde(skip)]
+    pub time: SystemTime
+}
+
+#[derive(Debug, Clone, Default)]
+struct RuleState {
+    pending_since: Option<SystemTime>,
+    active: Option<ActiveAlert>,
+    last_cleared: Option<SystemTime>
+}
+
+pub struct AlertEngine {
+    rules: Vec<AlertRule>,
+    states: Vec<RuleState>
+}
+
+impl AlertEngine {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        let states = vec![ RuleState::default(); rules.len() ];
+
+        Self {
+            rules,
+            states
+        }
+    }
+
+    // Rules whose metric has no current reading are left as they are
+    pub fn evaluate(&mut self, metrics: &BTreeMap<String, f32>, now: SystemTime) -> Vec<AlertEvent> {
+        let mut events = Vec::new();
+
+        for (rule, state) in self.rules.iter().zip(self.states.iter_mut()) {
+            let value = match metrics.get(&rule.metric) {
+                Some(value) => *value,
+                None => {
+                    state.pending_since = None;
+                    continue;
+                }
+            };
+
+            if let Some(alert) = state.active.as_ref() {
+                if !rule.comparator.is_met(value, rule.comparator.get_clear_threshold(rule.threshold, rule.hysteresis)) {
+                    events.push(AlertEvent { kind: AlertEventKind::Cleared, alert: alert.clone(), value, time: now });
+
+                    state.active = None;
+                    state.last_cleared = Some(now);
+                    state.pending_since = None;
+                }
+
+                continue;
+            }
+
+            if !rule.comparator.is_met(value, rule.threshold) {
+                state.pending_since = None;
+                continue;
+            }
+
+            let pending_since = *state.pending_since.get_or_insert(now);
+            let held = now.duration_since(pending_since).unwrap_or_default() >= Duration::from_secs(rule.duration_mins * 60);
+            let cooled_down = state.last_cleared.is_none_or(|cleared| now.duration_since(cleared).unwrap_or_default() >= Duration::from_secs(rule.cooldown_mins * 60));
+
+            if held && cooled_down {
+                let alert = ActiveAlert {
+                    rule: rule.name.clone(),
+                    metric: rule.metric.clone(),
+                    comparator: rule.comparator,
+                    threshold: rule.threshold,
+                    value,
+                    raised_at: now
+                };
+
+                events.push(AlertEvent { kind: AlertEventKind::Raised, alert: alert.clone(), value, time: now });
+
+                state.active = Some(alert);
+            }
+        }
+
+        events
+    }
+
+    pub fn get_active(&self) -> Vec<ActiveAlert> {
+        self.states.iter().filter_map(|state| state.active.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{ BTreeMap };
+    use std::time::{ Duration, SystemTime };
+    use crate::config::{ AlertRule };
+    use crate::data::alerts::{ AlertEngine, AlertEventKind, Comparator, is_valid_metric };
+
+    fn frost_rule() -> AlertRule {
+        AlertRule {
+            name: "frost".to_string(),
+            metric: "temperature".to_string(),
+            comparator: Comparator::Below,
+            threshold: 0.0,
+            duration_mins: 10,
+            hysteresis: 0.5,
+            cooldown_mins: 30,
+            notify: Vec::new(),
+            message: None
+        }
+    }
+
+    fn temperature(value: f32) -> BTreeMap<String, f32> {
+        let mut metrics = BTreeMap::new();
+        metrics.insert("temperature".to_string(), value);
+
+        metrics
+    }
+
+    #[test]
+    fn test_duration_and_hysteresis() {
+        let start = SystemTime::now();
+        let minutes = |mins: u64| start + Duration::from_secs(mins * 60);
+        let mut engine = AlertEngine::new(vec![ frost_rule() ]);
+
+        assert!(engine.evaluate(&temperature(-1.0), minutes(0)).is_empty());
+        assert!(engine.evaluate(&temperature(-1.0), minutes(5)).is_empty());
+
+        let events = engine.evaluate(&temperature(-1.5), minutes(10));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, AlertEventKind::Raised);
+        assert_eq!(engine.get_active().len(), 1);
+
+        // Back above freezing, but not past the hysteresis
+        assert!(engine.evaluate(&temperature(0.3), minutes(11)).is_empty());
+
+        // A missing reading leaves it raised
+        assert!(engine.evaluate(&BTreeMap::new(), minutes(12)).is_empty());
+
+        let events = engine.evaluate(&temperature(0.6), minutes(13));
+        assert_eq!(events[0].kind, AlertEventKind::Cleared);
+        assert!(engine.get_active().is_empty());
+    }
+
+    #[test]
+    fn test_cooldown() {
+        let start = SystemTime::now();
+        let minutes = |mins: u64| start + Duration::from_secs(mins * 60);
+
+        let mut rule = frost_rule();
+        rule.duration_mins = 0;
+
+        let mut engine = AlertEngine::new(vec![ rule ]);
+
+        assert_eq!(engine.evaluate(&temperature(-1.0), minutes(0)).len(), 1);
+        assert_eq!(engine.evaluate(&temperature(1.0), minutes(1)).len(), 1);
+
+        // Dropping below again within the cooldown doesn't re-raise it
+        assert!(engine.evaluate(&temperature(-1.0), minutes(2)).is_empty());
+        assert!(engine.evaluate(&temperature(-1.0), minutes(20)).is_empty());
+        assert_eq!(engine.evaluate(&temperature(-1.0), minutes(31))[0].kind, AlertEventKind::Raised);
+    }
+
+    #[test]
+    fn test_metrics() {
+        assert!(is_valid_metric("wind_gust"));
+        assert!(is_valid_metric("sensor:dht"));
+        assert!(!is_valid_metric("sensor:"));
+        assert!(!is_valid_metric("dew_point"));
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::{ VecDeque };
+use std::time::{ Duration, SystemTime };
+use serde::{ Serialize };
+
+const HISTORY_LENGTH: Duration = Duration::from_secs(3 * 60 * 60);
+const MIN_TENDENCY_SPAN: Duration = Duration::from_secs(60 * 60);
+
+// Zambretti works over a 950-1050 hPa barometer scale split into 22 steps
+const ZAMBRETTI_BARO_TOP: f32 = 1050.0;
+const ZAMBRETTI_BARO_BOTTOM: f32 = 950.0;
+const ZAMBRETTI_RANGE: f32 = ZAMBRETTI_BARO_TOP - ZAMBRETTI_BARO_BOTTOM;
+
+const ZAMBRETTI_FORECASTS: [&str; 26] = [
+    "Settled fine", "Fine weather", "Becoming fine", "Fine, becoming less settled",
+    "Fine, possible showers", "Fairly fine, improving", "Fairly fine, possible showers early", "Fairly fine, showery later",
+    "Showery early, improving", "Changeable, mending", "Fairly fine, showers likely", "Rather unsettled clearing later",
+    "Unsettled, probably improving", "Showery, bright intervals", "Showery, becoming less settled", "Changeable, some rain",
+    "Unsettled, short fine intervals", "Unsettled, rain later", "Unsettled, some rain", "Mostly very unsettled",
+    "Occasional rain, worsening", "Rain at times, very unsettled", "Rain at frequent intervals", "Rain, very unsettled",
+    "Stormy, may improve", "Stormy, much rain"
+];
+
+// Forecast index for each of the 22 pressure steps, lowest pressure first
+const ZAMBRETTI_RISING: [usize; 22] = [ 25, 25, 25, 24, 24, 19, 16, 12, 11, 9, 8, 6, 5, 2, 1, 1, 0, 0, 0, 0, 0, 0 ];
+const ZAMBRETTI_STEADY: [usize; 22] = [ 25, 25, 25, 25, 25, 25, 23, 23, 22, 18, 15, 13, 10, 4, 1, 1, 0, 0, 0, 0, 0, 0 ];
+const ZAMBRETTI_FALLING: [usize; 22] = [ 25, 25, 25, 25, 25, 25, 25, 25, 23, 23, 21, 20, 17, 14, 7, 3, 1, 1, 1, 0, 0, 0 ];
+
+// Pressure adjustment (% of the barometer range) for each wind direction, N first, northern hemisphere
+const ZAMBRETTI_WIND_ADJUSTMENTS: [f32; 16] = [
+    6.0, 5.0, 5.0, 2.0,
+    -0.5, -2.0, -5.0, -8.5,
+    -12.0, -10.0, -6.0, -4.5,
+    -3.0, -0.5, 1.5, 3.0
+];
+
+const ZAMBRETTI_SUMMER_ADJUSTMENT: f32 = 7.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PressureTrend {
+    Steady,
+    RisingSlowly,
+    Rising,
+    RisingQuickly,
+    RisingRapidly,
+    FallingSlowly,
+    Falling,
+    FallingQuickly,
+    FallingRapidly
+}
+
+impl PressureTrend {
+    // WMO pressure tendency characteristics, by change in hPa over 3 hours
+    pub fn from_tendency(tendency: f32) -> Self {
+        let change = tendency.abs();
+        let rising = tendency > 0.0;
+
+        if change < 0.1 {
+            PressureTrend::Steady
+        } else if change <= 1.5 {
+            if rising { PressureTrend::RisingSlowly } else { PressureTrend::FallingSlowly }
+        } else if change <= 3.5 {
+            if rising { PressureTrend::Rising } else { PressureTrend::Falling }
+        } else if change <= 6.0 {
+            if rising { PressureTrend::RisingQuickly } else { PressureTrend::FallingQuickly }
+        } else if rising {
+            PressureTrend::RisingRapidly
+        } else {
+            PressureTrend::FallingRapidly
+        }
+    }
+
+    pub fn is_rising(&self) -> bool {
+        matches!(self, PressureTrend::RisingSlowly | PressureTrend::Rising | PressureTrend::RisingQuickly | PressureTrend::RisingRapidly)
+    }
+
+    pub fn is_falling(&self) -> bool {
+        matches!(self, PressureTrend::FallingSlowly | PressureTrend::Falling | PressureTrend::FallingQuickly | PressureTrend::FallingRapidly)
+    }
+
+    pub fn get_label(&self) -> &'static str {
+        match self {
+            PressureTrend::Steady => "Steady",
+            PressureTrend::RisingSlowly => "Rising slowly",
+            PressureTrend::Rising => "Rising",
+            PressureTrend::RisingQuickly => "Rising quickly",
+            PressureTrend::RisingRapidly => "Rising rapidly",
+            PressureTrend::FallingSlowly => "Falling slowly",
+            PressureTrend::Falling => "Falling",
+            PressureTrend::FallingQuickly => "Falling quickly",
+            PressureTrend::FallingRapidly => "Falling rapidly"
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PressureHistory {
+    samples: VecDeque<(SystemTime, f32)>
+}
+
+impl PressureHistory {
+    pub fn new() -> Self {
+        Self {
+            samples: VecDeque::new()
+        }
+    }
+
+    pub fn add_sample(&mut self, time: SystemTime, pressure: f32) {
+        self.samples.push_back((time, pressure));
+
+        while let Some((oldest, _)) = self.samples.front() {
+            match time.duration_since(*oldest) {
+                Ok(age) if age > HISTORY_LENGTH => { self.samples.pop_front(); },
+                _ => break
+            }
+        }
+    }
+
+    // Change in hPa over the last 3 hours, scaled up when the history doesn't cover the full window yet
+    pub fn get_tendency(&self) -> Option<f32> {
+        let (oldest_time, oldest) = self.samples.front()?;
+        let (newest_time, newest) = self.samples.back()?;
+
+        let span = newest_time.duration_since(*oldest_time).ok()?;
+
+        if span < MIN_TENDENCY_SPAN {
+            return None;
+        }
+
+        Some((newest - oldest) * (HISTORY_LENGTH.as_secs_f32() / span.as_secs_f32()))
+    }
+
+    pub fn get_trend(&self) -> Option<PressureTrend> {
+        self.get_tendency().map(PressureTrend::from_tendency)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ZambrettiForecast {
+    pub letter: char,
+    pub forecast: &'static str
+}
+
+// Negretti & Zambra's forecaster, wind_direction is in degrees and month is 1-12
+pub fn get_zambretti_forecast(sea_level_pressure: f32, trend: PressureTrend, wind_direction: Option<f32>, month: u32, northern_hemisphere: bool) -> ZambrettiForecast {
+    let mut pressure = sea_level_pressure;
+
+    if let Some(direction) = wind_direction {
+        let mut sector = ((direction / 22.5).round() as usize) % 16;
+
+        // The southern hemisphere table is the northern one turned around
+        if !northern_hemisphere {
+            sector = (sector + 8) % 16;
+        }
+
+        pressure += ZAMBRETTI_WIND_ADJUSTMENTS[sector] / 100.0 * ZAMBRETTI_RANGE;
+    }
+
+    let summer = if northern_hemisphere { (4..=9).contains(&month) } else { !(4..=9).contains(&month) };
+
+    if summer {
+        if trend.is_rising() {
+            pressure += ZAMBRETTI_SUMMER_ADJUSTMENT / 100.0 * ZAMBRETTI_RANGE;
+        } else if trend.is_falling() {
+            pressure -= ZAMBRETTI_SUMMER_ADJUSTMENT / 100.0 * ZAMBRETTI_RANGE;
+        }
+    }
+
+    let step = ((pressure - ZAMBRETTI_BARO_BOTTOM) / (ZAMBRETTI_RANGE / 22.0)).floor().clamp(0.0, 21.0) as usize;
+
+    let index = if trend.is_rising() {
+        ZAMBRETTI_RISING[step]
+    } else if trend.is_falling() {
+        ZAMBRETTI_FALLING[step]
+    } else {
+        ZAMBRETTI_STEADY[step]
+    };
+
+    ZambrettiForecast {
+        letter: (b'A' + index as u8) as char,
+        forecast: ZAMBRETTI_FORECASTS[index]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{ Duration, SystemTime };
+    use crate::data::forecast::{ PressureHistory, PressureTrend, get_zambretti_forecast };
+
+    #[test]
+    fn test_wmo_trend() {
+        assert_eq!(PressureTrend::from_tendency(0.05), PressureTrend::Steady);
+        assert_eq!(PressureTrend::from_tendency(1.2), PressureTrend::RisingSlowly);
+        assert_eq!(PressureTrend::from_tendency(-2.0), PressureTrend::Falling);
+        assert_eq!(PressureTrend::from_tendency(-4.0), PressureTrend::FallingQuickly);
+        assert_eq!(PressureTrend::from_tendency(7.0), PressureTrend::RisingRapidly);
+    }
+
+    #[test]
+    fn test_history_tendency() {
+        let start = SystemTime::now();
+        let mut history = PressureHistory::new();
+
+        history.add_sample(start, 1010.0);
+        history.add_sample(start + Duration::from_secs(30 * 60), 1009.5);
+        assert_eq!(history.get_tendency(), None);
+
+        history.add_sample(start + Duration::from_secs(90 * 60), 1009.0);
+        assert!((history.get_tendency().unwrap() + 2.0).abs() < 0.001);
+
+        // Samples older than 3 hours fall out of the window
+        history.add_sample(start + Duration::from_secs(4 * 60 * 60), 1008.0);
+        assert!((history.get_tendency().unwrap() + 1.2).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_zambretti() {
+        // High, rising pressure in winter with no wind is settled
+        assert_eq!(get_zambretti_forecast(1040.0, PressureTrend::Rising, None, 1, true).letter, 'A');
+
+        // Low, falling pressure with a southerly is stormy
+        assert_eq!(get_zambretti_forecast(975.0, PressureTrend::Falling, Some(180.0), 1, true).forecast, "Stormy, much rain");
+
+        // Steady 1013 hPa in winter with no wind
+        assert_eq!(get_zambretti_forecast(1013.0, PressureTrend::Steady, None, 1, true).forecast, "Fine, possible showers");
+    }
+}
\ No newline at end of file
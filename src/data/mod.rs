@@ -1,4 +1,10 @@
 pub mod process;
+pub mod forecast;
+pub mod qc;
+pub mod air_quality;
+pub mod temperature;
+pub mod wind;
+pub mod alerts;
 pub mod types;
 
 use postgres::{ Client };
@@ -6,6 +12,4 @@ use postgres::{ Client };
 pub trait DatabaseType {
     fn create_table(client: &mut Client);
     fn insert(&self, client: &mut Client);
-    fn insert_many<T: DatabaseType>(data: Vec<T>, client: &mut Client);
-    fn find_all<T: DatabaseType>() -> Vec<T>;
 }
\ No newline at end of file
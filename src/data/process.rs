@@ -1,47 +1,100 @@
-use crossbeam_channel::{ Sender, Receiver };
+use crossbeam_channel::{ Receiver };
 use std::error::{ Error };
-use std::thread::{ sleep, spawn };
+use std::fs::{ read_to_string, write, rename };
+use std::thread::{ sleep, spawn, JoinHandle };
 use std::time::{ Duration, SystemTime };
-use chrono::{ DateTime, Date, Local };
+use chrono::{ DateTime, Date, Datelike, Local };
 use chrono::offset::{ Utc };
+use std::collections::{ BTreeMap, VecDeque };
 use sysinfo::{ ProcessorExt, System, SystemExt };
 use postgres::{ Client };
+use serde_json::json;
+use serde::{ Serialize, Deserialize };
 
-use crate::config::{ Config };
+use crate::config::{ Config, HealthConfig, LightningConfig, TemperatureConfig, AnemometerCalibration, RainCalibration };
 use crate::db::{ get_client };
 use crate::hardware::events::{ Event, EventType, Payload };
-use crate::hardware::dht::{ DHTData };
-use crate::hardware::anemometer::{ AnemometerData };
-use crate::hardware::vane::{ WindVaneData };
-use crate::hardware::rain::{ RainData };
-use crate::hardware::display::{ LCDDisplay };
+use crate::hardware::dht::{ self, DHTData, DHTStats };
+use crate::hardware::anemometer::{ self, AnemometerData };
+use crate::hardware::vane::{ self, WindVaneData };
+use crate::hardware::rain::{ self, RainData };
+use crate::hardware::bme280::{ self, PressureData };
+use crate::hardware::analog_sensor::{ AnalogReading };
+use crate::hardware::veml6075::{ self, UVData };
+use crate::hardware::pyranometer::{ self, SolarData };
+use crate::hardware::particulate::{ self, ParticulateData, ParticulateReading };
+use crate::hardware::as3935::{ LightningData };
+use crate::hardware::ds18b20::{ ProbeReading, ProbeHistory };
+use crate::hardware::display::{ LCDDisplay, wrap_text };
+use crate::hardware::status::{ SensorState };
 
 use crate::api::cache::{ update_api_cache };
+use crate::notify::{ Notification, Notifier, render_template };
+use crate::units::{ Units };
 
 use super::DatabaseType;
-use super::types::{ Rain };
-
-const DISPLAY_RS_PIN: u8 = 13;
-const DISPLAY_EN_PIN: u8 = 19;
-const DISPLAY_D_PINS: [u8; 4] = [ 12, 16, 20, 21 ];
-const DISPLAY_ROWS: usize = 2;
-const DISPLAY_COLS: usize = 16;
+use super::air_quality::{ ParticulateHistory, AirQualityIndex };
+use super::qc::{ QcMask, QualityControl, Quantity, QcFlag };
+use super::wind::{ Beaufort, get_wind_summary };
+use super::alerts::{ AlertEngine, AlertEvent, AlertEventKind, ActiveAlert };
+use super::temperature::{ TemperatureReading, SelectedTemperature, select_temperature, get_selected_time, get_source_sensor, get_spread, get_heat_index };
+use super::forecast::{ PressureHistory, PressureTrend, ZambrettiForecast, get_zambretti_forecast };
+use super::types::{ Rain, AnalogSample, ProbeTemperature, QcRecord, date_format };
+
+const DISPLAY_NAME: &str = "display";
+const ALERT_HISTORY_LENGTH: usize = 50;
+pub const LCD_SCREENS: i32 = 12;
+pub const DAYTIME_DATA_PATH: &str = "daytime.json";
+// How long a stop waits for queued notifications, a sink stuck retrying shouldn't hold it up for minutes
+const NOTIFIER_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Clone)]
 pub struct DataPoint {
     dht_data: DHTData,
+    dht_stats: DHTStats,
     anemometer_data: AnemometerData,
     directional_data: WindVaneData,
-    rain_data: RainData
+    rain_data: RainData,
+    pressure_data: PressureData,
+    pressure_history: PressureHistory,
+    uv_data: UVData,
+    solar_data: SolarData,
+    particulate_data: ParticulateData,
+    particulate_history: ParticulateHistory,
+    lightning_data: LightningData,
+    analog_data: BTreeMap<String, AnalogReading>,
+    probe_data: BTreeMap<String, ProbeReading>,
+    probe_history: ProbeHistory,
+    sensor_states: BTreeMap<&'static str, SensorState>,
+    sensor_last_seen: BTreeMap<&'static str, SystemTime>,
+    qc_flags: BTreeMap<String, BTreeMap<Quantity, QcFlag>>,
+    active_alerts: Vec<ActiveAlert>,
+    alert_history: VecDeque<AlertEvent>
 }
 
 impl DataPoint { 
     pub fn new() -> Self {
         Self {
             dht_data: DHTData::new(-999.0, -999.0, None),
-            anemometer_data: AnemometerData::new(0.0, None),
+            dht_stats: DHTStats::new(),
+            anemometer_data: AnemometerData::new(0.0, 0.0, AnemometerCalibration::default(), None),
             directional_data: WindVaneData::new(0.0, None),
-            rain_data: RainData::new(0, 0.0, None)
+            rain_data: RainData::new(0, 0.0, RainCalibration::default(), None),
+            pressure_data: PressureData::new(0.0, 0.0, 0.0, None, None),
+            pressure_history: PressureHistory::new(),
+            uv_data: UVData::new(0.0, 0.0, 0.0, None),
+            solar_data: SolarData::new(0.0, None),
+            particulate_data: ParticulateData::new(ParticulateReading { pm1_0: None, pm2_5: 0.0, pm10: 0.0 }, None),
+            particulate_history: ParticulateHistory::new(),
+            lightning_data: LightningData::new(),
+            analog_data: BTreeMap::new(),
+            probe_data: BTreeMap::new(),
+            probe_history: ProbeHistory::new(),
+            sensor_states: BTreeMap::new(),
+            sensor_last_seen: BTreeMap::new(),
+            qc_flags: BTreeMap::new(),
+            active_alerts: Vec::new(),
+            alert_history: VecDeque::new()
         }
     }
 
@@ -49,6 +102,10 @@ impl DataPoint {
         self.dht_data = data;
     }
 
+    pub fn update_dht_stats(&mut self, stats: DHTStats) {
+        self.dht_stats = stats;
+    }
+
     pub fn update_anemometer(&mut self, data: AnemometerData) {
         self.anemometer_data = data;
     }
@@ -61,79 +118,365 @@ impl DataPoint {
         self.rain_data = data;
     }
 
+    pub fn update_pressure(&mut self, data: PressureData) {
+        self.pressure_data = data;
+    }
+
+    pub fn add_pressure_sample(&mut self, time: SystemTime, pressure: f32) {
+        self.pressure_history.add_sample(time, pressure);
+    }
+
+    pub fn update_uv(&mut self, data: UVData) {
+        self.uv_data = data;
+    }
+
+    pub fn update_solar(&mut self, data: SolarData) {
+        self.solar_data = data;
+    }
+
+    pub fn update_particulate(&mut self, data: ParticulateData) {
+        self.particulate_data = data;
+    }
+
+    pub fn add_particulate_sample(&mut self, time: SystemTime, pm25: f32, pm10: f32) {
+        self.particulate_history.add_sample(time, pm25, pm10);
+    }
+
+    pub fn add_lightning_strike(&mut self, time: SystemTime, distance_km: Option<u8>, energy: u32) {
+        self.lightning_data.add_strike(time, distance_km, energy);
+    }
+
+    pub fn add_lightning_disturber(&mut self) {
+        self.lightning_data.add_disturber();
+    }
+
+    pub fn add_lightning_noise_event(&mut self) {
+        self.lightning_data.add_noise_event();
+    }
+
+    pub fn update_analog(&mut self, name: String, reading: AnalogReading) {
+        self.analog_data.insert(name, reading);
+    }
+
+    pub fn get_analog_data(&self) -> &BTreeMap<String, AnalogReading> {
+        &self.analog_data
+    }
+
+    pub fn update_probe(&mut self, name: String, reading: ProbeReading) {
+        self.probe_history.add_sample(&name, reading.last_updated, reading.temperature);
+        self.probe_data.insert(name, reading);
+    }
+
+    pub fn get_probe_data(&self) -> &BTreeMap<String, ProbeReading> {
+        &self.probe_data
+    }
+
+    pub fn get_probe_history(&self) -> &ProbeHistory {
+        &self.probe_history
+    }
+
+    // Coming up at startup counts as being seen, so a sensor that never reports goes stale after its interval
+    pub fn update_sensor_state(&mut self, sensor: &'static str, state: SensorState) {
+        if state == SensorState::Ok {
+            self.sensor_last_seen.insert(sensor, SystemTime::now());
+        }
+
+        self.sensor_states.insert(sensor, state);
+    }
+
+    pub fn get_sensor_states(&self, health: &HealthConfig) -> BTreeMap<&'static str, SensorState> {
+        let now = SystemTime::now();
+
+        self.sensor_states.iter()
+            .map(|(sensor, state)| (*sensor, state.with_staleness(self.sensor_last_seen.get(sensor).copied(), health.get_stale_after(sensor), now)))
+            .collect()
+    }
+
+    // When any sensor last reported successfully
+    pub fn get_last_reading_time(&self) -> Option<SystemTime> {
+        self.sensor_last_seen.values().max().copied()
+    }
+
+    pub fn get_sensor_state(&self, sensor: &str, health: &HealthConfig) -> Option<SensorState> {
+        self.get_sensor_states(health).get(sensor).copied()
+    }
+
+    // Current values for everything an alert rule can watch, readings from stale sensors are left out
+    pub fn get_alert_metrics(&self, config: &Config) -> BTreeMap<String, f32> {
+        let sensor_states = self.get_sensor_states(&config.health);
+        let is_fresh = |sensor: &str| sensor_states.get(sensor) != Some(&SensorState::Stale);
+
+        let mut metrics = BTreeMap::new();
+
+        let temperature = self.get_selected_temperature(&config.temperature, &config.health).map(|selected| selected.temperature);
+
+        let humidity = if self.dht_data.is_valid() && is_fresh(dht::SENSOR_NAME) {
+            Some(self.dht_data.get_humidity())
+        } else if self.pressure_data.is_valid() && is_fresh(bme280::SENSOR_NAME) {
+            self.pressure_data.get_humidity()
+        } else {
+            None
+        };
+
+        if let Some(temperature) = temperature {
+            metrics.insert("temperature".to_string(), temperature);
+
+            // A single fresh source can't disagree with anything
+            let spread = self.get_temperature_spread(&config.temperature, &config.health).unwrap_or(0.0);
+            metrics.insert("temperature_spread".to_string(), spread);
+        }
+
+        if let Some(humidity) = humidity {
+            metrics.insert("humidity".to_string(), humidity);
+        }
+
+        if let (Some(temperature), Some(humidity)) = (temperature, humidity) {
+            metrics.insert("heat_index".to_string(), get_heat_index(temperature, humidity));
+        }
+
+        if self.anemometer_data.is_valid() && is_fresh(anemometer::SENSOR_NAME) {
+            metrics.insert("wind_speed".to_string(), self.anemometer_data.get_kph());
+            metrics.insert("wind_gust".to_string(), self.anemometer_data.get_gust_kph());
+        }
+
+        if self.rain_data.is_valid() && is_fresh(rain::SENSOR_NAME) {
+            metrics.insert("rain_rate".to_string(), self.rain_data.get_rate_mm_per_hour());
+        }
+
+        if self.pressure_data.is_valid() && is_fresh(bme280::SENSOR_NAME) {
+            metrics.insert("pressure".to_string(), self.pressure_data.get_sea_level_pressure_hpa());
+        }
+
+        if self.particulate_data.is_valid() && is_fresh(particulate::SENSOR_NAME) {
+            metrics.insert("pm2_5".to_string(), self.particulate_data.get_pm2_5());
+        }
+
+        if let Some(aqi) = self.get_aqi() {
+            metrics.insert("aqi".to_string(), aqi.aqi as f32);
+        }
+
+        if self.uv_data.is_valid() && is_fresh(veml6075::SENSOR_NAME) {
+            metrics.insert("uv_index".to_string(), self.uv_data.get_uv_index());
+        }
+
+        for (sensor, state) in sensor_states.iter() {
+            metrics.insert(format!("sensor:{}", sensor), if state.is_healthy() { 0.0 } else { 1.0 });
+        }
+
+        metrics
+    }
+
+    pub fn update_alerts(&mut self, active: Vec<ActiveAlert>, events: Vec<AlertEvent>) {
+        self.active_alerts = active;
+
+        for event in events {
+            if self.alert_history.len() == ALERT_HISTORY_LENGTH {
+                self.alert_history.pop_front();
+            }
+
+            self.alert_history.push_back(event);
+        }
+    }
+
+    pub fn get_active_alerts(&self) -> &Vec<ActiveAlert> {
+        &self.active_alerts
+    }
+
+    pub fn get_alert_history(&self) -> &VecDeque<AlertEvent> {
+        &self.alert_history
+    }
+
+    pub fn update_qc_flags(&mut self, source: &str, flags: BTreeMap<Quantity, QcFlag>) {
+        self.qc_flags.entry(source.to_string()).or_default().extend(flags);
+    }
+
+    pub fn get_qc_flags(&self) -> BTreeMap<String, BTreeMap<Quantity, QcFlag>> {
+        self.qc_flags.clone()
+    }
+
     pub fn get_anemometer_data(&self) -> AnemometerData {
-        self.anemometer_data.clone()
+        self.anemometer_data
     }
 
     pub fn get_directional_data(&self) -> WindVaneData {
-        self.directional_data.clone()
+        self.directional_data
     }
 
     pub fn get_rain_data(&self) -> RainData {
-        self.rain_data.clone()
+        self.rain_data
     }
 
     pub fn get_temp_data(&self) -> DHTData {
-        self.dht_data.clone()
+        self.dht_data
+    }
+
+    // The configured temperature sources that have a reading which isn't stale, in order of preference
+    pub fn get_temperature_readings(&self, config: &TemperatureConfig, health: &HealthConfig) -> Vec<TemperatureReading> {
+        let now = SystemTime::now();
+
+        config.sources.iter()
+            .filter_map(|source| {
+                let (temperature, last_updated) = match source.split_once(':') {
+                    Some((_, probe)) => self.probe_data.get(probe).map(|reading| (reading.temperature, reading.last_updated))?,
+                    None if source == "bme280" => (self.pressure_data.get_temp_celsius(), self.pressure_data.get_last_updated()?),
+                    None => (self.dht_data.get_temp_celsius(), self.dht_data.get_last_updated()?)
+                };
+
+                let stale = health.get_stale_after(get_source_sensor(source))
+                    .is_some_and(|stale_after| now.duration_since(last_updated).unwrap_or_default() > stale_after);
+
+                if stale {
+                    None
+                } else {
+                    Some(TemperatureReading {
+                        source: source.clone(),
+                        temperature,
+                        last_updated
+                    })
+                }
+            })
+            .collect()
+    }
+
+    pub fn get_selected_temperature(&self, config: &TemperatureConfig, health: &HealthConfig) -> Option<SelectedTemperature> {
+        select_temperature(config.policy, &self.get_temperature_readings(config, health))
+    }
+
+    pub fn get_temperature_spread(&self, config: &TemperatureConfig, health: &HealthConfig) -> Option<f32> {
+        get_spread(&self.get_temperature_readings(config, health))
     }
 
-    pub fn print_data(&self) {
+    pub fn get_uv_data(&self) -> UVData {
+        self.uv_data
+    }
+
+    pub fn get_solar_data(&self) -> SolarData {
+        self.solar_data
+    }
+
+    pub fn get_particulate_data(&self) -> ParticulateData {
+        self.particulate_data
+    }
+
+    // (PM2.5, PM10) over the last 24 hours
+    pub fn get_particulate_averages(&self) -> Option<(f32, f32)> {
+        self.particulate_history.get_averages()
+    }
+
+    pub fn get_particulate_averaging_hours(&self) -> f32 {
+        self.particulate_history.get_covered_hours()
+    }
+
+    pub fn get_aqi(&self) -> Option<AirQualityIndex> {
+        self.particulate_history.get_aqi()
+    }
+
+    pub fn get_lightning_data(&self) -> &LightningData {
+        &self.lightning_data
+    }
+
+    pub fn is_lightning_alert_active(&self, config: &LightningConfig) -> bool {
+        self.lightning_data.is_alert_active(config.alert_distance_km, config.get_clear_after(), SystemTime::now())
+    }
+
+    pub fn get_dht_stats(&self) -> &DHTStats {
+        &self.dht_stats
+    }
+
+    pub fn get_pressure_data(&self) -> PressureData {
+        self.pressure_data
+    }
+
+    pub fn get_pressure_tendency(&self) -> Option<f32> {
+        self.pressure_history.get_tendency()
+    }
+
+    pub fn get_pressure_trend(&self) -> Option<PressureTrend> {
+        self.pressure_history.get_trend()
+    }
+
+    pub fn get_forecast(&self, northern_hemisphere: bool) -> Option<ZambrettiForecast> {
+        if !self.pressure_data.is_valid() {
+            return None;
+        }
+
+        let trend = self.get_pressure_trend()?;
+        let wind_direction = if self.directional_data.is_valid() { Some(self.directional_data.get_direction()) } else { None };
+
+        Some(get_zambretti_forecast(self.pressure_data.get_sea_level_pressure_hpa(), trend, wind_direction, Local::now().month(), northern_hemisphere))
+    }
+
+    pub fn print_data(&self, units: &Units) {
         let mut data_str = "".to_string();
 
         if self.dht_data.is_valid() {
             let time: DateTime<Utc> = self.dht_data.get_last_updated().unwrap().into();
 
-            data_str.push_str(format!("Temperature: {}°F ({}°C) -- Humidity: {}% (Last Updated: {})\n", self.dht_data.get_temp_farenheit(), self.dht_data.get_temp_celsius(), self.dht_data.get_humidity(), time.format("%d/%m/%Y %T").to_string()).as_str());
+            data_str.push_str(format!("Temperature: {:.1}{} -- Humidity: {:.1}% (Last Updated: {})\n", units.temperature.convert_celsius(self.dht_data.get_temp_celsius()), units.temperature.get_symbol(), self.dht_data.get_humidity(), time.format("%d/%m/%Y %T")).as_str());
         }
 
         if self.anemometer_data.is_valid() {
             let time: DateTime<Utc> = self.anemometer_data.get_last_updated().unwrap().into();
 
-            data_str.push_str(format!("Wind Speed: {} MPH ({} km/h) -- (Last Updated: {})\n", self.anemometer_data.get_mph(), self.anemometer_data.get_kph(), time.format("%d/%m/%Y %T").to_string()).as_str());
+            let direction = if self.directional_data.is_valid() { Some(self.directional_data.get_dir_as_string()) } else { None };
+            let summary = get_wind_summary(self.anemometer_data.get_kph(), Some(self.anemometer_data.get_gust_kph()), direction.as_deref(), units);
+
+            data_str.push_str(format!("Wind: {} -- (Last Updated: {})\n", summary, time.format("%d/%m/%Y %T")).as_str());
         }
 
         if self.directional_data.is_valid() {
             let time: DateTime<Utc> = self.directional_data.get_last_updated().unwrap().into();
 
-            data_str.push_str(format!("Wind Direction: {}° (Last Updated: {})\n", self.directional_data.get_direction(), time.format("%d/%m/%Y %T").to_string()).as_str())
+            data_str.push_str(format!("Wind Direction: {}° (Last Updated: {})\n", self.directional_data.get_direction(), time.format("%d/%m/%Y %T")).as_str())
         }
 
         if self.rain_data.is_valid() {
             let time: DateTime<Utc> = self.rain_data.get_last_updated().unwrap().into();
 
-            data_str.push_str(format!("Rain Collected: {}in ({}cm) -- (Last Updated: {})\n", self.rain_data.get_amount_in(), self.rain_data.get_amount_in(), time.format("%d/%m/%Y %T").to_string()).as_str());
+            data_str.push_str(format!("Rain Rate: {:.2} {}/h -- (Last Updated: {})\n", units.rain.convert_mm(self.rain_data.get_rate_mm_per_hour()), units.rain.get_symbol(), time.format("%d/%m/%Y %T")).as_str());
+        }
+
+        if self.pressure_data.is_valid() {
+            let time: DateTime<Utc> = self.pressure_data.get_last_updated().unwrap().into();
+
+            let symbol = units.pressure.get_symbol();
+
+            data_str.push_str(format!("Pressure: {:.2} {} (Sea Level: {:.2} {}) -- (Last Updated: {})\n", units.pressure.convert_hpa(self.pressure_data.get_station_pressure_hpa()), symbol, units.pressure.convert_hpa(self.pressure_data.get_sea_level_pressure_hpa()), symbol, time.format("%d/%m/%Y %T")).as_str());
         }
 
-        if data_str.len() > 0 { print!("{}", data_str); }
+        if !data_str.is_empty() { print!("{}", data_str); }
     }
 }
 
 fn ping() -> bool {
     let res = reqwest::blocking::get("http://detectportal.firefox.com/success.txt");
 
-    if let Err(e) = res {
-        return false;
-    } else if let Ok(r) = res {
-        if r.text().unwrap().eq("success".into()) {
+    if let Ok(r) = res {
+        if r.text().unwrap().eq("success") {
             return true;
         }
     }
 
-    return false;
+    false
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct DaytimeData {
+    #[serde(with = "date_format")]
     date: Date<Local>,
+    #[serde(skip)]
     prev_date: Option<Date<Local>>,
-    pub rain_total: u32,
-    pub wind_max: f32,
-    pub wind_min: f32,
+    pub rain_total: f32,                // mm
+    pub wind_max: f32,                  // kph
+    pub wind_min: f32,                  // kph
     pub temp_hi: f32,
     pub temp_lo: f32,
     pub temp_avg: f32,
     pub temp_total: f32,
-    pub temp_col_count: u32
+    pub temp_col_count: u32,
+    pub uv_max: f32,
+    pub solar_max: f32,                 // W/m²
+    pub lightning_strikes: u32
 }
 
 impl DaytimeData {
@@ -141,57 +484,90 @@ impl DaytimeData {
         Self {
             date: Local::today(),
             prev_date,
-            rain_total: 0,
+            rain_total: 0.0,
             wind_max: 0.0,
             wind_min: -1.0,
             temp_hi: 0.0,
             temp_lo: -1.0,
             temp_avg: 0.0,
             temp_total: 0.0,
-            temp_col_count: 0
+            temp_col_count: 0,
+            uv_max: 0.0,
+            solar_max: 0.0,
+            lightning_strikes: 0
         }
     }
 
-    pub fn get_rain_total_cm(&self) -> f32 {
-        RainData::convert_to_cm(self.rain_total)
-    }
+    pub fn add_temp_sample(&mut self, temperature: f32) {
+        self.temp_col_count += 1;
+        self.temp_total += temperature;
 
-    pub fn get_rain_total_in(&self) -> f32 {
-        RainData::convert_to_in(self.rain_total)
-    }
+        if self.temp_col_count == 1 || self.temp_lo > temperature {
+            self.temp_lo = temperature;
+        }
 
-    pub fn get_wind_min_mph(&self) -> f32 {
-        AnemometerData::convert_to_mph(self.wind_min)
-    }
+        if self.temp_col_count == 1 || self.temp_hi < temperature {
+            self.temp_hi = temperature;
+        }
 
-    pub fn get_wind_max_mph(&self) -> f32 {
-        AnemometerData::convert_to_mph(self.wind_max)
+        self.temp_avg = self.temp_total / self.temp_col_count as f32;
     }
 
     pub fn get_current_date(&self) -> Date<Local> {
         self.date
     }
 
-    pub fn save_to_file(&self) {
-        // TODO
+    // Written to a temporary file first so a crash mid-write can't leave half a file behind
+    pub fn save_to_file(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let tmp_path = format!("{}.tmp", path);
+
+        write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        rename(&tmp_path, path)?;
+
+        Ok(())
+    }
+
+    pub fn load_from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_str(&read_to_string(path)?)?)
+    }
+
+    // Picks up where the station left off if it was restarted during the same day
+    pub fn restore(path: &str) -> Self {
+        match Self::load_from_file(path) {
+            Ok(saved) if saved.date == Local::today() => {
+                println!("Restored today's stats from {}", path);
+
+                saved
+            },
+            Ok(saved) => Self::new(Some(saved.date)),
+            Err(e) => {
+                println!("No daily stats restored from {}: {}", path, e);
+
+                Self::new(None)
+            }
+        }
     }
 }
 
 pub struct DataManager {
     config: Config,
-    sender: Sender<Event>,
     receiver: Receiver<Box<dyn Payload>>,
     update_rcv: Receiver<Event>,
     data: DataPoint,
-    lcd_display: LCDDisplay,
+    lcd_display: Option<LCDDisplay>,
+    qc: QualityControl,
     system_info: System,
     db_client: Client,
     current_data: DaytimeData,
+    lightning_alert: bool,
+    last_temp_sample: Option<SystemTime>,
+    alerts: AlertEngine,
+    notifier: Notifier,
     has_internet_connection: bool
 }
 
 impl DataManager {
-    pub fn new(sender: Sender<Event>, receiver: Receiver<Box<dyn Payload>>, update_rcv: Receiver<Event>, config: Config) -> Result<Self, Box<dyn Error>> {
+    pub fn new(receiver: Receiver<Box<dyn Payload>>, update_rcv: Receiver<Event>, config: Config) -> Result<Self, Box<dyn Error>> {
         let mut client: Client;
 
         if config.is_prod_env() {
@@ -201,22 +577,56 @@ impl DataManager {
         }
 
         Rain::create_table(&mut client);
+        AnalogSample::create_table(&mut client);
+        ProbeTemperature::create_table(&mut client);
+        QcRecord::create_table(&mut client);
+
+        let display = config.hardware.display;
+        let mut data = DataPoint::new();
+
+        let lcd_display = if display.enabled {
+            match LCDDisplay::new(display.rs_pin, display.en_pin, display.d_pins, display.cols, display.rows) {
+                Ok(lcd_display) => {
+                    data.update_sensor_state(DISPLAY_NAME, SensorState::Ok);
+
+                    Some(lcd_display)
+                },
+                Err(e) => {
+                    println!("LCD display is unavailable: {}", e);
+                    data.update_sensor_state(DISPLAY_NAME, SensorState::Absent);
+
+                    None
+                }
+            }
+        } else {
+            data.update_sensor_state(DISPLAY_NAME, SensorState::Disabled);
+
+            None
+        };
+
+        let qc = QualityControl::new(config.qc);
+        let alerts = AlertEngine::new(config.get_alert_rules());
+        let notifier = Notifier::start(config.notifications.clone());
 
         Ok(Self {
-            config: config,
-            sender,
+            config,
             receiver,
             update_rcv,
-            data: DataPoint::new(),
-            lcd_display: LCDDisplay::new(DISPLAY_RS_PIN, DISPLAY_EN_PIN, DISPLAY_D_PINS, DISPLAY_COLS, DISPLAY_ROWS)?,
+            data,
+            lcd_display,
+            qc,
             system_info: System::new_all(),
             db_client: client,
-            current_data: DaytimeData::new(None),
+            current_data: DaytimeData::restore(DAYTIME_DATA_PATH),
+            lightning_alert: false,
+            last_temp_sample: None,
+            alerts,
+            notifier,
             has_internet_connection: ping()
         })
     }
 
-    pub fn start(mut self) {
+    pub fn start(mut self) -> JoinHandle<()> {
         spawn(move || {
             let mut lcd_loop = 0;
             let mut update_lcd = 5;         // Update on startup (updates LCD every 5 seconds to new state)
@@ -224,16 +634,11 @@ impl DataManager {
             let mut ping_loop = 0;
 
             loop {
-                // self.sender.send(Event::new(EventType::UpdateData)).unwrap();
                 let mut has_updated = false;
 
                 sleep(Duration::from_millis(5));
 
-                while !self.receiver.is_empty() {
-                    let payload = self.receiver.recv().unwrap();
-
-                    payload.update_data_fields(&mut self.data, &mut self.current_data);
-
+                if self.process_payloads() {
                     has_updated = true;
                 }
 
@@ -244,20 +649,59 @@ impl DataManager {
 
                     match event.get_event_type() {
                         EventType::MidnightRefresh => {
-                            self.current_data.save_to_file();
+                            if let Err(e) = self.current_data.save_to_file(DAYTIME_DATA_PATH) {
+                                println!("Failed to save daily stats: {}", e);
+                            }
 
                             let prev_data = self.current_data;
 
                             self.current_data = DaytimeData::new(Some(prev_data.get_current_date()));
                         },
+                        EventType::ResetDaily => {
+                            // Same day, so keep whatever the previous date was
+                            self.current_data = DaytimeData::new(self.current_data.prev_date);
+                            self.last_temp_sample = None;
+
+                            println!("Daily stats reset");
+
+                            has_updated = true;
+                        },
+                        EventType::ShowScreen(screen) => {
+                            lcd_loop = screen;
+                            update_lcd = 5;
+                        },
+                        EventType::Exit => {
+                            self.shutdown();
+
+                            return;
+                        },
                         _ => {}
                     }
                 }
 
+                self.update_temperature();
+
+                if self.update_alerts() {
+                    has_updated = true;
+                }
+
+                let lightning_alert = self.data.is_lightning_alert_active(&self.config.lightning);
+
+                if lightning_alert != self.lightning_alert {
+                    self.lightning_alert = lightning_alert;
+
+                    match (lightning_alert, self.data.lightning_data.get_last_distance_km()) {
+                        (true, Some(distance)) => println!("Lightning alert: strike detected {}km away", distance),
+                        _ => println!("Lightning alert cleared, no strikes within {}km for {} minutes", self.config.lightning.alert_distance_km, self.config.lightning.alert_clear_mins)
+                    }
+
+                    has_updated = true;
+                }
+
                 if has_updated {
-                    self.data.print_data();
+                    self.data.print_data(&self.config.units.get_units());
 
-                    update_api_cache(Some(self.current_data.clone()), Some(self.data.clone()));
+                    update_api_cache(Some(self.current_data), Some(self.data.clone()));
                 }
 
                 let mut elapsed = Duration::from_secs(0);
@@ -290,66 +734,361 @@ impl DataManager {
 
                     self.print_data_lcd(lcd_loop);
     
-                    lcd_loop = if lcd_loop == 5 { 0 } else { lcd_loop + 1 };
+                    lcd_loop = if lcd_loop == LCD_SCREENS - 1 { 0 } else { lcd_loop + 1 };
     
                     elapsed += time.elapsed().unwrap();
                 } 
 
-                sleep(Duration::from_millis(990) - elapsed);
+                sleep(Duration::from_millis(990).saturating_sub(elapsed));
+            }
+        })
+    }
+
+    // Applies everything the sensors have sent since the last loop, returns whether anything changed
+    fn process_payloads(&mut self) -> bool {
+        let mut has_updated = false;
+
+        while let Ok(payload) = self.receiver.try_recv() {
+            has_updated = true;
+
+            let mut rejected = QcMask::new();
+
+            if self.config.qc.enabled {
+                let time = SystemTime::now();
+                let mut dropped = Vec::new();
+
+                for source_readings in payload.get_readings() {
+                    let flags = self.qc.check(&source_readings.source, &source_readings.readings, time);
+
+                    // Good is the norm, only the exceptions are stored
+                    for (quantity, value) in source_readings.readings.iter() {
+                        match flags.get(quantity) {
+                            Some(flag) if *flag != QcFlag::Good => {
+                                QcRecord::new(source_readings.source.clone(), quantity.as_str().to_string(), *value, flag.as_str().to_string(), time.into()).insert(&mut self.db_client);
+
+                                if flag.is_rejected() {
+                                    rejected.reject(&source_readings.source, *quantity);
+                                    dropped.push(format!("{} {} ({})", source_readings.source, quantity.as_str(), flag.as_str()));
+                                }
+                            },
+                            _ => ()
+                        }
+                    }
+
+                    self.data.update_qc_flags(&source_readings.source, flags);
+                }
+
+                if !dropped.is_empty() {
+                    println!("Dropped reading that failed quality control: {}", dropped.join(", "));
+                }
             }
-        });
+
+            payload.update_data_fields(&mut self.data, &mut self.current_data, &rejected);
+            payload.save_to_database(&mut self.db_client, &rejected);
+        }
+
+        has_updated
+    }
+
+    // Last pass before the station stops, nothing queued should be lost
+    fn shutdown(mut self) {
+        println!("Stopping data manager");
+
+        self.process_payloads();
+        self.update_temperature();
+
+        match self.current_data.save_to_file(DAYTIME_DATA_PATH) {
+            Ok(_) => println!("Saved daily stats to {}", DAYTIME_DATA_PATH),
+            Err(e) => println!("Failed to save daily stats: {}", e)
+        }
+
+        update_api_cache(Some(self.current_data), Some(self.data.clone()));
+
+        if let Some(lcd_display) = self.lcd_display.as_mut() {
+            lcd_display.clear();
+            lcd_display.cursor_home();
+            lcd_display.write_message("   Pi Weather   \nStation stopped".to_string());
+        }
+
+        self.notifier.shutdown(NOTIFIER_SHUTDOWN_TIMEOUT);
+
+        // Every write is synchronous, closing just ends the session cleanly
+        if let Err(e) = self.db_client.close() {
+            println!("Failed to close the database connection: {}", e);
+        }
+    }
+
+    // Feeds the day's hi/lo/avg whenever the sources the policy selects have a new reading. Readings that failed
+    // quality control never reach the data point, and the disagreement alert is a rule like any other
+    fn update_temperature(&mut self) {
+        let readings = self.data.get_temperature_readings(&self.config.temperature, &self.config.health);
+
+        let selected = match select_temperature(self.config.temperature.policy, &readings) {
+            Some(selected) => selected,
+            None => return
+        };
+
+        let sample_time = get_selected_time(&selected, &readings);
+
+        if sample_time != self.last_temp_sample {
+            self.last_temp_sample = sample_time;
+            self.current_data.add_temp_sample(selected.temperature);
+        }
+    }
+
+    // Runs the [[alerts]] rules over the latest readings, returns whether anything was raised or cleared
+    fn update_alerts(&mut self) -> bool {
+        let metrics = self.data.get_alert_metrics(&self.config);
+        let events = self.alerts.evaluate(&metrics, SystemTime::now());
+
+        if events.is_empty() {
+            return false;
+        }
+
+        for event in events.iter() {
+            match event.kind {
+                AlertEventKind::Raised => println!("Alert \"{}\" raised: {} is {:.1} ({:?} {})", event.alert.rule, event.alert.metric, event.value, event.alert.comparator, event.alert.threshold),
+                AlertEventKind::Cleared => println!("Alert \"{}\" cleared: {} is {:.1}", event.alert.rule, event.alert.metric, event.value)
+            }
+        }
+
+        self.send_notifications(&events, &metrics);
+        self.data.update_alerts(self.alerts.get_active(), events);
+
+        true
+    }
+
+    // Fills in the templates for each sink the rule notifies, the notifier's workers do the sending
+    fn send_notifications(&self, events: &[AlertEvent], metrics: &BTreeMap<String, f32>) {
+        let rules = self.config.get_alert_rules();
+        let readings: Vec<String> = metrics.iter()
+            .filter(|(name, _)| !name.starts_with("sensor:"))
+            .map(|(name, value)| format!("{}: {:.1}", name, value))
+            .collect();
+
+        for event in events.iter() {
+            let rule = match rules.iter().find(|rule| rule.name == event.alert.rule) {
+                Some(rule) if !rule.notify.is_empty() => rule,
+                _ => continue
+            };
+
+            let mut values: BTreeMap<String, String> = metrics.iter().map(|(name, value)| (name.clone(), format!("{:.1}", value))).collect();
+
+            values.insert("rule".to_string(), rule.name.clone());
+            values.insert("state".to_string(), event.kind.get_label().to_string());
+            values.insert("metric".to_string(), rule.metric.clone());
+            values.insert("value".to_string(), format!("{:.1}", event.value));
+            values.insert("threshold".to_string(), format!("{:.1}", rule.threshold));
+            values.insert("comparator".to_string(), rule.comparator.get_label().to_string());
+            values.insert("time".to_string(), Local::now().format("%Y-%m-%d %H:%M").to_string());
+            values.insert("readings".to_string(), readings.join("\n"));
+
+            let title = render_template(&self.config.notifications.title_template, &values);
+            let message = render_template(rule.message.as_ref().unwrap_or(&self.config.notifications.message_template), &values);
+
+            let event_json = json!({
+                "state": event.kind,
+                "alert": event.alert,
+                "value": event.value,
+                "readings": metrics
+            });
+
+            for sink in rule.notify.iter() {
+                self.notifier.send(Notification {
+                    sink: sink.clone(),
+                    title: title.clone(),
+                    message: message.clone(),
+                    event: event_json.clone()
+                });
+            }
+        }
+    }
+
+    fn is_stale(&self, sensor: &str) -> bool {
+        self.data.get_sensor_state(sensor, &self.config.health) == Some(SensorState::Stale)
     }
 
     pub fn print_data_lcd(&mut self, show_id: i32) {
-        self.lcd_display.clear();
-        self.lcd_display.cursor_home();
+        if self.lcd_display.is_none() {
+            return;
+        }
+
+        let units = self.config.units.get_units();
 
-        match show_id {
+        let message = match show_id {
             0 => {
                 let time: DateTime<Local> = Local::now();
 
-                self.lcd_display.write_message(format!("   Pi Weather   \n {}", time.format("%m/%d/%y %H:%M").to_string()));
+                format!("   Pi Weather   \n {}", time.format("%m/%d/%y %H:%M"))
             },
             1 => {
-                if self.data.dht_data.is_valid() {
-                    self.lcd_display.write_message(format!("{:.1}°F ({:.1}°C)\n{:.1}% Humidity", self.data.dht_data.get_temp_farenheit(), self.data.dht_data.get_temp_celsius(), self.data.dht_data.get_humidity()));
+                if self.is_stale(dht::SENSOR_NAME) {
+                    "Temp/Humidity\nis stale!".to_string()
+                } else if self.data.dht_data.is_valid() {
+                    format!("{:.1}{}\n{:.1}% Humidity", units.temperature.convert_celsius(self.data.dht_data.get_temp_celsius()), units.temperature.get_symbol(), self.data.dht_data.get_humidity())
                 } else {
-                    self. lcd_display.write_message(format!("Temp/Humidity\nunavailable!"));
+                    "Temp/Humidity\nunavailable!".to_string()
                 }
             },
             2 => {
-                if self.data.anemometer_data.is_valid() && self.data.directional_data.is_valid() {
-                    self.lcd_display.write_message(format!("{}° {}\n{:.1}mph {:.1}k/hr", self.data.directional_data.get_direction(), self.data.directional_data.get_dir_as_string(), self.data.anemometer_data.get_mph(), self.data.anemometer_data.get_kph()));
+                if self.is_stale(anemometer::SENSOR_NAME) || self.is_stale(vane::SENSOR_NAME) {
+                    "Wind data\nis stale!".to_string()
+                } else if self.data.anemometer_data.is_valid() && self.data.directional_data.is_valid() {
+                    let beaufort = Beaufort::from_kph(self.data.anemometer_data.get_kph());
+
+                    if beaufort.is_calm() {
+                        format!("Calm\n{}° {}", self.data.directional_data.get_direction().round(), self.data.directional_data.get_dir_as_string())
+                    } else {
+                        // The force number is dropped when it won't fit next to the longer descriptions
+                        let force = format!("F{} {}", beaufort.force, beaufort.description);
+                        let force = if force.len() > self.config.hardware.display.cols { beaufort.description.to_string() } else { force };
+
+                        format!("{} {:.1} {}\n{}", self.data.directional_data.get_dir_as_string(), units.speed.convert_kph(self.data.anemometer_data.get_kph()), units.speed.get_symbol(), force)
+                    }
                 } else {
-                    self.lcd_display.write_message(format!("Wind data\nunavailable!"));
+                    "Wind data\nunavailable!".to_string()
                 }
             },
             3 => {
                 if self.data.anemometer_data.is_valid() {
-                    self.lcd_display.write_message(format!("Min: {:.1}mph\nMax: {:.1}mph", self.current_data.get_wind_min_mph(), self.current_data.get_wind_max_mph()));
+                    format!("Min: {:.1} {}\nMax: {:.1} {}", units.speed.convert_kph(self.current_data.wind_min), units.speed.get_symbol(), units.speed.convert_kph(self.current_data.wind_max), units.speed.get_symbol())
                 } else {
-                    self.lcd_display.write_message(format!("Wind data\nunavailable!"));
+                    "Wind data\nunavailable!".to_string()
                 }
             },
             4 => {
-                if self.data.rain_data.is_valid() {
-                    self.lcd_display.write_message(format!("{:.2} in\n{:.2} cm", self.current_data.get_rain_total_in(), self.current_data.get_rain_total_cm()));
+                if self.is_stale(rain::SENSOR_NAME) {
+                    "Rain data\nis stale!".to_string()
+                } else if self.data.rain_data.is_valid() {
+                    format!("Rain today\n{:.2} {}", units.rain.convert_mm(self.current_data.rain_total), units.rain.get_symbol())
                 } else {
-                    self.lcd_display.write_message(format!("Rain data\nunavailable!"));
+                    "Rain data\nunavailable!".to_string()
                 }
             },
             5 => {
                 self.system_info.refresh_system();
 
-                self.lcd_display.write_message(format!("CPU: {:.1}%\nMem: {:.2}MB", self.system_info.get_global_processor_info().get_cpu_usage(), (self.system_info.get_used_memory() as f32) / 1000.0));
+                format!("CPU: {:.1}%\nMem: {:.2}MB", self.system_info.get_global_processor_info().get_cpu_usage(), (self.system_info.get_used_memory() as f32) / 1000.0)
             },
             6 => {
+                if self.is_stale(bme280::SENSOR_NAME) {
+                    "Pressure data\nis stale!".to_string()
+                } else if self.data.pressure_data.is_valid() {
+                    let trend = self.data.get_pressure_trend().map(|trend| trend.get_label()).unwrap_or("Trend pending");
 
+                    format!("{:.2} {}\n{}", units.pressure.convert_hpa(self.data.pressure_data.get_sea_level_pressure_hpa()), units.pressure.get_symbol(), trend)
+                } else {
+                    "Pressure data\nunavailable!".to_string()
+                }
             },
             7 => {
+                match self.data.get_forecast(self.config.forecast.northern_hemisphere) {
+                    Some(forecast) => wrap_text(forecast.forecast, self.config.hardware.display.cols, self.config.hardware.display.rows),
+                    None => "Forecast\nunavailable!".to_string()
+                }
+            },
+            8 => {
+                if self.is_stale(veml6075::SENSOR_NAME) || self.is_stale(pyranometer::SENSOR_NAME) {
+                    "Solar/UV data\nis stale!".to_string()
+                } else if self.data.uv_data.is_valid() || self.data.solar_data.is_valid() {
+                    let uv = if self.data.uv_data.is_valid() { format!("UV: {:.1} Max {:.1}", self.data.uv_data.get_uv_index(), self.current_data.uv_max) } else { "UV: --".to_string() };
+                    let solar = if self.data.solar_data.is_valid() { format!("{:.0} W/m2", self.data.solar_data.get_radiation()) } else { "Solar: --".to_string() };
+
+                    format!("{}\n{}", uv, solar)
+                } else {
+                    return;
+                }
+            },
+            9 => {
+                if self.is_stale(particulate::SENSOR_NAME) {
+                    "Air quality\nis stale!".to_string()
+                } else if self.data.particulate_data.is_valid() {
+                    let aqi = self.data.get_aqi().map(|aqi| format!("AQI {} {}", aqi.aqi, aqi.category)).unwrap_or_else(|| "AQI pending".to_string());
 
+                    format!("PM2.5 {:.0} PM10 {:.0}\n{}", self.data.particulate_data.get_pm2_5(), self.data.particulate_data.get_pm10(), aqi)
+                } else {
+                    return;
+                }
             },
-            _ => {  }
+            10 => {
+                let lightning = &self.data.lightning_data;
+
+                match lightning.get_last_strike() {
+                    Some(time) => {
+                        let time: DateTime<Local> = time.into();
+                        let alert = if self.lightning_alert { "!" } else { "" };
+                        let distance = lightning.get_last_distance_km().map(|distance| format!("{}km", distance)).unwrap_or_else(|| "far".to_string());
+
+                        format!("{}Lightning {}/h\n{} at {}", alert, lightning.get_strikes_since(Duration::from_secs(60 * 60), SystemTime::now()), distance, time.format("%H:%M"))
+                    },
+                    None => return
+                }
+            },
+            11 => {
+                let stats = &self.data.dht_stats;
+
+                if stats.reads > 0 {
+                    format!("DHT err: {:.1}%\n{}/{} reads OK", stats.get_recent_error_rate(), stats.successes, stats.reads)
+                } else {
+                    return;
+                }
+            },
+            _ => {
+                return;
+            }
+        };
+
+        if let Some(lcd_display) = self.lcd_display.as_mut() {
+            lcd_display.clear();
+            lcd_display.cursor_home();
+            lcd_display.write_message(message);
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use crate::data::process::{ DataPoint, DaytimeData };
+    use crate::hardware::status::{ SensorState };
+    use chrono::{ Local };
+    use std::process;
+    use std::time::{ SystemTime };
+
+    #[test]
+    fn test_daytime_data_file() {
+        let path = std::env::temp_dir().join(format!("daytime-{}.json", process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut daytime = DaytimeData::new(None);
+        daytime.rain_total = 4.2;
+        daytime.add_temp_sample(12.5);
+        daytime.add_temp_sample(17.5);
+
+        daytime.save_to_file(path).unwrap();
+
+        let restored = DaytimeData::restore(path);
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(restored.get_current_date(), Local::today());
+        assert_eq!(restored.rain_total, 4.2);
+        assert_eq!((restored.temp_lo, restored.temp_hi, restored.temp_avg), (12.5, 17.5, 15.0));
+
+        assert_eq!(DaytimeData::restore(path).temp_col_count, 0);
+    }
+
+    #[test]
+    fn test_last_reading_time() {
+        let mut data = DataPoint::new();
+        assert_eq!(data.get_last_reading_time(), None);
+
+        let before = SystemTime::now();
+        data.update_sensor_state("dht", SensorState::Ok);
+        data.update_sensor_state("bme280", SensorState::Ok);
+
+        assert!(data.get_last_reading_time().is_some_and(|time| time >= before));
+
+        // A failed read isn't a reading
+        let last_reading = data.get_last_reading_time();
+        data.update_sensor_state("dht", SensorState::Failing);
+        assert_eq!(data.get_last_reading_time(), last_reading);
+    }
+}
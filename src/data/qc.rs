@@ -0,0 +1,266 @@
+use std::collections::{ BTreeMap, BTreeSet };
+use std::time::{ SystemTime };
+use serde::{ Serialize };
+
+use crate::config::{ QcConfig, QcLimits };
+
+// A real shift (e.g. the sun coming out) looks like a spike at first, so after this many in a row it's accepted
+const MAX_CONSECUTIVE_SPIKES: u32 = 3;
+const PERSISTENCE_TOLERANCE: f32 = 0.001;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Quantity {
+    Temperature,
+    Humidity,
+    WindSpeed,
+    WindDirection,
+    Pressure,
+    UvIndex,
+    Pm2_5,
+    Pm10
+}
+
+impl Quantity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Quantity::Temperature => "temperature",
+            Quantity::Humidity => "humidity",
+            Quantity::WindSpeed => "wind_speed",
+            Quantity::WindDirection => "wind_direction",
+            Quantity::Pressure => "pressure",
+            Quantity::UvIndex => "uv_index",
+            Quantity::Pm2_5 => "pm2_5",
+            Quantity::Pm10 => "pm10"
+        }
+    }
+}
+
+// What one source measured, history is kept per source so e.g. the DHT and a probe both reporting temperature
+// aren't judged against each other's last value
+#[derive(Debug, Clone)]
+pub struct SourceReadings {
+    pub source: String,
+    pub readings: Vec<(Quantity, f32)>
+}
+
+impl SourceReadings {
+    pub fn new(source: &str, readings: Vec<(Quantity, f32)>) -> Self {
+        Self {
+            source: source.to_string(),
+            readings
+        }
+    }
+}
+
+// Quantities quality control dropped. Payloads leave these at their last accepted value and still apply the rest,
+// so one bad humidity reading doesn't throw away the temperature taken with it
+#[derive(Debug, Clone, Default)]
+pub struct QcMask {
+    rejected: BTreeSet<(String, Quantity)>
+}
+
+impl QcMask {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reject(&mut self, source: &str, quantity: Quantity) {
+        self.rejected.insert((source.to_string(), quantity));
+    }
+
+    pub fn is_rejected(&self, source: &str, quantity: Quantity) -> bool {
+        self.rejected.iter().any(|(rejected_source, rejected_quantity)| rejected_source == source && *rejected_quantity == quantity)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QcFlag {
+    Good,
+    Stuck,              // Kept, but the value hasn't moved for longer than the persistence window
+    OutOfRange,         // Dropped
+    Spike               // Dropped
+}
+
+impl QcFlag {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QcFlag::Good => "good",
+            QcFlag::Stuck => "stuck",
+            QcFlag::OutOfRange => "out_of_range",
+            QcFlag::Spike => "spike"
+        }
+    }
+
+    pub fn is_rejected(&self) -> bool {
+        matches!(self, QcFlag::OutOfRange | QcFlag::Spike)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct QcHistory {
+    value: f32,
+    time: SystemTime,
+    last_change: SystemTime,
+    consecutive_spikes: u32
+}
+
+pub struct QualityControl {
+    config: QcConfig,
+    history: BTreeMap<(String, Quantity), QcHistory>
+}
+
+impl QualityControl {
+    pub fn new(config: QcConfig) -> Self {
+        Self {
+            config,
+            history: BTreeMap::new()
+        }
+    }
+
+    fn get_limits(&self, quantity: Quantity) -> QcLimits {
+        match quantity {
+            Quantity::Temperature => self.config.temperature,
+            Quantity::Humidity => self.config.humidity,
+            Quantity::WindSpeed => self.config.wind_speed,
+            Quantity::WindDirection => self.config.wind_direction,
+            Quantity::Pressure => self.config.pressure,
+            Quantity::UvIndex => self.config.uv_index,
+            Quantity::Pm2_5 => self.config.pm2_5,
+            Quantity::Pm10 => self.config.pm10
+        }
+    }
+
+    pub fn check(&mut self, source: &str, readings: &[(Quantity, f32)], time: SystemTime) -> BTreeMap<Quantity, QcFlag> {
+        readings.iter()
+            .map(|(quantity, value)| (*quantity, self.check_reading(source, *quantity, *value, time)))
+            .collect()
+    }
+
+    fn check_reading(&mut self, source: &str, quantity: Quantity, value: f32, time: SystemTime) -> QcFlag {
+        let limits = self.get_limits(quantity);
+
+        if !value.is_finite() || value < limits.min || value > limits.max {
+            return QcFlag::OutOfRange;
+        }
+
+        let previous = match self.history.get_mut(&(source.to_string(), quantity)) {
+            Some(previous) => previous,
+            None => {
+                self.history.insert((source.to_string(), quantity), QcHistory { value, time, last_change: time, consecutive_spikes: 0 });
+
+                return QcFlag::Good;
+            }
+        };
+
+        // Readings closer together than a minute are judged as if a minute had passed
+        let elapsed_mins = (time.duration_since(previous.time).unwrap_or_default().as_secs_f32() / 60.0).max(1.0);
+        let change = (value - previous.value).abs();
+
+        if let Some(max_rate) = limits.max_rate_per_min {
+            if change / elapsed_mins > max_rate && previous.consecutive_spikes < MAX_CONSECUTIVE_SPIKES {
+                previous.consecutive_spikes += 1;
+
+                return QcFlag::Spike;
+            }
+        }
+
+        if change > PERSISTENCE_TOLERANCE {
+            previous.last_change = time;
+        }
+
+        previous.value = value;
+        previous.time = time;
+        previous.consecutive_spikes = 0;
+
+        match limits.persistence_mins {
+            Some(mins) if time.duration_since(previous.last_change).unwrap_or_default().as_secs() > mins as u64 * 60 => QcFlag::Stuck,
+            _ => QcFlag::Good
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{ Duration, SystemTime };
+    use crate::config::{ QcConfig };
+    use crate::data::qc::{ QualityControl, Quantity, QcFlag };
+
+    fn minutes(start: SystemTime, mins: u64) -> SystemTime {
+        start + Duration::from_secs(mins * 60)
+    }
+
+    #[test]
+    fn test_range() {
+        let mut qc = QualityControl::new(QcConfig::default());
+        let now = SystemTime::now();
+
+        let flags = qc.check("dht", &[ (Quantity::Temperature, 85.0), (Quantity::Humidity, 50.0) ], now);
+
+        assert_eq!(flags[&Quantity::Temperature], QcFlag::OutOfRange);
+        assert_eq!(flags[&Quantity::Humidity], QcFlag::Good);
+        assert_eq!(qc.check("bme280", &[ (Quantity::Pressure, f32::NAN) ], now)[&Quantity::Pressure], QcFlag::OutOfRange);
+    }
+
+    #[test]
+    fn test_spike() {
+        let mut qc = QualityControl::new(QcConfig::default());
+        let start = SystemTime::now();
+
+        assert_eq!(qc.check("dht", &[ (Quantity::Temperature, 20.0) ], start)[&Quantity::Temperature], QcFlag::Good);
+        assert_eq!(qc.check("dht", &[ (Quantity::Temperature, 45.0) ], minutes(start, 1))[&Quantity::Temperature], QcFlag::Spike);
+
+        // The spike isn't used as the reference for the next reading
+        assert_eq!(qc.check("dht", &[ (Quantity::Temperature, 21.0) ], minutes(start, 2))[&Quantity::Temperature], QcFlag::Good);
+
+        // The same change is fine when spread over a longer gap
+        assert_eq!(qc.check("dht", &[ (Quantity::Temperature, 30.0) ], minutes(start, 60))[&Quantity::Temperature], QcFlag::Good);
+    }
+
+    #[test]
+    fn test_sources_kept_apart() {
+        let mut qc = QualityControl::new(QcConfig::default());
+        let start = SystemTime::now();
+
+        assert_eq!(qc.check("dht", &[ (Quantity::Temperature, 21.0) ], start)[&Quantity::Temperature], QcFlag::Good);
+
+        // A probe in the soil reads far from the air temperature, neither is a spike against the other
+        assert_eq!(qc.check("ds18b20:soil", &[ (Quantity::Temperature, 12.0) ], minutes(start, 1))[&Quantity::Temperature], QcFlag::Good);
+        assert_eq!(qc.check("dht", &[ (Quantity::Temperature, 21.5) ], minutes(start, 2))[&Quantity::Temperature], QcFlag::Good);
+        assert_eq!(qc.check("ds18b20:soil", &[ (Quantity::Temperature, 12.1) ], minutes(start, 3))[&Quantity::Temperature], QcFlag::Good);
+    }
+
+    #[test]
+    fn test_spike_becomes_shift() {
+        let mut qc = QualityControl::new(QcConfig::default());
+        let start = SystemTime::now();
+
+        qc.check("bme280", &[ (Quantity::Pressure, 1010.0) ], start);
+
+        for i in 1..=3 {
+            assert_eq!(qc.check("bme280", &[ (Quantity::Pressure, 1020.0) ], minutes(start, i))[&Quantity::Pressure], QcFlag::Spike);
+        }
+
+        assert_eq!(qc.check("bme280", &[ (Quantity::Pressure, 1020.0) ], minutes(start, 4))[&Quantity::Pressure], QcFlag::Good);
+        assert_eq!(qc.check("bme280", &[ (Quantity::Pressure, 1020.1) ], minutes(start, 5))[&Quantity::Pressure], QcFlag::Good);
+    }
+
+    #[test]
+    fn test_persistence() {
+        let mut qc = QualityControl::new(QcConfig::default());
+        let start = SystemTime::now();
+
+        for i in 0..=120 {
+            assert_eq!(qc.check("dht", &[ (Quantity::Temperature, 15.0) ], minutes(start, i))[&Quantity::Temperature], QcFlag::Good);
+        }
+
+        assert_eq!(qc.check("dht", &[ (Quantity::Temperature, 15.0) ], minutes(start, 121))[&Quantity::Temperature], QcFlag::Stuck);
+        assert_eq!(qc.check("dht", &[ (Quantity::Temperature, 15.2) ], minutes(start, 122))[&Quantity::Temperature], QcFlag::Good);
+
+        // Calm wind isn't a fault
+        for i in 0..=600 {
+            assert_eq!(qc.check("anemometer", &[ (Quantity::WindSpeed, 0.0) ], minutes(start, i))[&Quantity::WindSpeed], QcFlag::Good);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::time::{ SystemTime };
+use serde::{ Serialize, Deserialize };
+
+use crate::units::{ celsius_to_fahrenheit };
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TemperaturePolicy {
+    Primary,            // The first source in the list that isn't stale
+    Median              // The median of every source that isn't stale
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TemperatureReading {
+    pub source: String,
+    pub temperature: f32,
+    #[serde(skip)]
+    pub last_updated: SystemTime
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SelectedTemperature {
+    pub temperature: f32,
+    pub sources: Vec<String>
+}
+
+// Sources are "dht", "bme280" or "ds18b20:<probe name>", this is the sensor each one's staleness is judged by
+pub fn get_source_sensor(source: &str) -> &str {
+    source.split(':').next().unwrap_or(source)
+}
+
+// Readings are expected in order of preference with stale ones already left out
+pub fn select_temperature(policy: TemperaturePolicy, readings: &[TemperatureReading]) -> Option<SelectedTemperature> {
+    match policy {
+        TemperaturePolicy::Primary => {
+            let reading = readings.first()?;
+
+            Some(SelectedTemperature {
+                temperature: reading.temperature,
+                sources: vec![ reading.source.clone() ]
+            })
+        },
+        TemperaturePolicy::Median => {
+            if readings.is_empty() {
+                return None;
+            }
+
+            let mut temperatures: Vec<f32> = readings.iter().map(|reading| reading.temperature).collect();
+            temperatures.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+            let middle = temperatures.len() / 2;
+            let temperature = if temperatures.len() % 2 == 1 { temperatures[middle] } else { (temperatures[middle - 1] + temperatures[middle]) / 2.0 };
+
+            Some(SelectedTemperature {
+                temperature,
+                sources: readings.iter().map(|reading| reading.source.clone()).collect()
+            })
+        }
+    }
+}
+
+// When the selected value was measured, for the median that's the newest of the readings it came from
+pub fn get_selected_time(selected: &SelectedTemperature, readings: &[TemperatureReading]) -> Option<SystemTime> {
+    readings.iter()
+        .filter(|reading| selected.sources.contains(&reading.source))
+        .map(|reading| reading.last_updated)
+        .max()
+}
+
+// NWS heat index (Rothfusz regression with its low and high humidity adjustments), in °C
+pub fn get_heat_index(temp_c: f32, humidity: f32) -> f32 {
+    let t = celsius_to_fahrenheit(temp_c);
+    let rh = humidity;
+
+    let simple = 0.5 * (t + 61.0 + (t - 68.0) * 1.2 + rh * 0.094);
+
+    let heat_index = if (simple + t) / 2.0 < 80.0 {
+        simple
+    } else {
+        let mut heat_index = -42.379 + 2.049_015_2 * t + 10.143_331 * rh - 0.224_755_4 * t * rh - 0.006_837_83 * t * t
+            - 0.054_817_17 * rh * rh + 0.001_228_74 * t * t * rh + 0.000_852_82 * t * rh * rh - 0.000_001_99 * t * t * rh * rh;
+
+        if rh < 13.0 && (80.0..=112.0).contains(&t) {
+            heat_index -= ((13.0 - rh) / 4.0) * ((17.0 - (t - 95.0).abs()) / 17.0).sqrt();
+        } else if rh > 85.0 && (80.0..=87.0).contains(&t) {
+            heat_index += ((rh - 85.0) / 10.0) * ((87.0 - t) / 5.0);
+        }
+
+        heat_index
+    };
+
+    (heat_index - 32.0) * 5.0 / 9.0
+}
+
+// The gap between the warmest and coolest source, only meaningful with at least two of them
+pub fn get_spread(readings: &[TemperatureReading]) -> Option<f32> {
+    if readings.len() < 2 {
+        return None;
+    }
+
+    let max = readings.iter().map(|reading| reading.temperature).fold(f32::MIN, f32::max);
+    let min = readings.iter().map(|reading| reading.temperature).fold(f32::MAX, f32::min);
+
+    Some(max - min)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{ Duration, SystemTime };
+    use crate::data::temperature::{ TemperaturePolicy, TemperatureReading, get_heat_index, get_selected_time, get_source_sensor, get_spread, select_temperature };
+
+    fn reading(source: &str, temperature: f32) -> TemperatureReading {
+        TemperatureReading {
+            source: source.to_string(),
+            temperature,
+            last_updated: SystemTime::now()
+        }
+    }
+
+    #[test]
+    fn test_primary() {
+        let readings = vec![ reading("ds18b20:outside", 18.5), reading("dht", 20.0) ];
+
+        let selected = select_temperature(TemperaturePolicy::Primary, &readings).unwrap();
+        assert_eq!(selected.temperature, 18.5);
+        assert_eq!(selected.sources, vec![ "ds18b20:outside" ]);
+
+        // With the probe stale the DHT takes over
+        assert_eq!(select_temperature(TemperaturePolicy::Primary, &readings[1..]).unwrap().temperature, 20.0);
+        assert_eq!(select_temperature(TemperaturePolicy::Primary, &[]), None);
+    }
+
+    #[test]
+    fn test_median() {
+        let readings = vec![ reading("dht", 21.0), reading("bme280", 24.0), reading("ds18b20:outside", 20.0) ];
+
+        assert_eq!(select_temperature(TemperaturePolicy::Median, &readings).unwrap().temperature, 21.0);
+        assert_eq!(select_temperature(TemperaturePolicy::Median, &readings[..2]).unwrap().temperature, 22.5);
+        assert_eq!(select_temperature(TemperaturePolicy::Median, &readings).unwrap().sources.len(), 3);
+    }
+
+    #[test]
+    fn test_heat_index() {
+        // 90°F at 70% is 106°F on the NWS chart, 70°F at 50% stays at 69°F
+        assert!((get_heat_index(32.22, 70.0) - 41.1).abs() < 0.3, "{}", get_heat_index(32.22, 70.0));
+        assert!((get_heat_index(21.11, 50.0) - 20.6).abs() < 0.3, "{}", get_heat_index(21.11, 50.0));
+    }
+
+    #[test]
+    fn test_spread() {
+        assert_eq!(get_spread(&[ reading("dht", 21.0) ]), None);
+        assert_eq!(get_spread(&[ reading("dht", 21.0), reading("bme280", 24.0), reading("ds18b20:outside", 20.0) ]), Some(4.0));
+
+        assert_eq!(get_source_sensor("ds18b20:outside"), "ds18b20");
+        assert_eq!(get_source_sensor("dht"), "dht");
+    }
+
+    #[test]
+    fn test_selected_time() {
+        let start = SystemTime::now();
+        let probe = TemperatureReading { last_updated: start, ..reading("ds18b20:outside", 18.5) };
+        let dht = TemperatureReading { last_updated: start + Duration::from_secs(30), ..reading("dht", 20.0) };
+        let readings = vec![ probe, dht ];
+
+        // A newer DHT reading doesn't make the primary probe's value new
+        let selected = select_temperature(TemperaturePolicy::Primary, &readings).unwrap();
+        assert_eq!(get_selected_time(&selected, &readings), Some(start));
+
+        let selected = select_temperature(TemperaturePolicy::Median, &readings).unwrap();
+        assert_eq!(get_selected_time(&selected, &readings), Some(start + Duration::from_secs(30)));
+    }
+}
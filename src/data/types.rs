@@ -1,5 +1,5 @@
 use serde::{ Serialize, Deserialize };
-use chrono::{ DateTime, Utc, Date, Local };
+use chrono::{ DateTime, Utc };
 use postgres::{ Client };
 
 use super::{ DatabaseType };
@@ -34,16 +34,143 @@ impl DatabaseType for Rain {
     fn insert(&self, client: &mut Client) {
         let timestamp = format!("{}", self.timestamp.format("%Y-%m-%d %H:%M:%S"));
 
-        client.execute("INSERT INTO Rain (rain_counter, timestamp) VALUES ($1, $2)",
-             &[&self.count, &timestamp]).expect("Failed to insert rain data!");
+        if let Err(e) = client.execute("INSERT INTO Rain (rain_counter, timestamp) VALUES ($1, $2::TEXT::TIMESTAMP)",
+             &[&(self.count as i32), &timestamp]) {
+            println!("Failed to insert rain data! Error: {}", e);
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AnalogSample {
+    name: String,
+    value: f32,
+    unit: String,
+    #[serde(with = "dt_format")]
+    timestamp: DateTime<Utc>
+}
+
+impl AnalogSample {
+    pub fn new(name: String, value: f32, unit: String, timestamp: DateTime<Utc>) -> Self {
+        Self {
+            name,
+            value,
+            unit,
+            timestamp
+        }
+    }
+}
+
+impl DatabaseType for AnalogSample {
+    fn create_table(client: &mut Client) {
+        client.batch_execute("
+            CREATE TABLE IF NOT EXISTS AnalogSample (
+                id              SERIAL PRIMARY KEY,
+                name            TEXT NOT NULL,
+                value           REAL NOT NULL,
+                unit            TEXT NOT NULL,
+                timestamp       TIMESTAMP NOT NULL
+            )
+        ").expect("Failed to create table!");
     }
 
-    fn insert_many<T: DatabaseType>(data: Vec<T>, client: &mut Client) {
-        
+    fn insert(&self, client: &mut Client) {
+        let timestamp = format!("{}", self.timestamp.format("%Y-%m-%d %H:%M:%S"));
+
+        if let Err(e) = client.execute("INSERT INTO AnalogSample (name, value, unit, timestamp) VALUES ($1, $2, $3, $4::TEXT::TIMESTAMP)",
+             &[&self.name, &self.value, &self.unit, &timestamp]) {
+            println!("Failed to insert analog data! Error: {}", e);
+        }
     }
+}
 
-    fn find_all<T: DatabaseType>() -> Vec<T> {
-        Vec::new()
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProbeTemperature {
+    rom_id: String,
+    name: String,
+    temperature: f32,
+    #[serde(with = "dt_format")]
+    timestamp: DateTime<Utc>
+}
+
+impl ProbeTemperature {
+    pub fn new(rom_id: String, name: String, temperature: f32, timestamp: DateTime<Utc>) -> Self {
+        Self {
+            rom_id,
+            name,
+            temperature,
+            timestamp
+        }
+    }
+}
+
+impl DatabaseType for ProbeTemperature {
+    fn create_table(client: &mut Client) {
+        client.batch_execute("
+            CREATE TABLE IF NOT EXISTS ProbeTemperature (
+                id              SERIAL PRIMARY KEY,
+                rom_id          TEXT NOT NULL,
+                name            TEXT NOT NULL,
+                temperature     REAL NOT NULL,
+                timestamp       TIMESTAMP NOT NULL
+            )
+        ").expect("Failed to create table!");
+    }
+
+    fn insert(&self, client: &mut Client) {
+        let timestamp = format!("{}", self.timestamp.format("%Y-%m-%d %H:%M:%S"));
+
+        if let Err(e) = client.execute("INSERT INTO ProbeTemperature (rom_id, name, temperature, timestamp) VALUES ($1, $2, $3, $4::TEXT::TIMESTAMP)",
+             &[&self.rom_id, &self.name, &self.temperature, &timestamp]) {
+            println!("Failed to insert probe temperature! Error: {}", e);
+        }
+    }
+}
+
+// A reading quality control didn't pass as good, dropped ones included
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QcRecord {
+    source: String,
+    quantity: String,
+    value: f32,
+    flag: String,
+    #[serde(with = "dt_format")]
+    timestamp: DateTime<Utc>
+}
+
+impl QcRecord {
+    pub fn new(source: String, quantity: String, value: f32, flag: String, timestamp: DateTime<Utc>) -> Self {
+        Self {
+            source,
+            quantity,
+            value,
+            flag,
+            timestamp
+        }
+    }
+}
+
+impl DatabaseType for QcRecord {
+    fn create_table(client: &mut Client) {
+        client.batch_execute("
+            CREATE TABLE IF NOT EXISTS QcFlag (
+                id              SERIAL PRIMARY KEY,
+                source          TEXT NOT NULL,
+                quantity        TEXT NOT NULL,
+                value           REAL NOT NULL,
+                flag            TEXT NOT NULL,
+                timestamp       TIMESTAMP NOT NULL
+            )
+        ").expect("Failed to create table!");
+    }
+
+    fn insert(&self, client: &mut Client) {
+        let timestamp = format!("{}", self.timestamp.format("%Y-%m-%d %H:%M:%S"));
+
+        if let Err(e) = client.execute("INSERT INTO QcFlag (source, quantity, value, flag, timestamp) VALUES ($1, $2, $3, $4, $5::TEXT::TIMESTAMP)",
+             &[&self.source, &self.quantity, &self.value, &self.flag, &timestamp]) {
+            println!("Failed to insert quality control flag! Error: {}", e);
+        }
     }
 }
 
@@ -52,7 +179,7 @@ pub mod dt_format {
     use chrono::{DateTime, Utc, TimeZone};
     use serde::{self, Deserialize, Serializer, Deserializer};
 
-    const FORMAT: &'static str = "%Y-%m-%d %H:%M:%S";
+    const FORMAT: &str = "%Y-%m-%d %H:%M:%S";
 
     // The signature of a serialize_with function must follow the pattern:
     //
@@ -94,7 +221,7 @@ pub mod date_format {
     use chrono::{TimeZone, Date, Local, NaiveDate};
     use serde::{self, Deserialize, Serializer, Deserializer};
 
-    const FORMAT: &'static str = "%Y-%m-%d";
+    const FORMAT: &str = "%Y-%m-%d";
 
     // The signature of a serialize_with function must follow the pattern:
     //
@@ -128,7 +255,7 @@ pub mod date_format {
         D: Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        let date = NaiveDate::parse_from_str(&s, FORMAT).unwrap();
-        Ok(Local.from_local_date(&date).unwrap())
+        let date = NaiveDate::parse_from_str(&s, FORMAT).map_err(serde::de::Error::custom)?;
+        Local.from_local_date(&date).single().ok_or_else(|| serde::de::Error::custom("date doesn't exist in the local timezone"))
     }
 }
\ No newline at end of file
@@ -0,0 +1,78 @@
+use serde::{ Serialize };
+
+use crate::units::{ Units, kph_to_beaufort };
+
+const BEAUFORT_DESCRIPTIONS: [&str; 13] = [
+    "Calm", "Light air", "Light breeze", "Gentle breeze", "Moderate breeze", "Fresh breeze", "Strong breeze",
+    "Near gale", "Gale", "Strong gale", "Storm", "Violent storm", "Hurricane force"
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Beaufort {
+    pub force: u8,
+    pub description: &'static str
+}
+
+impl Beaufort {
+    pub fn from_kph(kph: f32) -> Self {
+        let force = kph_to_beaufort(kph);
+
+        Self {
+            force,
+            description: BEAUFORT_DESCRIPTIONS[force as usize]
+        }
+    }
+
+    pub fn is_calm(&self) -> bool {
+        self.force == 0
+    }
+}
+
+// e.g. "Fresh breeze from the NW at 22.4 mph, gusting to 31.1 mph"
+pub fn get_wind_summary(speed_kph: f32, gust_kph: Option<f32>, direction: Option<&str>, units: &Units) -> String {
+    let beaufort = Beaufort::from_kph(speed_kph);
+    let symbol = units.speed.get_symbol();
+
+    let mut summary = beaufort.description.to_string();
+
+    if !beaufort.is_calm() {
+        if let Some(direction) = direction {
+            summary.push_str(&format!(" from the {}", direction));
+        }
+
+        summary.push_str(&format!(" at {:.1} {}", units.speed.convert_kph(speed_kph), symbol));
+    }
+
+    // Only worth mentioning when it's a step up the scale from the average
+    if let Some(gust_kph) = gust_kph.filter(|gust_kph| Beaufort::from_kph(*gust_kph).force > beaufort.force) {
+        let separator = if beaufort.is_calm() { " with gusts to" } else { ", gusting to" };
+
+        summary.push_str(&format!("{} {:.1} {}", separator, units.speed.convert_kph(gust_kph), symbol));
+    }
+
+    summary
+}
+
+#[cfg(test)]
+mod test {
+    use crate::data::wind::{ Beaufort, get_wind_summary };
+    use crate::units::{ UnitSystem };
+
+    #[test]
+    fn test_beaufort() {
+        assert!(Beaufort::from_kph(1.0).is_calm());
+        assert_eq!(Beaufort::from_kph(35.0).description, "Fresh breeze");
+        assert_eq!(Beaufort::from_kph(80.0).force, 9);
+        assert_eq!(Beaufort::from_kph(200.0).description, "Hurricane force");
+    }
+
+    #[test]
+    fn test_summary() {
+        let metric = UnitSystem::Metric.get_units();
+
+        assert_eq!(get_wind_summary(1.0, Some(1.5), Some("N"), &metric), "Calm");
+        assert_eq!(get_wind_summary(35.0, Some(36.0), Some("NW"), &metric), "Fresh breeze from the NW at 35.0 km/h");
+        assert_eq!(get_wind_summary(35.0, Some(55.0), None, &metric), "Fresh breeze at 35.0 km/h, gusting to 55.0 km/h");
+        assert_eq!(get_wind_summary(0.0, Some(8.0), None, &metric), "Calm with gusts to 8.0 km/h");
+    }
+}
@@ -1,32 +1,59 @@
-use rppal::spi::{ Spi, Bus, SlaveSelect, Mode, BitOrder };
+use std::error::Error;
+use rppal::spi::{ Spi, Bus, SlaveSelect, Mode, BitOrder, Result };
+
+const BUFFER_SIZE: usize = 16;
+const MAX_READING: u16 = 0x03FF;
+
+pub const REFERENCE_VOLTAGE: f32 = 3.3;
 
-#[allow(dead_code)]
 pub struct MCP3008 {
-    bus: Bus,
-    slave_select: SlaveSelect,
-    clock_speed: u32,
-    mode: Mode,
     spi: Spi
 }
 
 impl MCP3008 {
-    pub fn new(bus: Bus, slave_select: SlaveSelect, clock_speed: u32, mode: Mode) -> Self {
-        let spi = Spi::new(bus, slave_select, clock_speed, mode).unwrap();
-        spi.set_bit_order(BitOrder::MsbFirst).unwrap();
-
-        Self {
-            bus,
-            slave_select,
-            clock_speed,
-            mode,
-            spi: spi
-        }
+    pub fn new(bus: Bus, slave_select: SlaveSelect, clock_speed: u32, mode: Mode) -> Result<Self> {
+        let spi = Spi::new(bus, slave_select, clock_speed, mode)?;
+        spi.set_bit_order(BitOrder::MsbFirst)?;
+
+        Ok(Self {
+            spi
+        })
     }
 
-    pub fn read_from_channel(&mut self, channel: u8, buf: &mut [u8]) -> usize {
+    pub fn read_from_channel(&mut self, channel: u8, buf: &mut [u8]) -> Result<usize> {
         let mut command_buf = [0x01u8, 0x80u8, 0u8];
         command_buf[1] |= channel << 5;
 
-        self.spi.transfer(buf, &command_buf).unwrap()
+        self.spi.transfer(buf, &command_buf)
+    }
+
+    pub fn read_voltage(&mut self, channel: u8) -> std::result::Result<f32, Box<dyn Error>> {
+        let mut buf = [0u8; BUFFER_SIZE];
+        let bytes_read = self.read_from_channel(channel, &mut buf)?;
+
+        if bytes_read < 3 {
+            return Err(format!("Expected 3 bytes from MCP3008, got {}", bytes_read).into());
+        }
+
+        Ok(parse_voltage(&buf[..bytes_read]))
     }
-}
\ No newline at end of file
+}
+
+// The 10-bit result is spread across the low 2 bits of the second byte and all of the third
+pub fn parse_voltage(bytes: &[u8]) -> f32 {
+    let reading = ((bytes[1] & 3) as u16) << 8 | bytes[2] as u16;
+
+    (reading as f32 / MAX_READING as f32) * REFERENCE_VOLTAGE
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hardware::analog::{ parse_voltage, REFERENCE_VOLTAGE };
+
+    #[test]
+    fn test_parse_voltage() {
+        assert_eq!(parse_voltage(&[ 0x00, 0x00, 0x00 ]), 0.0);
+        assert_eq!(parse_voltage(&[ 0xFF, 0xFF, 0xFF ]), REFERENCE_VOLTAGE);
+        assert!((parse_voltage(&[ 0x00, 0x02, 0x00 ]) - 512.0 / 1023.0 * REFERENCE_VOLTAGE).abs() < 0.0001);
+    }
+}
@@ -0,0 +1,181 @@
+use std::time::{ SystemTime };
+use crossbeam_channel::{ Sender };
+use serde::{ Serialize, Deserialize };
+use postgres::{ Client };
+
+use super::events::{ Payload };
+use super::analog::{ MCP3008 };
+use super::status::{ SensorState, SensorStatusPayload };
+use crate::config::{ AnalogSensorConfig };
+use crate::data::process::{ DataPoint, DaytimeData };
+use crate::data::qc::{ QcMask };
+use crate::data::types::{ AnalogSample };
+use crate::data::DatabaseType;
+
+pub const SENSOR_NAME: &str = "analog";
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TransferFunction {
+    Linear { scale: f32, offset: f32 },         // value = voltage * scale + offset
+    Table { points: Vec<(f32, f32)> }           // (voltage, value) pairs, interpolated between and clamped at the ends
+}
+
+impl TransferFunction {
+    pub fn apply(&self, voltage: f32) -> f32 {
+        match self {
+            TransferFunction::Linear { scale, offset } => voltage * scale + offset,
+            TransferFunction::Table { points } => {
+                let (first, last) = (points[0], points[points.len() - 1]);
+
+                if voltage <= first.0 {
+                    return first.1;
+                }
+
+                if voltage >= last.0 {
+                    return last.1;
+                }
+
+                let upper = points.iter().position(|(v, _)| *v >= voltage).unwrap();
+                let ((v0, y0), (v1, y1)) = (points[upper - 1], points[upper]);
+
+                y0 + (voltage - v0) / (v1 - v0) * (y1 - y0)
+            }
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            TransferFunction::Linear { .. } => Ok(()),
+            TransferFunction::Table { points } => {
+                if points.len() < 2 {
+                    return Err("a lookup table needs at least 2 points".to_string());
+                }
+
+                if points.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+                    return Err("lookup table voltages must be strictly increasing".to_string());
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AnalogReading {
+    pub value: f32,
+    pub unit: String,
+    pub voltage: f32,
+    #[serde(skip)]
+    pub last_updated: SystemTime
+}
+
+pub struct AnalogPayload {
+    readings: Vec<(String, AnalogReading)>
+}
+
+impl AnalogPayload {
+    pub fn new(readings: Vec<(String, AnalogReading)>) -> Self {
+        Self {
+            readings
+        }
+    }
+}
+
+impl Payload for AnalogPayload {
+    fn send_message(&self) {
+        // ...
+    }
+
+    fn update_data_fields(&self, data: &mut DataPoint, _daytime_info: &mut DaytimeData, _rejected: &QcMask) {
+        for (name, reading) in self.readings.iter() {
+            data.update_analog(name.clone(), reading.clone());
+        }
+
+        data.update_sensor_state(SENSOR_NAME, SensorState::Ok);
+    }
+
+    fn save_to_database(&self, client: &mut Client, _rejected: &QcMask) {
+        for (name, reading) in self.readings.iter() {
+            AnalogSample::new(name.clone(), reading.value, reading.unit.clone(), reading.last_updated.into()).insert(client);
+        }
+    }
+}
+
+// Every configured MCP3008 channel other than the vane, read together on the analog schedule
+pub struct AnalogSensors {
+    mcp: MCP3008,
+    sensors: Vec<AnalogSensorConfig>,
+    payload_sender: Sender<Box<dyn Payload>>
+}
+
+impl AnalogSensors {
+    pub fn new(mcp: MCP3008, sensors: Vec<AnalogSensorConfig>, payload_sender: Sender<Box<dyn Payload>>) -> Self {
+        Self {
+            mcp,
+            sensors: sensors.into_iter().filter(|sensor| sensor.enabled).collect(),
+            payload_sender
+        }
+    }
+
+    pub fn update_data(&mut self) {
+        let mut readings = Vec::new();
+        let mut failed = false;
+
+        for sensor in self.sensors.iter() {
+            match self.mcp.read_voltage(sensor.channel) {
+                Ok(voltage) => {
+                    readings.push((sensor.name.clone(), AnalogReading {
+                        value: sensor.transfer.apply(voltage),
+                        unit: sensor.unit.clone(),
+                        voltage,
+                        last_updated: SystemTime::now()
+                    }));
+                },
+                Err(e) => {
+                    println!("Failed to read analog sensor \"{}\" on channel {}! Error: {}", sensor.name, sensor.channel, e);
+
+                    failed = true;
+                }
+            }
+        }
+
+        if !readings.is_empty() {
+            self.payload_sender.send(Box::new(AnalogPayload::new(readings))).unwrap();
+        }
+
+        if failed {
+            self.payload_sender.send(Box::new(SensorStatusPayload::new(SENSOR_NAME, SensorState::Failing))).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hardware::analog_sensor::{ TransferFunction };
+
+    #[test]
+    fn test_linear() {
+        // Battery voltage through a 1:2 divider
+        let divider = TransferFunction::Linear { scale: 2.0, offset: 0.0 };
+
+        assert_eq!(divider.apply(1.65), 3.3);
+        assert!(divider.validate().is_ok());
+    }
+
+    #[test]
+    fn test_table() {
+        // Soil moisture probe reads high when dry
+        let table = TransferFunction::Table { points: vec![ (1.0, 100.0), (2.0, 40.0), (2.8, 0.0) ] };
+
+        assert!(table.validate().is_ok());
+        assert_eq!(table.apply(0.5), 100.0);
+        assert_eq!(table.apply(1.5), 70.0);
+        assert!((table.apply(2.4) - 20.0).abs() < 0.001);
+        assert_eq!(table.apply(3.3), 0.0);
+
+        assert!(TransferFunction::Table { points: vec![ (1.0, 0.0) ] }.validate().is_err());
+        assert!(TransferFunction::Table { points: vec![ (2.0, 0.0), (1.0, 1.0) ] }.validate().is_err());
+    }
+}
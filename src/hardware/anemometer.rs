@@ -1,25 +1,37 @@
-use rppal::gpio::{ InputPin, Trigger };
-use std::time::{ SystemTime };
+use rppal::gpio::{ InputPin, Trigger, Result };
+use std::collections::{ VecDeque };
+use std::time::{ Duration, Instant, SystemTime };
 use crossbeam_channel::{ Sender };
 
 use super::events::{ Event, Payload, EventType };
+use super::status::{ SensorState };
+use crate::config::{ AnemometerCalibration };
 use crate::data::process::{ DataPoint, DaytimeData };
+use crate::data::qc::{ QcMask, Quantity, SourceReadings };
+use crate::units::{ kph_to_mph };
 
 const CM_TO_KM: f32 = 100000.0;
 const SEC_TO_HR: f32 = 3600.0;
-const KM_TO_MI: f32 = 1.609344;
-const WIND_ADJUSTMENT: f32 = 1.18;
+
+// WMO gusts are the highest 3 second average within the reporting period
+const GUST_WINDOW: Duration = Duration::from_secs(3);
+
+pub const SENSOR_NAME: &str = "anemometer";
 
 #[derive(Debug, Clone, Copy)]
 pub struct AnemometerData {
     spins_per_sec: f32,
+    gust_spins_per_sec: f32,
+    calibration: AnemometerCalibration,
     last_updated: Option<SystemTime>
 }
 
 impl AnemometerData {
-    pub fn new(spins_per_sec: f32, last_updated: Option<SystemTime>) -> Self {
+    pub fn new(spins_per_sec: f32, gust_spins_per_sec: f32, calibration: AnemometerCalibration, last_updated: Option<SystemTime>) -> Self {
         Self {
             spins_per_sec,
+            gust_spins_per_sec,
+            calibration,
             last_updated
         }
     }
@@ -32,24 +44,25 @@ impl AnemometerData {
         self.last_updated
     }
 
-    fn get_cm_per_sec(&self) -> f32 {
-        (self.spins_per_sec / 2.0) * ((2.0 * std::f32::consts::PI) * 9.0)
+    fn get_cm_per_sec(&self, spins_per_sec: f32) -> f32 {
+        (spins_per_sec / 2.0) * ((2.0 * std::f32::consts::PI) * self.calibration.cup_radius_cm)
     }
 
-    pub fn get_kph(&self) -> f32{
-        (self.get_cm_per_sec() / CM_TO_KM) * SEC_TO_HR * WIND_ADJUSTMENT
+    fn spins_to_kph(&self, spins_per_sec: f32) -> f32 {
+        (self.get_cm_per_sec(spins_per_sec) / CM_TO_KM) * SEC_TO_HR * self.calibration.multiplier
     }
 
-    pub fn get_mph(&self) -> f32 {
-        self.get_kph() / KM_TO_MI
+    pub fn get_kph(&self) -> f32{
+        self.spins_to_kph(self.spins_per_sec)
     }
 
-    pub fn convert_to_kph(spins: f32) -> f32 {
-        (spins / 2.0) * ((2.0 * std::f32::consts::PI) * 9.0)
+    pub fn get_mph(&self) -> f32 {
+        kph_to_mph(self.get_kph())
     }
 
-    pub fn convert_to_mph(spins: f32) -> f32 {
-        Self::convert_to_kph(spins) / KM_TO_MI
+    // Never below the average, a period too short for a full gust window would otherwise report less
+    pub fn get_gust_kph(&self) -> f32 {
+        self.spins_to_kph(self.gust_spins_per_sec.max(self.spins_per_sec))
     }
 }
 
@@ -58,9 +71,9 @@ pub struct AnemometerPayload {
 }
 
 impl AnemometerPayload {
-    pub fn new(spins_per_sec: f32, last_updated: Option<SystemTime>) -> Self {
+    pub fn new(spins_per_sec: f32, gust_spins_per_sec: f32, calibration: AnemometerCalibration, last_updated: Option<SystemTime>) -> Self {
         Self {
-            data: AnemometerData::new(spins_per_sec, last_updated)
+            data: AnemometerData::new(spins_per_sec, gust_spins_per_sec, calibration, last_updated)
         }
     }
 }
@@ -70,16 +83,26 @@ impl Payload for AnemometerPayload {
         // ...
     }
 
-    fn update_data_fields(&self, data: &mut DataPoint, daytime_info: &mut DaytimeData) {
-        if daytime_info.wind_min < 0.0 || daytime_info.wind_min > self.data.spins_per_sec {
-            daytime_info.wind_min = self.data.spins_per_sec;
-        }
+    fn update_data_fields(&self, data: &mut DataPoint, daytime_info: &mut DaytimeData, rejected: &QcMask) {
+        if !rejected.is_rejected(SENSOR_NAME, Quantity::WindSpeed) {
+            let kph = self.data.get_kph();
 
-        if daytime_info.wind_max < self.data.spins_per_sec {
-            daytime_info.wind_max = self.data.spins_per_sec;
+            if daytime_info.wind_min < 0.0 || daytime_info.wind_min > kph {
+                daytime_info.wind_min = kph;
+            }
+
+            if daytime_info.wind_max < kph {
+                daytime_info.wind_max = kph;
+            }
+
+            data.update_anemometer(self.data);
         }
 
-        data.update_anemometer(self.data.clone());
+        data.update_sensor_state(SENSOR_NAME, SensorState::Ok);
+    }
+
+    fn get_readings(&self) -> Vec<SourceReadings> {
+        vec![ SourceReadings::new(SENSOR_NAME, vec![ (Quantity::WindSpeed, self.data.get_kph()) ]) ]
     }
 }
 
@@ -89,31 +112,46 @@ pub struct Anemometer {
     payload_sender: Sender<Box<dyn Payload>>,
     counter: i32,
     spins_per_sec: f32,
+    recent_spins: VecDeque<Instant>,
+    max_window_spins: usize,
+    calibration: AnemometerCalibration,
     last_updated: SystemTime
 }
 
 impl Anemometer {
-    pub fn new(pin: InputPin, sender: Sender<Event>, payload_sender: Sender<Box<dyn Payload>>) -> Self {
+    pub fn new(pin: InputPin, sender: Sender<Event>, payload_sender: Sender<Box<dyn Payload>>, calibration: AnemometerCalibration) -> Self {
         Self {
             pin,
             sender,
             payload_sender,
             counter: 0,
             spins_per_sec: 0.0,
+            recent_spins: VecDeque::new(),
+            max_window_spins: 0,
+            calibration,
             last_updated: SystemTime::now()
         }
     }
 
-    pub fn start(&mut self) {
+    pub fn start(&mut self) -> Result<()> {
         let copy_sender = self.sender.clone();
 
         self.pin.set_async_interrupt(Trigger::RisingEdge, move |_| {
             copy_sender.send(Event::new(EventType::AnemometerCount)).unwrap();
-        }).unwrap();
+        })
     }
 
     pub fn increment_counter(&mut self) {
         self.counter += 1;
+
+        let now = Instant::now();
+
+        while self.recent_spins.front().is_some_and(|time| now.duration_since(*time) > GUST_WINDOW) {
+            self.recent_spins.pop_front();
+        }
+
+        self.recent_spins.push_back(now);
+        self.max_window_spins = self.max_window_spins.max(self.recent_spins.len());
     }
 
     pub fn update_data(&mut self) {
@@ -123,6 +161,37 @@ impl Anemometer {
         self.counter = 0;
         self.last_updated = SystemTime::now();
 
-        self.payload_sender.send(Box::new(AnemometerPayload::new(self.spins_per_sec, Some(self.last_updated)))).unwrap();
+        let gust_spins_per_sec = self.max_window_spins as f32 / GUST_WINDOW.as_secs_f32();
+        self.max_window_spins = 0;
+
+        self.payload_sender.send(Box::new(AnemometerPayload::new(self.spins_per_sec, gust_spins_per_sec, self.calibration, Some(self.last_updated)))).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::{ AnemometerCalibration };
+    use crate::hardware::anemometer::{ AnemometerData };
+
+    #[test]
+    fn test_calibrated_speed() {
+        // One spin a second on 10cm cups is half a circumference, 31.4cm/s
+        let calibration = AnemometerCalibration { multiplier: 1.0, cup_radius_cm: 10.0 };
+        let data = AnemometerData::new(1.0, 0.0, calibration, None);
+
+        assert!((data.get_kph() - 1.131).abs() < 0.001);
+        assert!((data.get_mph() - 0.703).abs() < 0.001);
+
+        // The multiplier scales the reading, the defaults match the stock 9cm cups
+        let data = AnemometerData::new(1.0, 0.0, AnemometerCalibration { multiplier: 2.0, ..calibration }, None);
+        assert!((data.get_kph() - 2.262).abs() < 0.001);
+
+        let data = AnemometerData::new(1.0, 0.0, AnemometerCalibration::default(), None);
+        assert!((data.get_kph() - 1.201).abs() < 0.001);
+
+        // A gust is never reported below the average
+        let data = AnemometerData::new(2.0, 1.0, calibration, None);
+        assert_eq!(data.get_gust_kph(), data.get_kph());
+        assert!((AnemometerData::new(1.0, 3.0, calibration, None).get_gust_kph() - 3.393).abs() < 0.001);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,370 @@
+// ams AS3935 Franklin lightning sensor over I2C, register map from the datasheet:
+// https://www.sciosense.com/wp-content/uploads/documents/AS3935-Data-Sheet.pdf
+
+use rppal::gpio::{ InputPin, Trigger };
+use rppal::i2c::{ I2c };
+use std::collections::{ VecDeque };
+use std::error::Error;
+use std::thread::{ sleep };
+use std::time::{ Duration, SystemTime };
+use crossbeam_channel::{ Sender };
+
+use super::events::{ Event, EventType, Payload };
+use super::status::{ SensorState, SensorStatusPayload };
+use crate::config::{ AS3935Config };
+use crate::data::process::{ DataPoint, DaytimeData };
+use crate::data::qc::{ QcMask };
+
+// Registers
+const REG_AFE_GAIN: u8 = 0x00;
+const REG_THRESHOLD: u8 = 0x01;
+const REG_LIGHTNING: u8 = 0x02;
+const REG_INTERRUPT: u8 = 0x03;
+const REG_ENERGY_LSB: u8 = 0x04;
+const REG_DISTANCE: u8 = 0x07;
+const REG_TUNING: u8 = 0x08;
+const REG_PRESET_DEFAULT: u8 = 0x3C;
+const REG_CALIB_RCO: u8 = 0x3D;
+
+const DIRECT_COMMAND: u8 = 0x96;
+
+// AFE gain boost settings, bits 5:1 of register 0x00
+const AFE_INDOOR: u8 = 0b10010;
+const AFE_OUTDOOR: u8 = 0b01110;
+
+const INT_NOISE: u8 = 0x01;
+const INT_DISTURBER: u8 = 0x04;
+const INT_LIGHTNING: u8 = 0x08;
+
+const MASK_DISTURBER: u8 = 0x20;
+const DISTANCE_OUT_OF_RANGE: u8 = 0x3F;
+const DISTANCE_OVERHEAD: u8 = 0x01;
+const MAX_NOISE_FLOOR: u8 = 7;
+
+// The interrupt register isn't ready until 2ms after IRQ goes high
+const INTERRUPT_SETTLE: Duration = Duration::from_millis(2);
+const STRIKE_HISTORY_LENGTH: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub const SENSOR_NAME: &str = "as3935";
+
+pub fn get_min_strikes_bits(min_strikes: u8) -> Option<u8> {
+    match min_strikes {
+        1 => Some(0),
+        5 => Some(1),
+        9 => Some(2),
+        16 => Some(3),
+        _ => None
+    }
+}
+
+// Estimated distance to the head of the storm in km, 0 when it's overhead and None when out of range (> 40km)
+pub fn parse_distance(register: u8) -> Option<u8> {
+    match register & 0x3F {
+        DISTANCE_OUT_OF_RANGE => None,
+        DISTANCE_OVERHEAD => Some(0),
+        distance => Some(distance)
+    }
+}
+
+// The energy is a unitless 21-bit value spread over three registers, least significant first
+pub fn parse_energy(bytes: &[u8; 3]) -> u32 {
+    ((bytes[2] & 0x1F) as u32) << 16 | (bytes[1] as u32) << 8 | bytes[0] as u32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightningEvent {
+    Strike { distance_km: Option<u8>, energy: u32 },
+    Disturber,
+    Noise
+}
+
+impl LightningEvent {
+    pub fn from_interrupt(interrupt: u8) -> Option<Self> {
+        match interrupt & 0x0F {
+            INT_LIGHTNING => Some(LightningEvent::Strike { distance_km: None, energy: 0 }),
+            INT_DISTURBER => Some(LightningEvent::Disturber),
+            INT_NOISE => Some(LightningEvent::Noise),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LightningData {
+    strikes: VecDeque<SystemTime>,
+    last_strike: Option<SystemTime>,
+    last_distance_km: Option<u8>,
+    last_energy: u32,
+    disturbers: u32,
+    noise_events: u32
+}
+
+impl LightningData {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_strike(&mut self, time: SystemTime, distance_km: Option<u8>, energy: u32) {
+        self.strikes.push_back(time);
+        self.last_strike = Some(time);
+        self.last_distance_km = distance_km;
+        self.last_energy = energy;
+
+        while let Some(oldest) = self.strikes.front() {
+            match time.duration_since(*oldest) {
+                Ok(age) if age > STRIKE_HISTORY_LENGTH => { self.strikes.pop_front(); },
+                _ => break
+            }
+        }
+    }
+
+    pub fn add_disturber(&mut self) {
+        self.disturbers += 1;
+    }
+
+    pub fn add_noise_event(&mut self) {
+        self.noise_events += 1;
+    }
+
+    pub fn get_strikes_since(&self, period: Duration, now: SystemTime) -> usize {
+        self.strikes.iter().filter(|time| now.duration_since(**time).map(|age| age <= period).unwrap_or(true)).count()
+    }
+
+    pub fn get_last_strike(&self) -> Option<SystemTime> {
+        self.last_strike
+    }
+
+    pub fn get_last_distance_km(&self) -> Option<u8> {
+        self.last_distance_km
+    }
+
+    pub fn get_last_energy(&self) -> u32 {
+        self.last_energy
+    }
+
+    pub fn get_disturbers(&self) -> u32 {
+        self.disturbers
+    }
+
+    pub fn get_noise_events(&self) -> u32 {
+        self.noise_events
+    }
+
+    // The alert holds while strikes within range keep coming, and clears once none have been seen for the clear period
+    pub fn is_alert_active(&self, alert_distance_km: u8, clear_after: Duration, now: SystemTime) -> bool {
+        match (self.last_strike, self.last_distance_km) {
+            (Some(time), Some(distance)) => distance <= alert_distance_km && now.duration_since(time).map(|age| age <= clear_after).unwrap_or(true),
+            _ => false
+        }
+    }
+}
+
+pub struct LightningPayload {
+    event: LightningEvent,
+    time: SystemTime
+}
+
+impl LightningPayload {
+    pub fn new(event: LightningEvent, time: SystemTime) -> Self {
+        Self {
+            event,
+            time
+        }
+    }
+}
+
+impl Payload for LightningPayload {
+    fn send_message(&self) {
+        // ...
+    }
+
+    fn update_data_fields(&self, data: &mut DataPoint, daytime_info: &mut DaytimeData, _rejected: &QcMask) {
+        match self.event {
+            LightningEvent::Strike { distance_km, energy } => {
+                daytime_info.lightning_strikes += 1;
+
+                data.add_lightning_strike(self.time, distance_km, energy);
+            },
+            LightningEvent::Disturber => data.add_lightning_disturber(),
+            LightningEvent::Noise => data.add_lightning_noise_event()
+        }
+
+        data.update_sensor_state(SENSOR_NAME, SensorState::Ok);
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+pub struct AS3935 {
+    i2c: I2c,
+    irq_pin: InputPin,
+    config: AS3935Config,
+    noise_floor: u8,
+    sender: Sender<Event>,
+    payload_sender: Sender<Box<dyn Payload>>
+}
+
+impl AS3935 {
+    pub fn new(config: AS3935Config, irq_pin: InputPin, sender: Sender<Event>, payload_sender: Sender<Box<dyn Payload>>) -> Result<Self, Box<dyn Error>> {
+        let mut i2c = I2c::with_bus(config.bus)?;
+        i2c.set_slave_address(config.address)?;
+
+        i2c.smbus_write_byte(REG_PRESET_DEFAULT, DIRECT_COMMAND)?;
+        sleep(Duration::from_millis(2));
+
+        let mut sensor = Self {
+            i2c,
+            irq_pin,
+            config,
+            noise_floor: config.noise_floor,
+            sender,
+            payload_sender
+        };
+
+        sensor.configure()?;
+
+        Ok(sensor)
+    }
+
+    fn configure(&mut self) -> Result<(), Box<dyn Error>> {
+        let afe_gain = if self.config.indoor { AFE_INDOOR } else { AFE_OUTDOOR };
+        self.i2c.smbus_write_byte(REG_AFE_GAIN, afe_gain << 1)?;
+
+        self.write_threshold()?;
+
+        let min_strikes = get_min_strikes_bits(self.config.min_strikes).unwrap_or(0);
+        self.i2c.smbus_write_byte(REG_LIGHTNING, 0x80 | min_strikes << 4 | (self.config.spike_rejection & 0x0F))?;
+
+        let interrupt = self.i2c.smbus_read_byte(REG_INTERRUPT)?;
+        let interrupt = if self.config.mask_disturbers { interrupt | MASK_DISTURBER } else { interrupt & !MASK_DISTURBER };
+        self.i2c.smbus_write_byte(REG_INTERRUPT, interrupt)?;
+
+        self.i2c.smbus_write_byte(REG_TUNING, self.config.tuning_capacitor & 0x0F)?;
+
+        // Calibrate the internal RC oscillators against the (now tuned) antenna
+        self.i2c.smbus_write_byte(REG_CALIB_RCO, DIRECT_COMMAND)?;
+        sleep(Duration::from_millis(2));
+
+        Ok(())
+    }
+
+    fn write_threshold(&mut self) -> Result<(), Box<dyn Error>> {
+        self.i2c.smbus_write_byte(REG_THRESHOLD, (self.noise_floor & 0x07) << 4 | (self.config.watchdog_threshold & 0x0F))?;
+
+        Ok(())
+    }
+
+    pub fn start(&mut self) -> rppal::gpio::Result<()> {
+        let copy_sender = self.sender.clone();
+
+        self.irq_pin.set_async_interrupt(Trigger::RisingEdge, move |_| {
+            copy_sender.send(Event::new(EventType::LightningInterrupt)).unwrap();
+        })
+    }
+
+    pub fn handle_interrupt(&mut self) {
+        match self.read_event() {
+            Ok(Some(event)) => {
+                if let LightningEvent::Noise = event {
+                    self.raise_noise_floor();
+                }
+
+                self.payload_sender.send(Box::new(LightningPayload::new(event, SystemTime::now()))).unwrap();
+            },
+            Ok(None) => {},
+            Err(e) => {
+                println!("Failed to read from AS3935! Error: {}", e);
+
+                self.payload_sender.send(Box::new(SensorStatusPayload::new(SENSOR_NAME, SensorState::Failing))).unwrap();
+            }
+        }
+    }
+
+    fn read_event(&mut self) -> Result<Option<LightningEvent>, Box<dyn Error>> {
+        sleep(INTERRUPT_SETTLE);
+
+        let event = match LightningEvent::from_interrupt(self.i2c.smbus_read_byte(REG_INTERRUPT)?) {
+            Some(LightningEvent::Strike { .. }) => {
+                let mut energy = [0u8; 3];
+                self.i2c.write_read(&[ REG_ENERGY_LSB ], &mut energy)?;
+
+                let distance_km = parse_distance(self.i2c.smbus_read_byte(REG_DISTANCE)?);
+
+                Some(LightningEvent::Strike { distance_km, energy: parse_energy(&energy) })
+            },
+            event => event
+        };
+
+        Ok(event)
+    }
+
+    // Too much background noise stops the sensor from working, so back off the noise floor a step at a time
+    fn raise_noise_floor(&mut self) {
+        if self.noise_floor >= MAX_NOISE_FLOOR {
+            println!("AS3935 noise floor is already at its maximum, move the sensor away from sources of interference");
+            return;
+        }
+
+        self.noise_floor += 1;
+
+        match self.write_threshold() {
+            Ok(_) => println!("AS3935 noise level too high, raised noise floor to {}", self.noise_floor),
+            Err(e) => println!("Failed to raise AS3935 noise floor! Error: {}", e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{ Duration, SystemTime };
+    use crate::hardware::as3935::{ LightningData, LightningEvent, get_min_strikes_bits, parse_distance, parse_energy };
+
+    #[test]
+    fn test_registers() {
+        assert_eq!(parse_distance(0x3F), None);
+        assert_eq!(parse_distance(0x01), Some(0));
+        assert_eq!(parse_distance(0x0E), Some(14));
+        assert_eq!(parse_distance(0xC8), Some(8));
+
+        assert_eq!(parse_energy(&[ 0x34, 0x12, 0xE1 ]), 0x011234);
+
+        assert_eq!(LightningEvent::from_interrupt(0x08), Some(LightningEvent::Strike { distance_km: None, energy: 0 }));
+        assert_eq!(LightningEvent::from_interrupt(0x24), Some(LightningEvent::Disturber));
+        assert_eq!(LightningEvent::from_interrupt(0x01), Some(LightningEvent::Noise));
+        assert_eq!(LightningEvent::from_interrupt(0x00), None);
+
+        assert_eq!(get_min_strikes_bits(9), Some(2));
+        assert_eq!(get_min_strikes_bits(3), None);
+    }
+
+    #[test]
+    fn test_strike_counts() {
+        let start = SystemTime::now();
+        let mut data = LightningData::new();
+
+        data.add_strike(start, Some(30), 1000);
+        data.add_strike(start + Duration::from_secs(2 * 60 * 60), Some(12), 2000);
+        data.add_strike(start + Duration::from_secs(2 * 60 * 60 + 600), Some(8), 3000);
+
+        let now = start + Duration::from_secs(2 * 60 * 60 + 900);
+
+        assert_eq!(data.get_strikes_since(Duration::from_secs(60 * 60), now), 2);
+        assert_eq!(data.get_strikes_since(Duration::from_secs(24 * 60 * 60), now), 3);
+        assert_eq!(data.get_last_distance_km(), Some(8));
+    }
+
+    #[test]
+    fn test_alert() {
+        let start = SystemTime::now();
+        let mut data = LightningData::new();
+        let clear_after = Duration::from_secs(30 * 60);
+
+        assert!(!data.is_alert_active(10, clear_after, start));
+
+        data.add_strike(start, Some(20), 1000);
+        assert!(!data.is_alert_active(10, clear_after, start));
+
+        data.add_strike(start, Some(6), 1000);
+        assert!(data.is_alert_active(10, clear_after, start + Duration::from_secs(29 * 60)));
+        assert!(!data.is_alert_active(10, clear_after, start + Duration::from_secs(31 * 60)));
+    }
+}
@@ -0,0 +1,486 @@
+// Bosch BME280 / BMP280 over I2C, compensation formulas from the datasheets:
+// https://www.bosch-sensortec.com/media/boschsensortec/downloads/datasheets/bst-bme280-ds002.pdf
+// https://www.bosch-sensortec.com/media/boschsensortec/downloads/datasheets/bst-bmp280-ds001.pdf
+
+use rppal::i2c::{ I2c };
+use std::error::Error;
+use std::thread::{ sleep };
+use std::time::{ Duration, SystemTime };
+use crossbeam_channel::{ Sender };
+
+use super::events::{ Payload };
+use super::status::{ SensorState, SensorStatusPayload };
+use crate::config::{ BME280Config };
+use crate::data::process::{ DataPoint, DaytimeData };
+use crate::data::qc::{ QcMask, Quantity, SourceReadings };
+
+// Registers
+const REG_CALIB_00: u8 = 0x88;
+const REG_CHIP_ID: u8 = 0xD0;
+const REG_RESET: u8 = 0xE0;
+const REG_CALIB_26: u8 = 0xE1;
+const REG_CTRL_HUM: u8 = 0xF2;
+const REG_STATUS: u8 = 0xF3;
+const REG_CTRL_MEAS: u8 = 0xF4;
+const REG_CONFIG: u8 = 0xF5;
+const REG_DATA: u8 = 0xF7;
+
+// Chip IDs
+const BMP280_CHIP_IDS: [u8; 3] = [ 0x56, 0x57, 0x58 ];
+const BME280_CHIP_ID: u8 = 0x60;
+
+const RESET_COMMAND: u8 = 0xB6;
+const MODE_FORCED: u8 = 0x01;
+const STATUS_MEASURING: u8 = 0x08;
+const MAX_STATUS_POLLS: u32 = 50;
+
+const HPA_TO_PA: f32 = 100.0;
+
+pub const SENSOR_NAME: &str = "bme280";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChipModel {
+    BMP280,
+    BME280
+}
+
+impl ChipModel {
+    pub fn from_chip_id(chip_id: u8) -> Option<Self> {
+        if chip_id == BME280_CHIP_ID {
+            Some(ChipModel::BME280)
+        } else if BMP280_CHIP_IDS.contains(&chip_id) {
+            Some(ChipModel::BMP280)
+        } else {
+            None
+        }
+    }
+
+    pub fn has_humidity(&self) -> bool {
+        *self == ChipModel::BME280
+    }
+}
+
+// Maps an oversampling factor (0 = skipped, 1, 2, 4, 8, 16) to its register value
+pub fn get_oversampling_bits(oversampling: u8) -> Option<u8> {
+    match oversampling {
+        0 => Some(0b000),
+        1 => Some(0b001),
+        2 => Some(0b010),
+        4 => Some(0b011),
+        8 => Some(0b100),
+        16 => Some(0b101),
+        _ => None
+    }
+}
+
+// Maps an IIR filter coefficient (0 = off, 2, 4, 8, 16) to its register value
+pub fn get_filter_bits(filter: u8) -> Option<u8> {
+    match filter {
+        0 => Some(0b000),
+        2 => Some(0b001),
+        4 => Some(0b010),
+        8 => Some(0b011),
+        16 => Some(0b100),
+        _ => None
+    }
+}
+
+// Barometric formula, reduces station pressure to mean sea level
+pub fn get_sea_level_pressure(station_pressure: f32, altitude_m: f32) -> f32 {
+    station_pressure / (1.0 - altitude_m / 44330.0).powf(5.255)
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrimmingParams {
+    dig_t1: u16,
+    dig_t2: i16,
+    dig_t3: i16,
+    dig_p1: u16,
+    dig_p2: i16,
+    dig_p3: i16,
+    dig_p4: i16,
+    dig_p5: i16,
+    dig_p6: i16,
+    dig_p7: i16,
+    dig_p8: i16,
+    dig_p9: i16,
+    dig_h1: u8,
+    dig_h2: i16,
+    dig_h3: u8,
+    dig_h4: i16,
+    dig_h5: i16,
+    dig_h6: i8
+}
+
+impl TrimmingParams {
+    // calib00..calib25 (0x88..0xA1), little endian
+    pub fn from_temp_press_bytes(bytes: &[u8; 26]) -> Self {
+        let word = |i: usize| u16::from_le_bytes([ bytes[i], bytes[i + 1] ]);
+
+        Self {
+            dig_t1: word(0),
+            dig_t2: word(2) as i16,
+            dig_t3: word(4) as i16,
+            dig_p1: word(6),
+            dig_p2: word(8) as i16,
+            dig_p3: word(10) as i16,
+            dig_p4: word(12) as i16,
+            dig_p5: word(14) as i16,
+            dig_p6: word(16) as i16,
+            dig_p7: word(18) as i16,
+            dig_p8: word(20) as i16,
+            dig_p9: word(22) as i16,
+            dig_h1: bytes[25],
+            ..Default::default()
+        }
+    }
+
+    // calib26..calib32 (0xE1..0xE7), H4 and H5 are 12-bit values sharing 0xE5
+    pub fn set_humidity_bytes(&mut self, bytes: &[u8; 7]) {
+        self.dig_h2 = i16::from_le_bytes([ bytes[0], bytes[1] ]);
+        self.dig_h3 = bytes[2];
+        self.dig_h4 = ((bytes[3] as i8 as i16) << 4) | (bytes[4] & 0x0F) as i16;
+        self.dig_h5 = ((bytes[5] as i8 as i16) << 4) | (bytes[4] >> 4) as i16;
+        self.dig_h6 = bytes[6] as i8;
+    }
+
+    // Returns (t_fine, temperature in °C)
+    pub fn compensate_temperature(&self, adc_t: i32) -> (i32, f32) {
+        let adc_t = adc_t as i64;
+        let t1 = self.dig_t1 as i64;
+
+        let var1 = (((adc_t >> 3) - (t1 << 1)) * self.dig_t2 as i64) >> 11;
+        let var2 = (((((adc_t >> 4) - t1) * ((adc_t >> 4) - t1)) >> 12) * self.dig_t3 as i64) >> 14;
+
+        let t_fine = (var1 + var2) as i32;
+        let temp = (t_fine * 5 + 128) >> 8;
+
+        (t_fine, temp as f32 / 100.0)
+    }
+
+    // Returns pressure in Pa, or None when the trimming parameters would divide by zero
+    pub fn compensate_pressure(&self, adc_p: i32, t_fine: i32) -> Option<f32> {
+        let mut var1 = t_fine as i64 - 128000;
+        let mut var2 = var1 * var1 * self.dig_p6 as i64;
+        var2 += (var1 * self.dig_p5 as i64) << 17;
+        var2 += (self.dig_p4 as i64) << 35;
+        var1 = ((var1 * var1 * self.dig_p3 as i64) >> 8) + ((var1 * self.dig_p2 as i64) << 12);
+        var1 = (((1i64 << 47) + var1) * self.dig_p1 as i64) >> 33;
+
+        if var1 == 0 {
+            return None;
+        }
+
+        let mut p = 1048576 - adc_p as i64;
+        p = (((p << 31) - var2) * 3125) / var1;
+        var1 = (self.dig_p9 as i64 * (p >> 13) * (p >> 13)) >> 25;
+        var2 = (self.dig_p8 as i64 * p) >> 19;
+        p = ((p + var1 + var2) >> 8) + ((self.dig_p7 as i64) << 4);
+
+        Some(p as f32 / 256.0)
+    }
+
+    // Returns relative humidity in %
+    pub fn compensate_humidity(&self, adc_h: i32, t_fine: i32) -> f32 {
+        let adc_h = adc_h as i64;
+        let mut v_x1 = t_fine as i64 - 76800;
+
+        v_x1 = ((((adc_h << 14) - ((self.dig_h4 as i64) << 20) - (self.dig_h5 as i64 * v_x1)) + 16384) >> 15)
+            * (((((((v_x1 * self.dig_h6 as i64) >> 10) * (((v_x1 * self.dig_h3 as i64) >> 11) + 32768)) >> 10) + 2097152) * self.dig_h2 as i64 + 8192) >> 14);
+        v_x1 -= ((((v_x1 >> 15) * (v_x1 >> 15)) >> 7) * self.dig_h1 as i64) >> 4;
+        v_x1 = v_x1.clamp(0, 419430400);
+
+        (v_x1 >> 12) as f32 / 1024.0
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PressureData {
+    station_pressure: f32,
+    sea_level_pressure: f32,
+    temperature: f32,
+    humidity: Option<f32>,
+    last_updated: Option<SystemTime>
+}
+
+impl PressureData {
+    pub fn new(station_pressure: f32, sea_level_pressure: f32, temperature: f32, humidity: Option<f32>, last_updated: Option<SystemTime>) -> Self {
+        Self {
+            station_pressure,
+            sea_level_pressure,
+            temperature,
+            humidity,
+            last_updated
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.last_updated.is_some()
+    }
+
+    pub fn get_last_updated(&self) -> Option<SystemTime> {
+        self.last_updated
+    }
+
+    pub fn get_station_pressure_hpa(&self) -> f32 {
+        self.station_pressure
+    }
+
+    pub fn get_sea_level_pressure_hpa(&self) -> f32 {
+        self.sea_level_pressure
+    }
+
+    pub fn get_temp_celsius(&self) -> f32 {
+        self.temperature
+    }
+
+    pub fn get_humidity(&self) -> Option<f32> {
+        self.humidity
+    }
+}
+
+pub struct PressurePayload {
+    data: PressureData
+}
+
+impl PressurePayload {
+    pub fn new(data: PressureData) -> Self {
+        Self {
+            data
+        }
+    }
+}
+
+impl Payload for PressurePayload {
+    fn send_message(&self) {
+        // ...
+    }
+
+    fn update_data_fields(&self, data: &mut DataPoint, _daytime_info: &mut DaytimeData, rejected: &QcMask) {
+        let previous = data.get_pressure_data();
+        let pressure_rejected = rejected.is_rejected(SENSOR_NAME, Quantity::Pressure);
+        let (station_pressure, sea_level_pressure) = if pressure_rejected { (previous.station_pressure, previous.sea_level_pressure) } else { (self.data.station_pressure, self.data.sea_level_pressure) };
+        let temperature = if rejected.is_rejected(SENSOR_NAME, Quantity::Temperature) { previous.temperature } else { self.data.temperature };
+        let humidity = if rejected.is_rejected(SENSOR_NAME, Quantity::Humidity) { previous.humidity } else { self.data.humidity };
+
+        data.update_pressure(PressureData::new(station_pressure, sea_level_pressure, temperature, humidity, self.data.last_updated));
+
+        if !pressure_rejected {
+            data.add_pressure_sample(self.data.get_last_updated().unwrap(), self.data.get_sea_level_pressure_hpa());
+        }
+
+        data.update_sensor_state(SENSOR_NAME, SensorState::Ok);
+    }
+
+    fn get_readings(&self) -> Vec<SourceReadings> {
+        let mut readings = vec![ (Quantity::Pressure, self.data.get_sea_level_pressure_hpa()), (Quantity::Temperature, self.data.get_temp_celsius()) ];
+
+        // A BMP280 doesn't measure humidity
+        if let Some(humidity) = self.data.get_humidity() {
+            readings.push((Quantity::Humidity, humidity));
+        }
+
+        vec![ SourceReadings::new(SENSOR_NAME, readings) ]
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+pub struct BME280 {
+    i2c: I2c,
+    model: ChipModel,
+    trimming: TrimmingParams,
+    config: BME280Config,
+    payload_sender: Sender<Box<dyn Payload>>
+}
+
+impl BME280 {
+    pub fn new(config: BME280Config, payload_sender: Sender<Box<dyn Payload>>) -> Result<Self, Box<dyn Error>> {
+        let mut i2c = I2c::with_bus(config.bus)?;
+        i2c.set_slave_address(config.address)?;
+
+        let chip_id = i2c.smbus_read_byte(REG_CHIP_ID)?;
+        let model = ChipModel::from_chip_id(chip_id).ok_or_else(|| format!("Unknown chip ID 0x{:02X} at address 0x{:02X}", chip_id, config.address))?;
+
+        i2c.smbus_write_byte(REG_RESET, RESET_COMMAND)?;
+        sleep(Duration::from_millis(10));
+
+        let mut temp_press_bytes = [0u8; 26];
+        i2c.write_read(&[ REG_CALIB_00 ], &mut temp_press_bytes)?;
+
+        let mut trimming = TrimmingParams::from_temp_press_bytes(&temp_press_bytes);
+
+        if model.has_humidity() {
+            let mut humidity_bytes = [0u8; 7];
+            i2c.write_read(&[ REG_CALIB_26 ], &mut humidity_bytes)?;
+
+            trimming.set_humidity_bytes(&humidity_bytes);
+        }
+
+        let sensor = Self {
+            i2c,
+            model,
+            trimming,
+            config,
+            payload_sender
+        };
+
+        sensor.i2c.smbus_write_byte(REG_CONFIG, sensor.get_filter_bits() << 2)?;
+
+        Ok(sensor)
+    }
+
+    fn get_filter_bits(&self) -> u8 {
+        get_filter_bits(self.config.filter).unwrap_or(0)
+    }
+
+    pub fn update_data(&mut self) {
+        match self.read_sensor() {
+            Ok(data) => {
+                self.payload_sender.send(Box::new(PressurePayload::new(data))).unwrap();
+            },
+            Err(e) => {
+                println!("Failed to read from {:?}! Error: {}", self.model, e);
+
+                self.payload_sender.send(Box::new(SensorStatusPayload::new(SENSOR_NAME, SensorState::Failing))).unwrap();
+            }
+        }
+    }
+
+    fn read_sensor(&mut self) -> Result<PressureData, Box<dyn Error>> {
+        let osrs_t = get_oversampling_bits(self.config.oversampling_temperature).unwrap_or(1);
+        let osrs_p = get_oversampling_bits(self.config.oversampling_pressure).unwrap_or(1);
+        let osrs_h = get_oversampling_bits(self.config.oversampling_humidity).unwrap_or(1);
+
+        // ctrl_hum only takes effect after a write to ctrl_meas
+        if self.model.has_humidity() {
+            self.i2c.smbus_write_byte(REG_CTRL_HUM, osrs_h)?;
+        }
+
+        self.i2c.smbus_write_byte(REG_CTRL_MEAS, (osrs_t << 5) | (osrs_p << 2) | MODE_FORCED)?;
+
+        let mut polls = 0;
+        loop {
+            sleep(Duration::from_millis(2));
+
+            if self.i2c.smbus_read_byte(REG_STATUS)? & STATUS_MEASURING == 0 {
+                break;
+            }
+
+            polls += 1;
+            if polls >= MAX_STATUS_POLLS {
+                return Err("Timed out waiting for measurement".into());
+            }
+        }
+
+        let mut raw = [0u8; 8];
+        let data_len = if self.model.has_humidity() { 8 } else { 6 };
+        self.i2c.write_read(&[ REG_DATA ], &mut raw[..data_len])?;
+
+        let adc_p = ((raw[0] as i32) << 12) | ((raw[1] as i32) << 4) | ((raw[2] as i32) >> 4);
+        let adc_t = ((raw[3] as i32) << 12) | ((raw[4] as i32) << 4) | ((raw[5] as i32) >> 4);
+        let adc_h = ((raw[6] as i32) << 8) | raw[7] as i32;
+
+        let (t_fine, temperature) = self.trimming.compensate_temperature(adc_t);
+        let station_pressure = self.trimming.compensate_pressure(adc_p, t_fine).ok_or("Invalid pressure trimming parameters")? / HPA_TO_PA;
+        let humidity = if self.model.has_humidity() { Some(self.trimming.compensate_humidity(adc_h, t_fine)) } else { None };
+
+        Ok(PressureData::new(station_pressure, get_sea_level_pressure(station_pressure, self.config.altitude_m), temperature, humidity, Some(SystemTime::now())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hardware::bme280::{ TrimmingParams, ChipModel, get_sea_level_pressure };
+
+    // Example trimming values and readings from section 3.12 of the BMP280 datasheet
+    fn get_datasheet_params() -> TrimmingParams {
+        TrimmingParams {
+            dig_t1: 27504,
+            dig_t2: 26435,
+            dig_t3: -1000,
+            dig_p1: 36477,
+            dig_p2: -10685,
+            dig_p3: 3024,
+            dig_p4: 2855,
+            dig_p5: 140,
+            dig_p6: -7,
+            dig_p7: 15500,
+            dig_p8: -14600,
+            dig_p9: 6000,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_datasheet_compensation() {
+        let params = get_datasheet_params();
+
+        let (t_fine, temp) = params.compensate_temperature(519888);
+        assert_eq!(t_fine, 128422);
+        assert!((temp - 25.08).abs() < 0.001, "Temperature: {}", temp);
+
+        let pressure = params.compensate_pressure(415148, t_fine).unwrap();
+        assert!((pressure - 100653.27).abs() < 0.1, "Pressure: {}", pressure);
+    }
+
+    // The datasheet has no worked humidity example, so these are checked against its floating point formula in section 8.1
+    #[test]
+    fn test_humidity_compensation() {
+        let params = TrimmingParams {
+            dig_h1: 75,
+            dig_h2: 362,
+            dig_h3: 0,
+            dig_h4: 325,
+            dig_h5: 0,
+            dig_h6: 30,
+            ..get_datasheet_params()
+        };
+
+        let (t_fine, _) = params.compensate_temperature(519888);
+
+        let humidity = params.compensate_humidity(30000, t_fine);
+        assert!((humidity - 51.604).abs() < 0.01, "Humidity: {}", humidity);
+
+        let humidity = params.compensate_humidity(25000, t_fine);
+        assert!((humidity - 23.654).abs() < 0.01, "Humidity: {}", humidity);
+
+        // Out of range readings are clamped to 0 and 100 %
+        assert_eq!(params.compensate_humidity(20000, t_fine), 0.0);
+        assert_eq!(params.compensate_humidity(50000, t_fine), 100.0);
+    }
+
+    #[test]
+    fn test_trimming_byte_layout() {
+        let mut bytes = [0u8; 26];
+        bytes[0..2].copy_from_slice(&27504u16.to_le_bytes());
+        bytes[4..6].copy_from_slice(&(-1000i16).to_le_bytes());
+        bytes[22..24].copy_from_slice(&6000i16.to_le_bytes());
+        bytes[25] = 75;
+
+        let mut params = TrimmingParams::from_temp_press_bytes(&bytes);
+        assert_eq!(params.dig_t1, 27504);
+        assert_eq!(params.dig_t3, -1000);
+        assert_eq!(params.dig_p9, 6000);
+        assert_eq!(params.dig_h1, 75);
+
+        // H4 = 0x14 << 4 | 0x5, H5 = 0x00 << 4 | 0x0
+        params.set_humidity_bytes(&[ 0x6A, 0x01, 0x00, 0x14, 0x05, 0x00, 0x1E ]);
+        assert_eq!(params.dig_h2, 362);
+        assert_eq!(params.dig_h4, 325);
+        assert_eq!(params.dig_h5, 0);
+        assert_eq!(params.dig_h6, 30);
+    }
+
+    #[test]
+    fn test_chip_detection() {
+        assert_eq!(ChipModel::from_chip_id(0x60), Some(ChipModel::BME280));
+        assert_eq!(ChipModel::from_chip_id(0x58), Some(ChipModel::BMP280));
+        assert_eq!(ChipModel::from_chip_id(0x55), None);
+    }
+
+    #[test]
+    fn test_sea_level_pressure() {
+        assert_eq!(get_sea_level_pressure(1013.25, 0.0), 1013.25);
+        assert!((get_sea_level_pressure(1000.0, 100.0) - 1011.94).abs() < 0.01);
+    }
+}
\ No newline at end of file
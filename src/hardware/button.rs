@@ -1,18 +1,22 @@
-use rppal::gpio::{ InputPin, Trigger, Level };
+use rppal::gpio::{ InputPin };
 use std::time::{ SystemTime };
 use crossbeam_channel::{ Sender };
 use chrono::{ DateTime };
 use chrono::offset::{ Utc };
 
-use super::events::{ Event, EventType, Payload };
+use super::events::{ Event, Payload };
 use crate::data::process::{ DataPoint, DaytimeData };
+use crate::data::qc::{ QcMask };
 
+// The button isn't on the current board, these come back once it's wired up again in main.rs
+#[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
 pub struct IncrementalPayload {
     presses_per_sec: f32,
     last_updated: SystemTime
 }
 
+#[allow(dead_code)]
 impl IncrementalPayload {
     pub fn new(presses_per_sec: f32, last_updated: SystemTime) -> Self {
         Self {
@@ -26,14 +30,15 @@ impl Payload for IncrementalPayload {
     fn send_message(&self) {
         let time: DateTime<Utc> = self.last_updated.into();
 
-        println!("Received Button Payload --- {} CPS, Last Updated: {}", self.presses_per_sec, time.format("%d/%m/%Y %T").to_string());
+        println!("Received Button Payload --- {} CPS, Last Updated: {}", self.presses_per_sec, time.format("%d/%m/%Y %T"));
     }
 
-    fn update_data_fields(&self, data: &mut DataPoint, daytime_info: &mut DaytimeData) {
+    fn update_data_fields(&self, _data: &mut DataPoint, _daytime_info: &mut DaytimeData, _rejected: &QcMask) {
         // data.update_message(format!("Received Button Payload --- {} CPS", self.presses_per_sec).to_string());
     }
 }
 
+#[allow(dead_code)]
 pub struct Button {
     pin: InputPin,
     sender: Sender<Event>,
@@ -43,6 +48,7 @@ pub struct Button {
     last_updated: SystemTime
 }
 
+#[allow(dead_code)]
 impl Button {
     pub fn new(pin: InputPin, sender: Sender<Event>, payload_sender: Sender<Box<dyn Payload>>) -> Self {
         Self {
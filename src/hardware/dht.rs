@@ -2,17 +2,27 @@
 // https://github.com/jackmead515/rust_dht11/blob/master/src/dht11.rs
 // https://github.com/RobTillaart/DHTstable/blob/master/DHTStable.cpp
 
-use rppal::gpio::{ IoPin, Mode, PullUpDown };
-use std::thread::{ sleep, spawn };
-use std::time::{ Duration, SystemTime };
+use rppal::gpio::{ IoPin, Mode, PullUpDown, Level };
+use std::thread::{ sleep };
+use std::ops::{ RangeInclusive };
+use std::collections::{ VecDeque };
+use std::time::{ Duration, Instant, SystemTime };
 use crossbeam_channel::{ Sender };
-use chrono::{ DateTime };
-use chrono::offset::{ Utc };
+use serde::{ Serialize, Deserialize };
 
-use crate::hardware::events::{ Event, EventType, Payload };
+use crate::config::{ DHTCalibration };
+use crate::hardware::events::{ Payload };
+use crate::hardware::status::{ SensorState, SensorStatusPayload };
 use crate::data::process::{ DataPoint, DaytimeData };
+use crate::data::qc::{ QcMask, Quantity, SourceReadings };
+use crate::units::{ celsius_to_fahrenheit };
 
 const MAX_CLOCKS: u32 = 32_000;
+const FRAME_BITS: usize = 40;
+const BIT_THRESHOLD_MICROS: u32 = 40;
+const RECENT_READS: usize = 50;
+
+pub const SENSOR_NAME: &str = "dht";
 
 #[derive(Debug, Clone, Copy)]
 pub struct DHTData {
@@ -39,7 +49,7 @@ impl DHTData {
     }
 
     pub fn get_temp_farenheit(&self) -> f32 {
-        self.get_temp_celsius() * 9.0 / 5.0 + 32.0
+        celsius_to_fahrenheit(self.get_temp_celsius())
     }
 
     pub fn get_humidity(&self) -> f32 {
@@ -49,10 +59,6 @@ impl DHTData {
     pub fn get_last_updated(&self) -> Option<SystemTime> {
         self.last_updated
     }
-
-    pub fn convert_temp_to_farenheit(temp_c: f32) -> f32 {
-        temp_c * 9.0 / 5.0 + 32.0
-    }
 }
 
 pub struct DHTPayload {
@@ -72,21 +78,146 @@ impl Payload for DHTPayload {
         // ...
     }
 
-    fn update_data_fields(&self, data: &mut DataPoint, daytime_info: &mut DaytimeData) {
-        daytime_info.temp_col_count += 1;
-        daytime_info.temp_total += self.data.temperature;
-        
-        if daytime_info.temp_lo < 0.0 || daytime_info.temp_lo > self.data.temperature {
-            daytime_info.temp_lo = self.data.temperature;
+    fn update_data_fields(&self, data: &mut DataPoint, _daytime_info: &mut DaytimeData, rejected: &QcMask) {
+        let previous = data.get_temp_data();
+        let temperature = if rejected.is_rejected(SENSOR_NAME, Quantity::Temperature) { previous.temperature } else { self.data.temperature };
+        let humidity = if rejected.is_rejected(SENSOR_NAME, Quantity::Humidity) { previous.humidity } else { self.data.humidity };
+
+        data.update_dht(DHTData::new(temperature, humidity, self.data.last_updated));
+        data.update_sensor_state(SENSOR_NAME, SensorState::Ok);
+    }
+
+    fn get_readings(&self) -> Vec<SourceReadings> {
+        vec![ SourceReadings::new(SENSOR_NAME, vec![ (Quantity::Temperature, self.data.temperature), (Quantity::Humidity, self.data.humidity) ]) ]
+    }
+}
+
+// Running read counts, the recent window makes it easier to tell a dying sensor from the odd bad frame
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DHTStats {
+    pub reads: u32,
+    pub successes: u32,
+    pub checksum_errors: u32,
+    pub timeouts: u32,
+    pub invalid_values: u32,
+    #[serde(skip)]
+    recent: VecDeque<bool>
+}
+
+impl DHTStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, result: &Result<(f32, f32), i32>) {
+        self.reads += 1;
+
+        match result {
+            Ok(_) => self.successes += 1,
+            Err(code) => match DHTState::get_state_from_code(*code) {
+                DHTState::ErrorChecksum => self.checksum_errors += 1,
+                DHTState::ErrorTimeout => self.timeouts += 1,
+                _ => self.invalid_values += 1
+            }
+        }
+
+        self.recent.push_back(result.is_ok());
+
+        if self.recent.len() > RECENT_READS {
+            self.recent.pop_front();
         }
+    }
 
-        if daytime_info.temp_hi < self.data.temperature {
-            daytime_info.temp_hi = self.data.temperature;
+    pub fn get_failures(&self) -> u32 {
+        self.reads - self.successes
+    }
+
+    // Percentage of all reads since startup that failed
+    pub fn get_error_rate(&self) -> f32 {
+        if self.reads == 0 { 0.0 } else { self.get_failures() as f32 / self.reads as f32 * 100.0 }
+    }
+
+    // Percentage of the last 50 reads that failed
+    pub fn get_recent_error_rate(&self) -> f32 {
+        if self.recent.is_empty() {
+            0.0
+        } else {
+            self.recent.iter().filter(|ok| !**ok).count() as f32 / self.recent.len() as f32 * 100.0
         }
+    }
+}
 
-        daytime_info.temp_avg = daytime_info.temp_total / daytime_info.temp_col_count as f32;
+pub struct DHTStatsPayload {
+    stats: DHTStats
+}
 
-        data.update_dht(self.data.clone())
+impl DHTStatsPayload {
+    pub fn new(stats: DHTStats) -> Self {
+        Self {
+            stats
+        }
+    }
+}
+
+impl Payload for DHTStatsPayload {
+    fn send_message(&self) {
+        // ...
+    }
+
+    fn update_data_fields(&self, data: &mut DataPoint, _daytime_info: &mut DaytimeData, _rejected: &QcMask) {
+        data.update_dht_stats(self.stats.clone());
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DHTModel {
+    DHT11,
+    DHT22,
+    AM2302
+}
+
+impl DHTModel {
+    // The host holds the line low this long to wake the sensor up
+    pub fn get_start_signal_duration(&self) -> Duration {
+        match self {
+            DHTModel::DHT11 => Duration::from_millis(20),
+            DHTModel::DHT22 | DHTModel::AM2302 => Duration::from_micros(1100)
+        }
+    }
+
+    // Reading any faster than this returns the previous measurement or garbage
+    pub fn get_min_read_interval(&self) -> Duration {
+        match self {
+            DHTModel::DHT11 => Duration::from_secs(1),
+            DHTModel::DHT22 | DHTModel::AM2302 => Duration::from_secs(2)
+        }
+    }
+
+    pub fn get_temp_range(&self) -> RangeInclusive<f32> {
+        match self {
+            DHTModel::DHT11 => -20.0..=60.0,
+            DHTModel::DHT22 | DHTModel::AM2302 => -40.0..=80.0
+        }
+    }
+
+    // Returns (temperature in °C, relative humidity in %), the sign of the temperature is the top bit
+    pub fn decode(&self, data: &[u8; 5]) -> (f32, f32) {
+        match self {
+            DHTModel::DHT11 => {
+                let humidity = data[0] as f32 + data[1] as f32 * 0.1;
+                let temp = data[2] as f32 + (data[3] & 0x0F) as f32 * 0.1;
+
+                (if data[3] & 0x80 > 0 { -temp } else { temp }, humidity)
+            },
+            DHTModel::DHT22 | DHTModel::AM2302 => {
+                let humidity = u16::from_be_bytes([ data[0], data[1] ]) as f32 * 0.1;
+                let temp = u16::from_be_bytes([ data[2] & 0x7F, data[3] ]) as f32 * 0.1;
+
+                (if data[2] & 0x80 > 0 { -temp } else { temp }, humidity)
+            }
+        }
     }
 }
 
@@ -118,183 +249,213 @@ impl DHTState {
     }
 }
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct DHT {
     pin: IoPin,
     humidity: f32,
     temp: f32,
-    event_sender: Sender<Event>,
     payload_sender: Sender<Box<dyn Payload>>,
+    model: DHTModel,
+    retries: u8,
+    calibration: DHTCalibration,
+    stats: DHTStats,
+    last_read: Option<Instant>,
     last_update: Option<SystemTime>
 }
 
 impl DHT {
-    pub fn new(pin: IoPin, event_sender: Sender<Event>, payload_sender: Sender<Box<dyn Payload>>) -> Self {
+    pub fn new(pin: IoPin, model: DHTModel, retries: u8, payload_sender: Sender<Box<dyn Payload>>, calibration: DHTCalibration) -> Self {
         Self {
             pin,
+            model,
+            retries,
             humidity: 0.0,
             temp: 0.0,
-            event_sender,
             payload_sender,
+            calibration,
+            stats: DHTStats::new(),
+            last_read: None,
             last_update: None
         }
     }
 
     pub fn update_data(&mut self) {
-        match self.update() {
+        let mut result = self.update();
+
+        for _ in 0..self.retries {
+            match result {
+                Err(code) => {
+                    println!("Failed to read from sensor, retrying! Code: {}", DHTState::get_state_str(DHTState::get_state_from_code(code)));
+
+                    result = self.update();
+                },
+                Ok(_) => break
+            }
+        }
+
+        self.payload_sender.send(Box::new(DHTStatsPayload::new(self.stats.clone()))).unwrap();
+
+        match result {
             Ok(_) => {
                 //println!("Temperature: {}°F ({}°C)", self.get_temp_farenheit(), self.get_temp_celsius());
                 //println!("Humidity: {}%", self.get_humidity());
 
                 self.last_update = Some(SystemTime::now());
 
-                self.payload_sender.send(Box::new(DHTPayload::new(self.temp, self.humidity, self.last_update))).unwrap();
+                let temp = self.calibration.apply_temp(self.temp);
+                let humidity = self.calibration.apply_humidity(self.humidity);
+
+                self.payload_sender.send(Box::new(DHTPayload::new(temp, humidity, self.last_update))).unwrap();
             }
             Err(code) => {
                 println!("Failed to read from sensor! Code: {}", DHTState::get_state_str(DHTState::get_state_from_code(code)));
+
+                self.payload_sender.send(Box::new(SensorStatusPayload::new(SENSOR_NAME, SensorState::Failing))).unwrap();
             }
         }
     }
 
     fn update(&mut self) -> Result<(), i32> {
-        let read_ret = self.read_sensor();
+        match self.read_sensor() {
+            Ok((temp, humidity)) => {
+                self.temp = temp;
+                self.humidity = humidity;
 
-        if read_ret != DHTState::Ok as i32 {
-            self.humidity = (DHTState::InvalidValue as i32) as f32;
-            self.temp = (DHTState::InvalidValue as i32) as f32;
-            return Err(read_ret)
+                Ok(())
+            },
+            Err(code) => {
+                self.humidity = (DHTState::InvalidValue as i32) as f32;
+                self.temp = (DHTState::InvalidValue as i32) as f32;
+
+                Err(code)
+            }
         }
+    }
 
-        Ok(())
+    fn read_sensor(&mut self) -> Result<(f32, f32), i32> {
+        self.wait_for_min_interval();
+
+        let result = self.read_pulses().and_then(|pulses| decode_frame(self.model, &pulses));
+
+        self.last_read = Some(Instant::now());
+        self.stats.record(&result);
+
+        result
+    }
+
+    // Spaces out reads (retries included) so the sensor has time to take a new measurement
+    fn wait_for_min_interval(&self) {
+        if let Some(last_read) = self.last_read {
+            let elapsed = last_read.elapsed();
+            let min_interval = self.model.get_min_read_interval();
+
+            if elapsed < min_interval {
+                sleep(min_interval - elapsed);
+            }
+        }
     }
 
-    fn read_sensor(&mut self) -> i32 {
-        let mut mask = 128u8;
-        let mut idx = 0;
+    // Captures the length of the high half of each of the 40 data bits, in microseconds
+    fn read_pulses(&mut self) -> Result<[u32; FRAME_BITS], i32> {
+        let mut pulses = [0u32; FRAME_BITS];
 
         // Startup
         self.pin.set_mode(Mode::Output);
         self.pin.set_high();
         sleep(Duration::from_millis(100));
         self.pin.set_low();
-        sleep(Duration::from_micros(1100));
+        sleep(self.model.get_start_signal_duration());
         self.pin.set_mode(Mode::Input);
         self.pin.set_pullupdown(PullUpDown::PullUp);
         sleep(Duration::from_micros(30));
 
-        const PULSES: usize = 41;
-        let mut data = [0u8; 5];
-
-        /*
-        let mut pulse_cnts = [0u32; PULSES*2];
-        
-        for i in (0..PULSES*2).step_by(2) {
-            while self.pin.is_low() {
-                pulse_cnts[i] += 1;
-                if pulse_cnts[i] >= MAX_CLOCKS {
-                    return DHTState::ErrorTimeout as i32; // Exceeded timeout, fail.
-                }
-            }
+        // Sensor response, ~80us low then ~80us high
+        self.wait_while_level(Level::Low)?;
+        self.wait_while_level(Level::High)?;
 
-            while self.pin.is_high() {
-                pulse_cnts[i+1] += 1;
-                if pulse_cnts[i+1] >= MAX_CLOCKS {
-                    return DHTState::ErrorTimeout as i32; // Exceeded timeout, fail.
-                }
-            }
-        }
+        for pulse in pulses.iter_mut() {
+            self.wait_while_level(Level::Low)?;
 
-        let mut threshold = 0;
-        for i in (2..PULSES).step_by(2) {
-            threshold += pulse_cnts[i];
-        }
-        threshold /= PULSES as u32 - 1;
+            let time = Instant::now();
 
-        for i in (3..PULSES * 2).step_by(2) {
-            let idx = (i - 3) / 16;
-            data[idx] <<= 1;
+            self.wait_while_level(Level::High)?;
 
-            if pulse_cnts[i] >= threshold {
-                data[idx] |= 1;
-            }
+            *pulse = time.elapsed().as_micros() as u32;
         }
-        */
 
+        Ok(pulses)
+    }
+
+    fn wait_while_level(&self, level: Level) -> Result<(), i32> {
         let mut count = MAX_CLOCKS;
-        while self.pin.is_low() {
-            count -= 1;
-            if count == 0 { return DHTState::ErrorTimeout as i32; }
-        }
 
-        count = MAX_CLOCKS;
-        while self.pin.is_high() {
+        while self.pin.read() == level {
             count -= 1;
-            if count == 0 { return DHTState::ErrorTimeout as i32; }
+            if count == 0 { return Err(DHTState::ErrorTimeout as i32); }
         }
 
-        for _ in 0..PULSES-1 {
-            count = MAX_CLOCKS;
-                while self.pin.is_low() {
-                count -= 1;
-                if count == 0 { return DHTState::ErrorTimeout as i32; }
-            }
-
-            let time = SystemTime::now();
+        Ok(())
+    }
+}
 
-            count = MAX_CLOCKS;
-            while self.pin.is_high() {
-                count -= 1;
-                if count == 0 { return DHTState::ErrorTimeout as i32; }
-            }
+// Turns high pulse lengths into the 5 frame bytes, a 0 bit is ~26-28us high and a 1 bit ~70us
+pub fn decode_bits(pulses: &[u32; FRAME_BITS]) -> [u8; 5] {
+    let mut data = [0u8; 5];
 
-            if time.elapsed().unwrap().as_micros() > 40 {
-                data[idx] |= mask;
-            }
+    for (i, pulse) in pulses.iter().enumerate() {
+        data[i / 8] <<= 1;
 
-            mask >>= 1;
-            if mask == 0 {
-                mask = 128;
-                idx += 1;
-            }
+        if *pulse > BIT_THRESHOLD_MICROS {
+            data[i / 8] |= 1;
         }
+    }
 
-        self.humidity = ((data[0] as i16) << 8 | (data[1] as i16)) as f32 * 0.1;
-        self.temp = ((data[2] as i16) * 256 + (data[3] as i16)) as f32 * 0.1;
+    data
+}
 
-        let mut checksum = 0u8;
+// The checksum is the low byte of the sum of the first four bytes, so it is allowed to overflow
+pub fn is_checksum_valid(data: &[u8; 5]) -> bool {
+    data[..4].iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == data[4]
+}
 
-        // println!("Data bits: {:?}, humidity: {:.2}, temp: {:.2}", data, self.humidity, self.temp);
+// Returns (temperature in °C, relative humidity in %)
+pub fn decode_frame(model: DHTModel, pulses: &[u32; FRAME_BITS]) -> Result<(f32, f32), i32> {
+    let data = decode_bits(pulses);
 
-        for i in 0..4 {
-            match checksum.checked_add(data[i]) {
-                Some(val) => checksum = val,
-                None => return DHTState::ErrorChecksum as i32
-            }
-        }
+    if !is_checksum_valid(&data) {
+        return Err(DHTState::ErrorChecksum as i32);
+    }
 
-        if checksum != data[4] {
-            return DHTState::ErrorChecksum as i32;
-        }
+    let (temp, humidity) = model.decode(&data);
 
-        return DHTState::Ok as i32;
+    if !(0.0..=100.0).contains(&humidity) || !model.get_temp_range().contains(&temp) {
+        return Err(DHTState::InvalidValue as i32);
     }
+
+    Ok((temp, humidity))
 }
 
 #[cfg(test)]
 mod test {
     use std::error::Error;
     use std::thread::sleep;
-    use std::time::Duration;
+    use std::time::{ Duration, SystemTime };
     use rppal::gpio::{ Gpio };
-    use crate::{ DHT, DHT_PIN, Mode };
-    use crate::hardware::dht::{ DHTState };
+    use crate::{ DHT, Mode };
+    use crate::config::{ DHTCalibration, Hardware, HealthConfig };
+    use crate::data::process::{ DataPoint, DaytimeData };
+    use crate::data::qc::{ QcMask, Quantity };
+    use crate::hardware::dht::{ DHTPayload, DHTState, DHTModel, DHTStats, decode_frame, is_checksum_valid };
+    use crate::hardware::events::{ Payload };
+    use crate::hardware::status::{ SensorState };
     use crossbeam_channel as channel;
 
     #[test]
     fn test_temp() -> Result<(), Box<dyn Error>> {
-        let (tx, _) = channel::unbounded();
         let (payload_tx, _) = channel::unbounded();
 
-        let mut dht_sensor = DHT::new(Gpio::new()?.get(DHT_PIN)?.into_io(Mode::Input), tx.clone(), payload_tx.clone());
+        let hardware = Hardware::default();
+        let mut dht_sensor = DHT::new(Gpio::new()?.get(hardware.dht.pin)?.into_io(Mode::Input), hardware.dht.model, 0, payload_tx.clone(), DHTCalibration::default());
 
         let mut success = 0;
 
@@ -315,4 +476,86 @@ mod test {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    // Recorded frame: 65.2%, -10.1°C, the checksum byte is 0x173 truncated to 0x73
+    const DHT22_FRAME: [u32; 40] = [
+        27, 24, 28, 22, 23, 30, 69, 27, 68, 30,
+        25, 22, 69, 74, 28, 23, 71, 23, 30, 28,
+        22, 23, 25, 22, 28, 68, 71, 22, 30, 70,
+        26, 74, 24, 76, 69, 72, 30, 24, 69, 71
+    ];
+
+    // Recorded frame: 45%, 23°C
+    const DHT11_FRAME: [u32; 40] = [
+        27, 23, 76, 23, 68, 71, 29, 76, 28, 27,
+        29, 29, 27, 26, 25, 24, 25, 23, 26, 76,
+        29, 73, 75, 72, 23, 23, 30, 28, 24, 27,
+        24, 29, 28, 68, 23, 30, 27, 73, 27, 29
+    ];
+
+    #[test]
+    fn test_decode_dht22_negative() {
+        let (temp, humidity) = decode_frame(DHTModel::DHT22, &DHT22_FRAME).unwrap();
+
+        assert!((temp + 10.1).abs() < 0.001, "Temperature: {}", temp);
+        assert!((humidity - 65.2).abs() < 0.001, "Humidity: {}", humidity);
+
+        assert_eq!(decode_frame(DHTModel::AM2302, &DHT22_FRAME), decode_frame(DHTModel::DHT22, &DHT22_FRAME));
+    }
+
+    #[test]
+    fn test_decode_dht11() {
+        assert_eq!(decode_frame(DHTModel::DHT11, &DHT11_FRAME), Ok((23.0, 45.0)));
+    }
+
+    #[test]
+    fn test_checksum() {
+        assert!(is_checksum_valid(&[ 0x02, 0x8C, 0x80, 0x65, 0x73 ]));
+        assert!(!is_checksum_valid(&[ 0x02, 0x8C, 0x80, 0x65, 0x74 ]));
+
+        let mut corrupted = DHT22_FRAME;
+        corrupted[39] = 25;
+
+        assert_eq!(decode_frame(DHTModel::DHT22, &corrupted), Err(DHTState::ErrorChecksum as i32));
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut stats = DHTStats::new();
+        assert_eq!(stats.get_error_rate(), 0.0);
+
+        stats.record(&Ok((20.0, 50.0)));
+        stats.record(&Err(DHTState::ErrorChecksum as i32));
+        stats.record(&Err(DHTState::ErrorTimeout as i32));
+        stats.record(&Ok((20.0, 50.0)));
+
+        assert_eq!((stats.reads, stats.successes, stats.checksum_errors, stats.timeouts), (4, 2, 1, 1));
+        assert_eq!(stats.get_error_rate(), 50.0);
+
+        // Only the last 50 reads count towards the recent rate
+        for _ in 0..50 {
+            stats.record(&Ok((20.0, 50.0)));
+        }
+
+        assert_eq!(stats.get_recent_error_rate(), 0.0);
+        assert!(stats.get_error_rate() > 0.0);
+    }
+
+    #[test]
+    fn test_masked_payload() {
+        let mut data = DataPoint::new();
+        let mut daytime = DaytimeData::new(None);
+
+        DHTPayload::new(20.0, 50.0, Some(SystemTime::now())).update_data_fields(&mut data, &mut daytime, &QcMask::new());
+
+        // A rejected temperature keeps the last accepted one, the humidity taken with it still goes through
+        let mut rejected = QcMask::new();
+        rejected.reject("dht", Quantity::Temperature);
+
+        DHTPayload::new(85.0, 55.0, Some(SystemTime::now())).update_data_fields(&mut data, &mut daytime, &rejected);
+
+        assert_eq!(data.get_temp_data().get_temp_celsius(), 20.0);
+        assert_eq!(data.get_temp_data().get_humidity(), 55.0);
+        assert_eq!(data.get_sensor_state("dht", &HealthConfig::default()), Some(SensorState::Ok));
+    }
+}
@@ -9,42 +9,55 @@ const CLEAR_DISPLAY: u8 = 0x01;
 const RETURN_HOME: u8 = 0x02;
 const ENTRY_MODE_SET: u8 = 0x04;
 const DISPLAY_CONTROL: u8 = 0x08;
-const CURSOR_SHIFT: u8 = 0x10;
 const FUNCTION_SET: u8 = 0x20;
-const SET_CGRAM_ADDR: u8 = 0x40;
 const SET_DDRAM_ADDR: u8 = 0x80;
 
 // Entry flags
-const ENTRY_RIGHT: u8 = 0x00;
 const ENTRY_LEFT: u8 = 0x02;
-const ENTRY_SHIFT_INCREMENT: u8 = 0x01;
 const ENTRY_SHIFT_DECREMENT: u8 = 0x00;
 
 // Control flags
 const DISPLAY_ON: u8 = 0x04;
-const DISPLAY_OFF: u8 = 0x00;
-const CURSOR_ON: u8 = 0x02;
 const CURSOR_OFF: u8 = 0x00;
-const BLINK_ON: u8 = 0x01;
 const BLINK_OFF: u8 = 0x00;
 
-// Move flags
-const DISPLAY_MOVE: u8 = 0x08;
-const CURSOR_MOVE: u8 = 0x00;
-const MOVE_RIGHT: u8 = 0x04;
-const MOVE_LEFT: u8 = 0x00;
-
 // Function set flags
-const EIGHT_BIT_MODE: u8 = 0x10;
 const FOUR_BIT_MODE: u8 = 0x00;
 const TWO_LINE: u8 = 0x08;
 const ONE_LINE: u8 = 0x00;
-const FIVE_BY_TEN_DOTS: u8 = 0x04;
 const FIVE_BY_EIGHT_DOTS: u8 = 0x00;
 
 // Row offsets
 const ROW_OFFSETS: [u8; 4] = [ 0x00, 0x40, 0x14, 0x54 ];
 
+// Word-wraps text onto the display's lines, dropping whatever doesn't fit
+pub fn wrap_text(text: &str, cols: usize, rows: usize) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+
+    for word in text.split_whitespace() {
+        if current.is_empty() {
+            current = word.to_string();
+        } else if current.chars().count() + 1 + word.chars().count() <= cols {
+            current.push(' ');
+            current.push_str(word);
+        } else {
+            lines.push(current);
+            current = word.to_string();
+        }
+    }
+
+    if !current.is_empty() {
+        lines.push(current);
+    }
+
+    lines.iter()
+        .take(rows)
+        .map(|line| line.chars().take(cols).collect::<String>())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
 pub struct LCDAsciiConverter {}
 
 // https://www.engineersgarage.com/wp-content/uploads/2/2/1/5/22159166/7547888_orig.jpg
@@ -74,8 +87,8 @@ impl LCDDisplay {
         let en = Gpio::new()?.get(en_pin)?.into_io(Mode::Output);
         let mut d_pins_vec = Vec::new();
 
-        for i in 0..4 {
-            let io_pin = Gpio::new()?.get(d_pins[i])?.into_io(Mode::Output);
+        for d_pin in d_pins.iter() {
+            let io_pin = Gpio::new()?.get(*d_pin)?.into_io(Mode::Output);
 
             d_pins_vec.push(io_pin);
         }
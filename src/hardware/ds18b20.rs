@@ -0,0 +1,280 @@
+// Maxim DS18B20 1-Wire temperature probes, read through the kernel's w1-gpio/w1-therm drivers
+// (dtoverlay=w1-gpio in /boot/config.txt) rather than bit-banging the bus
+
+use std::collections::{ BTreeMap, VecDeque };
+use std::error::Error;
+use std::fs::{ read_dir, read_to_string };
+use std::path::{ PathBuf };
+use std::time::{ Duration, SystemTime };
+use crossbeam_channel::{ Sender };
+use postgres::{ Client };
+
+use super::events::{ Payload };
+use super::status::{ SensorState, SensorStatusPayload };
+use crate::config::{ DS18B20Config, ProbeConfig };
+use crate::data::process::{ DataPoint, DaytimeData };
+use crate::data::qc::{ QcMask, Quantity, SourceReadings };
+use crate::data::types::{ ProbeTemperature };
+use crate::data::DatabaseType;
+use crate::units::{ celsius_to_fahrenheit };
+
+const FAMILY_CODE: &str = "28-";
+const SLAVE_FILE: &str = "w1_slave";
+
+// What the scratchpad holds after power-on, before any conversion has run
+const POWER_ON_RESET_MILLIDEGREES: i32 = 85000;
+
+const HISTORY_LENGTH: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub const SENSOR_NAME: &str = "ds18b20";
+
+// w1_slave holds the raw scratchpad twice, the first line ending in the CRC check and the second in the temperature:
+// 72 01 4b 46 7f ff 0e 10 57 : crc=57 YES
+// 72 01 4b 46 7f ff 0e 10 57 t=23125
+pub fn parse_w1_slave(contents: &str) -> Result<f32, String> {
+    let mut lines = contents.lines();
+
+    let crc_line = lines.next().ok_or("w1_slave is empty")?;
+
+    if !crc_line.trim_end().ends_with("YES") {
+        return Err("CRC check failed".to_string());
+    }
+
+    let millidegrees: i32 = lines.next()
+        .and_then(|line| line.split("t=").nth(1))
+        .ok_or("w1_slave has no temperature")?
+        .trim()
+        .parse()
+        .map_err(|e| format!("Invalid temperature: {}", e))?;
+
+    if millidegrees == POWER_ON_RESET_MILLIDEGREES {
+        return Err("Probe returned its power-on value, the conversion didn't run".to_string());
+    }
+
+    Ok(millidegrees as f32 / 1000.0)
+}
+
+// The sysfs tree is normally at /sys/bus/w1/devices, but can be pointed anywhere (e.g. a fake tree in tests)
+pub struct W1Bus {
+    root: PathBuf
+}
+
+impl W1Bus {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root
+        }
+    }
+
+    pub fn list_devices(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut devices: Vec<String> = read_dir(&self.root)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|name| name.starts_with(FAMILY_CODE))
+            .collect();
+
+        devices.sort();
+
+        Ok(devices)
+    }
+
+    pub fn read_temperature(&self, rom_id: &str) -> Result<f32, Box<dyn Error>> {
+        let contents = read_to_string(self.root.join(rom_id).join(SLAVE_FILE))?;
+
+        Ok(parse_w1_slave(&contents)?)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProbeReading {
+    pub rom_id: String,
+    pub temperature: f32,
+    pub last_updated: SystemTime
+}
+
+impl ProbeReading {
+    pub fn get_temp_farenheit(&self) -> f32 {
+        celsius_to_fahrenheit(self.temperature)
+    }
+}
+
+// The last day of readings for each probe, for graphing
+#[derive(Debug, Clone, Default)]
+pub struct ProbeHistory {
+    samples: BTreeMap<String, VecDeque<(SystemTime, f32)>>
+}
+
+impl ProbeHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_sample(&mut self, name: &str, time: SystemTime, temperature: f32) {
+        let samples = self.samples.entry(name.to_string()).or_default();
+
+        samples.push_back((time, temperature));
+
+        while let Some((oldest, _)) = samples.front() {
+            match time.duration_since(*oldest) {
+                Ok(age) if age > HISTORY_LENGTH => { samples.pop_front(); },
+                _ => break
+            }
+        }
+    }
+
+    pub fn get_samples(&self) -> &BTreeMap<String, VecDeque<(SystemTime, f32)>> {
+        &self.samples
+    }
+}
+
+pub struct ProbePayload {
+    readings: Vec<(String, ProbeReading)>
+}
+
+impl ProbePayload {
+    pub fn new(readings: Vec<(String, ProbeReading)>) -> Self {
+        Self {
+            readings
+        }
+    }
+}
+
+fn is_probe_rejected(rejected: &QcMask, name: &str) -> bool {
+    rejected.is_rejected(&format!("{}:{}", SENSOR_NAME, name), Quantity::Temperature)
+}
+
+impl Payload for ProbePayload {
+    fn send_message(&self) {
+        // ...
+    }
+
+    fn update_data_fields(&self, data: &mut DataPoint, _daytime_info: &mut DaytimeData, rejected: &QcMask) {
+        for (name, reading) in self.readings.iter().filter(|(name, _)| !is_probe_rejected(rejected, name)) {
+            data.update_probe(name.clone(), reading.clone());
+        }
+
+        data.update_sensor_state(SENSOR_NAME, SensorState::Ok);
+    }
+
+    // Each probe is its own source, named the way the temperature sources refer to them
+    fn get_readings(&self) -> Vec<SourceReadings> {
+        self.readings.iter()
+            .map(|(name, reading)| SourceReadings::new(&format!("{}:{}", SENSOR_NAME, name), vec![ (Quantity::Temperature, reading.temperature) ]))
+            .collect()
+    }
+
+    fn save_to_database(&self, client: &mut Client, rejected: &QcMask) {
+        for (name, reading) in self.readings.iter().filter(|(name, _)| !is_probe_rejected(rejected, name)) {
+            ProbeTemperature::new(reading.rom_id.clone(), name.clone(), reading.temperature, reading.last_updated.into()).insert(client);
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+pub struct DS18B20Probes {
+    bus: W1Bus,
+    probes: Vec<ProbeConfig>,
+    payload_sender: Sender<Box<dyn Payload>>
+}
+
+impl DS18B20Probes {
+    pub fn new(config: &DS18B20Config, payload_sender: Sender<Box<dyn Payload>>) -> Result<Self, Box<dyn Error>> {
+        let bus = W1Bus::new(PathBuf::from(&config.root));
+        let devices = bus.list_devices()?;
+
+        for probe in config.probes.iter().filter(|probe| !devices.contains(&probe.rom_id)) {
+            println!("DS18B20 probe \"{}\" ({}) was not found on the 1-Wire bus", probe.name, probe.rom_id);
+        }
+
+        // Makes it easy to find the ROM ID of a newly attached probe
+        for device in devices.iter().filter(|device| !config.probes.iter().any(|probe| probe.rom_id == **device)) {
+            println!("Found unconfigured DS18B20 probe {}", device);
+        }
+
+        Ok(Self {
+            bus,
+            probes: config.probes.clone(),
+            payload_sender
+        })
+    }
+
+    pub fn update_data(&mut self) {
+        let mut readings = Vec::new();
+        let mut failed = false;
+
+        for probe in self.probes.iter() {
+            match self.bus.read_temperature(&probe.rom_id) {
+                Ok(temperature) => {
+                    readings.push((probe.name.clone(), ProbeReading {
+                        rom_id: probe.rom_id.clone(),
+                        temperature,
+                        last_updated: SystemTime::now()
+                    }));
+                },
+                Err(e) => {
+                    println!("Failed to read DS18B20 probe \"{}\" ({})! Error: {}", probe.name, probe.rom_id, e);
+
+                    failed = true;
+                }
+            }
+        }
+
+        if !readings.is_empty() {
+            self.payload_sender.send(Box::new(ProbePayload::new(readings))).unwrap();
+        }
+
+        if failed {
+            self.payload_sender.send(Box::new(SensorStatusPayload::new(SENSOR_NAME, SensorState::Failing))).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{ create_dir_all, remove_dir_all, write };
+    use std::path::{ PathBuf };
+    use std::process;
+    use crate::hardware::ds18b20::{ W1Bus, parse_w1_slave };
+
+    const GOOD_READING: &str = "72 01 4b 46 7f ff 0e 10 57 : crc=57 YES\n72 01 4b 46 7f ff 0e 10 57 t=23125\n";
+    const NEGATIVE_READING: &str = "5e ff 4b 46 7f ff 02 10 d8 : crc=d8 YES\n5e ff 4b 46 7f ff 02 10 d8 t=-10125\n";
+    const BAD_CRC: &str = "72 01 4b 46 7f ff 0e 10 57 : crc=12 NO\n72 01 4b 46 7f ff 0e 10 57 t=23125\n";
+    const POWER_ON: &str = "50 05 4b 46 7f ff 0c 10 1c : crc=1c YES\n50 05 4b 46 7f ff 0c 10 1c t=85000\n";
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_w1_slave(GOOD_READING), Ok(23.125));
+        assert_eq!(parse_w1_slave(NEGATIVE_READING), Ok(-10.125));
+        assert!(parse_w1_slave(BAD_CRC).is_err());
+        assert!(parse_w1_slave(POWER_ON).is_err());
+        assert!(parse_w1_slave("").is_err());
+        assert!(parse_w1_slave("72 01 4b 46 7f ff 0e 10 57 : crc=57 YES\n").is_err());
+    }
+
+    #[test]
+    fn test_fake_sysfs_tree() {
+        let root = std::env::temp_dir().join(format!("w1-devices-{}", process::id()));
+
+        let make_device = |name: &str, contents: Option<&str>| {
+            let path: PathBuf = root.join(name);
+            create_dir_all(&path).unwrap();
+
+            if let Some(contents) = contents {
+                write(path.join("w1_slave"), contents).unwrap();
+            }
+        };
+
+        make_device("28-0316a2796bff", Some(GOOD_READING));
+        make_device("28-000005e2fdc3", Some(BAD_CRC));
+        make_device("w1_bus_master1", None);
+
+        let bus = W1Bus::new(root.clone());
+
+        assert_eq!(bus.list_devices().unwrap(), vec![ "28-000005e2fdc3", "28-0316a2796bff" ]);
+        assert_eq!(bus.read_temperature("28-0316a2796bff").unwrap(), 23.125);
+        assert!(bus.read_temperature("28-000005e2fdc3").is_err());
+        assert!(bus.read_temperature("28-ffffffffffff").is_err());
+
+        remove_dir_all(&root).unwrap();
+    }
+}
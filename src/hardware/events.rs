@@ -1,15 +1,23 @@
 use crate::data::process::{ DataPoint, DaytimeData };
+use crate::data::qc::{ QcMask, SourceReadings };
+use postgres::{ Client };
 
 #[derive(Debug, Clone, Copy)]
 pub enum EventType {
-    UpdateData,
     UpdateRain,
     UpdateWind,
     UpdateTemp,
+    UpdatePressure,
+    UpdateAnalog,
+    UpdateSolar,
+    UpdateParticulate,
+    UpdateProbes,
+    LightningInterrupt,
     MidnightRefresh,
+    ResetDaily,
+    ShowScreen(i32),
     AnemometerCount,
     RainCount,
-    UpdateDataCache,
     Exit
 }
 
@@ -31,25 +39,17 @@ impl Event {
 }
 
 pub trait Payload: Send {
+    #[allow(dead_code)]     // Not called anywhere, kept so a driver's output can be dumped while debugging it
     fn send_message(&self);
-    fn update_data_fields(&self, data: &mut DataPoint, daytime_info: &mut DaytimeData);
-}
-
-#[derive(Debug, Clone, Copy)]
-pub struct EmptyPayload {}
+    // Quantities in `rejected` failed quality control and keep their last accepted value, the rest is applied
+    fn update_data_fields(&self, data: &mut DataPoint, daytime_info: &mut DaytimeData, rejected: &QcMask);
 
-impl EmptyPayload {
-    pub fn new() -> Self {
-        Self {}
+    // Values the quality control stage checks before the payload is applied, payloads without fixed limits
+    // (analog channels, the pyranometer, rain and lightning) aren't checked
+    fn get_readings(&self) -> Vec<SourceReadings> {
+        Vec::new()
     }
-}
 
-impl Payload for EmptyPayload {
-    fn send_message(&self) {
-        println!("Printing empty payload!");
-    }
-
-    fn update_data_fields(&self, data: &mut DataPoint, daytime_info: &mut DaytimeData) {
-        // data.update_message("New message sent!".to_string());
-    }
+    // Persists the payload once it has been applied, most sensors aren't stored yet
+    fn save_to_database(&self, _client: &mut Client, _rejected: &QcMask) {}
 }
\ No newline at end of file
@@ -1,12 +1,16 @@
-#[allow(dead_code)]
 pub mod events;
-#[allow(dead_code)]
 pub mod display;
 pub mod dht;
 pub mod analog;
-#[allow(dead_code)]
-#[allow(unused_imports)]
+pub mod analog_sensor;
 pub mod button;
 pub mod anemometer;
 pub mod rain;
-pub mod vane;
\ No newline at end of file
+pub mod vane;
+pub mod status;
+pub mod bme280;
+pub mod veml6075;
+pub mod pyranometer;
+pub mod particulate;
+pub mod as3935;
+pub mod ds18b20;
\ No newline at end of file
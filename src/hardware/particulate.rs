@@ -0,0 +1,400 @@
+// Nova SDS011 and Plantower PMS5003 particulate matter sensors over UART (9600 8N1), frame layouts from the datasheets:
+// https://cdn-reichelt.de/documents/datenblatt/X200/SDS011-DATASHEET.pdf
+// https://www.aqmd.gov/docs/default-source/aq-spec/resources-page/plantower-pms5003-manual_v2-3.pdf
+
+use rppal::uart::{ Uart, Parity, Queue };
+use std::error::Error;
+use std::time::{ Duration, Instant, SystemTime };
+use crossbeam_channel::{ Sender };
+use serde::{ Deserialize };
+
+use super::events::{ Payload };
+use super::status::{ SensorState, SensorStatusPayload };
+use crate::config::{ ParticulateConfig };
+use crate::data::process::{ DataPoint, DaytimeData };
+use crate::data::qc::{ QcMask, Quantity, SourceReadings };
+
+const BAUD_RATE: u32 = 9600;
+const READ_BUFFER_SIZE: usize = 256;
+
+// The fan needs to run for a while before readings settle after waking up
+const WARMUP_PERIOD: Duration = Duration::from_secs(30);
+
+const SDS011_FRAME_LENGTH: usize = 10;
+const SDS011_HEADER: [u8; 2] = [ 0xAA, 0xC0 ];
+const SDS011_TAIL: u8 = 0xAB;
+const SDS011_COMMAND_LENGTH: usize = 19;
+const SDS011_SET_SLEEP: u8 = 0x06;
+
+const PMS5003_FRAME_LENGTH: usize = 32;
+const PMS5003_HEADER: [u8; 2] = [ 0x42, 0x4D ];
+const PMS5003_SET_SLEEP: u8 = 0xE4;
+
+pub const SENSOR_NAME: &str = "particulate";
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ParticulateModel {
+    SDS011,
+    PMS5003
+}
+
+impl ParticulateModel {
+    fn get_frame_length(&self) -> usize {
+        match self {
+            ParticulateModel::SDS011 => SDS011_FRAME_LENGTH,
+            ParticulateModel::PMS5003 => PMS5003_FRAME_LENGTH
+        }
+    }
+
+    fn get_header(&self) -> [u8; 2] {
+        match self {
+            ParticulateModel::SDS011 => SDS011_HEADER,
+            ParticulateModel::PMS5003 => PMS5003_HEADER
+        }
+    }
+
+    // The command that puts the sensor (and its fan) to sleep or wakes it back up
+    pub fn get_sleep_command(&self, sleep: bool) -> Vec<u8> {
+        match self {
+            ParticulateModel::SDS011 => {
+                let mut command = vec![ 0u8; SDS011_COMMAND_LENGTH ];
+
+                command[0] = 0xAA;
+                command[1] = 0xB4;
+                command[2] = SDS011_SET_SLEEP;
+                command[3] = 0x01;                                      // Set rather than query
+                command[4] = if sleep { 0x00 } else { 0x01 };
+                command[15] = 0xFF;                                     // Any device ID
+                command[16] = 0xFF;
+                command[17] = command[2..17].iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+                command[18] = SDS011_TAIL;
+
+                command
+            },
+            ParticulateModel::PMS5003 => {
+                let mut command = vec![ PMS5003_HEADER[0], PMS5003_HEADER[1], PMS5003_SET_SLEEP, 0x00, if sleep { 0x00 } else { 0x01 } ];
+                let checksum = command.iter().map(|byte| *byte as u16).sum::<u16>();
+
+                command.extend_from_slice(&checksum.to_be_bytes());
+
+                command
+            }
+        }
+    }
+
+    fn parse_frame(&self, frame: &[u8]) -> Option<ParticulateReading> {
+        match self {
+            ParticulateModel::SDS011 => {
+                let checksum = frame[2..8].iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+
+                if frame[8] != checksum || frame[9] != SDS011_TAIL {
+                    return None;
+                }
+
+                Some(ParticulateReading {
+                    pm1_0: None,
+                    pm2_5: u16::from_le_bytes([ frame[2], frame[3] ]) as f32 / 10.0,
+                    pm10: u16::from_le_bytes([ frame[4], frame[5] ]) as f32 / 10.0
+                })
+            },
+            ParticulateModel::PMS5003 => {
+                let word = |index: usize| u16::from_be_bytes([ frame[index], frame[index + 1] ]);
+
+                let checksum = frame[..30].iter().map(|byte| *byte as u16).fold(0u16, |sum, byte| sum.wrapping_add(byte));
+
+                if word(2) != 28 || word(30) != checksum {
+                    return None;
+                }
+
+                // Atmospheric environment concentrations, rather than the CF=1 "standard particle" ones
+                Some(ParticulateReading {
+                    pm1_0: Some(word(10) as f32),
+                    pm2_5: word(12) as f32,
+                    pm10: word(14) as f32
+                })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParticulateReading {
+    pub pm1_0: Option<f32>,         // µg/m³, the SDS011 doesn't measure PM1.0
+    pub pm2_5: f32,
+    pub pm10: f32
+}
+
+// Pulls every complete, valid frame out of the buffer. Returns the readings and how many bytes were consumed,
+// anything after that is the start of a frame that hasn't fully arrived yet
+pub fn parse_frames(model: ParticulateModel, bytes: &[u8]) -> (Vec<ParticulateReading>, usize) {
+    let header = model.get_header();
+    let frame_length = model.get_frame_length();
+
+    let mut readings = Vec::new();
+    let mut position = 0;
+
+    while position + 1 < bytes.len() {
+        if bytes[position..position + 2] != header {
+            position += 1;
+            continue;
+        }
+
+        if position + frame_length > bytes.len() {
+            break;
+        }
+
+        match model.parse_frame(&bytes[position..position + frame_length]) {
+            Some(reading) => {
+                readings.push(reading);
+                position += frame_length;
+            },
+            // Could be a header lookalike inside a frame, so resync one byte on
+            None => position += 1
+        }
+    }
+
+    if position + 1 == bytes.len() && bytes[position] != header[0] {
+        position += 1;
+    }
+
+    (readings, position)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ParticulateData {
+    reading: ParticulateReading,
+    last_updated: Option<SystemTime>
+}
+
+impl ParticulateData {
+    pub fn new(reading: ParticulateReading, last_updated: Option<SystemTime>) -> Self {
+        Self {
+            reading,
+            last_updated
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.last_updated.is_some()
+    }
+
+    pub fn get_last_updated(&self) -> Option<SystemTime> {
+        self.last_updated
+    }
+
+    pub fn get_pm1_0(&self) -> Option<f32> {
+        self.reading.pm1_0
+    }
+
+    pub fn get_pm2_5(&self) -> f32 {
+        self.reading.pm2_5
+    }
+
+    pub fn get_pm10(&self) -> f32 {
+        self.reading.pm10
+    }
+}
+
+pub struct ParticulatePayload {
+    data: ParticulateData
+}
+
+impl ParticulatePayload {
+    pub fn new(data: ParticulateData) -> Self {
+        Self {
+            data
+        }
+    }
+}
+
+impl Payload for ParticulatePayload {
+    fn send_message(&self) {
+        // ...
+    }
+
+    fn update_data_fields(&self, data: &mut DataPoint, _daytime_info: &mut DaytimeData, rejected: &QcMask) {
+        let previous = data.get_particulate_data().reading;
+        let pm2_5_rejected = rejected.is_rejected(SENSOR_NAME, Quantity::Pm2_5);
+        let pm10_rejected = rejected.is_rejected(SENSOR_NAME, Quantity::Pm10);
+        let reading = ParticulateReading {
+            pm2_5: if pm2_5_rejected { previous.pm2_5 } else { self.data.reading.pm2_5 },
+            pm10: if pm10_rejected { previous.pm10 } else { self.data.reading.pm10 },
+            ..self.data.reading
+        };
+
+        data.update_particulate(ParticulateData::new(reading, self.data.last_updated));
+
+        // The averages pair both sizes per sample, a half-rejected sample would skew one of them
+        if !pm2_5_rejected && !pm10_rejected {
+            data.add_particulate_sample(self.data.get_last_updated().unwrap(), self.data.get_pm2_5(), self.data.get_pm10());
+        }
+
+        data.update_sensor_state(SENSOR_NAME, SensorState::Ok);
+    }
+
+    fn get_readings(&self) -> Vec<SourceReadings> {
+        vec![ SourceReadings::new(SENSOR_NAME, vec![ (Quantity::Pm2_5, self.data.get_pm2_5()), (Quantity::Pm10, self.data.get_pm10()) ]) ]
+    }
+}
+
+pub struct ParticulateSensor {
+    uart: Uart,
+    config: ParticulateConfig,
+    buffer: Vec<u8>,
+    awake_since: Option<Instant>,
+    asleep_since: Option<Instant>,
+    payload_sender: Sender<Box<dyn Payload>>
+}
+
+impl ParticulateSensor {
+    pub fn new(config: ParticulateConfig, payload_sender: Sender<Box<dyn Payload>>) -> Result<Self, Box<dyn Error>> {
+        let mut uart = Uart::with_path(&config.port, BAUD_RATE, Parity::None, 8, 1)?;
+        uart.set_read_mode(0, Duration::default())?;
+
+        let mut sensor = Self {
+            uart,
+            config,
+            buffer: Vec::new(),
+            awake_since: None,
+            asleep_since: None,
+            payload_sender
+        };
+
+        sensor.set_sleep(false)?;
+        sensor.awake_since = Some(Instant::now());
+
+        Ok(sensor)
+    }
+
+    fn set_sleep(&mut self, sleep: bool) -> Result<(), Box<dyn Error>> {
+        self.uart.write(&self.config.model.get_sleep_command(sleep))?;
+
+        Ok(())
+    }
+
+    // Called on the particulate schedule. With a work period set, the sensor is woken once per period, given time to
+    // warm up, read and put back to sleep to spare the fan and laser
+    pub fn update_data(&mut self) {
+        if let Err(e) = self.update() {
+            println!("Failed to read from {:?}! Error: {}", self.config.model, e);
+
+            self.payload_sender.send(Box::new(SensorStatusPayload::new(SENSOR_NAME, SensorState::Failing))).unwrap();
+        }
+    }
+
+    fn update(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.config.work_period_mins == 0 {
+            return self.read_and_send();
+        }
+
+        match (self.awake_since, self.asleep_since) {
+            (Some(awake_since), _) if awake_since.elapsed() >= WARMUP_PERIOD => {
+                self.read_and_send()?;
+                self.set_sleep(true)?;
+
+                self.awake_since = None;
+                self.asleep_since = Some(Instant::now());
+            },
+            (Some(_), _) => {
+                // Still warming up, so throw away what's arrived
+                self.uart.flush(Queue::Input)?;
+            },
+            (None, Some(asleep_since)) if asleep_since.elapsed() >= Duration::from_secs(self.config.work_period_mins as u64 * 60) => {
+                self.set_sleep(false)?;
+
+                self.awake_since = Some(Instant::now());
+                self.asleep_since = None;
+            },
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn read_and_send(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut read_buf = [0u8; READ_BUFFER_SIZE];
+
+        loop {
+            let bytes_read = self.uart.read(&mut read_buf)?;
+
+            if bytes_read == 0 {
+                break;
+            }
+
+            self.buffer.extend_from_slice(&read_buf[..bytes_read]);
+        }
+
+        let (readings, consumed) = parse_frames(self.config.model, &self.buffer);
+        self.buffer.drain(..consumed);
+
+        // Only the newest reading matters, the sensors report about once a second
+        if let Some(reading) = readings.last() {
+            self.payload_sender.send(Box::new(ParticulatePayload::new(ParticulateData::new(*reading, Some(SystemTime::now()))))).unwrap();
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hardware::particulate::{ ParticulateModel, ParticulateReading, parse_frames };
+
+    // PM2.5 12.3, PM10 45.6, device ID 0x60A1
+    const SDS011_FRAME: [u8; 10] = [ 0xAA, 0xC0, 0x7B, 0x00, 0xC8, 0x01, 0xA1, 0x60, 0x45, 0xAB ];
+
+    // Atmospheric PM1.0 5, PM2.5 8, PM10 10
+    const PMS5003_FRAME: [u8; 32] = [
+        0x42, 0x4D, 0x00, 0x1C, 0x00, 0x05, 0x00, 0x08, 0x00, 0x0A, 0x00, 0x05, 0x00, 0x08, 0x00, 0x0A,
+        0x03, 0xE8, 0x01, 0x2C, 0x00, 0x32, 0x00, 0x0A, 0x00, 0x02, 0x00, 0x01, 0x97, 0x00, 0x02, 0xC7
+    ];
+
+    #[test]
+    fn test_sds011_frame() {
+        let (readings, consumed) = parse_frames(ParticulateModel::SDS011, &SDS011_FRAME);
+
+        assert_eq!(readings, vec![ ParticulateReading { pm1_0: None, pm2_5: 12.3, pm10: 45.6 } ]);
+        assert_eq!(consumed, SDS011_FRAME.len());
+
+        let mut corrupted = SDS011_FRAME;
+        corrupted[8] = 0x46;
+
+        assert!(parse_frames(ParticulateModel::SDS011, &corrupted).0.is_empty());
+    }
+
+    #[test]
+    fn test_pms5003_frame() {
+        let (readings, _) = parse_frames(ParticulateModel::PMS5003, &PMS5003_FRAME);
+
+        assert_eq!(readings, vec![ ParticulateReading { pm1_0: Some(5.0), pm2_5: 8.0, pm10: 10.0 } ]);
+
+        let mut corrupted = PMS5003_FRAME;
+        corrupted[12] = 0x01;
+
+        assert!(parse_frames(ParticulateModel::PMS5003, &corrupted).0.is_empty());
+    }
+
+    #[test]
+    fn test_stream_resync() {
+        // Noise, a full frame, then the first half of the next one
+        let mut stream = vec![ 0x00, 0xAB, 0xAA ];
+        stream.extend_from_slice(&SDS011_FRAME);
+        stream.extend_from_slice(&SDS011_FRAME[..4]);
+
+        let (readings, consumed) = parse_frames(ParticulateModel::SDS011, &stream);
+
+        assert_eq!(readings.len(), 1);
+        assert_eq!(&stream[consumed..], &SDS011_FRAME[..4]);
+    }
+
+    #[test]
+    fn test_sleep_commands() {
+        assert_eq!(ParticulateModel::SDS011.get_sleep_command(true), vec![
+            0xAA, 0xB4, 0x06, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x05, 0xAB
+        ]);
+
+        assert_eq!(ParticulateModel::PMS5003.get_sleep_command(false), vec![ 0x42, 0x4D, 0xE4, 0x00, 0x01, 0x01, 0x74 ]);
+    }
+}
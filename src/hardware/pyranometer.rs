@@ -0,0 +1,100 @@
+use std::time::{ SystemTime };
+use crossbeam_channel::{ Sender };
+
+use super::events::{ Payload };
+use super::analog::{ MCP3008 };
+use super::analog_sensor::{ TransferFunction };
+use super::status::{ SensorState, SensorStatusPayload };
+use crate::data::process::{ DataPoint, DaytimeData };
+use crate::data::qc::{ QcMask };
+
+pub const SENSOR_NAME: &str = "pyranometer";
+
+#[derive(Debug, Clone, Copy)]
+pub struct SolarData {
+    radiation: f32,                         // W/m²
+    last_updated: Option<SystemTime>
+}
+
+impl SolarData {
+    pub fn new(radiation: f32, last_updated: Option<SystemTime>) -> Self {
+        Self {
+            radiation,
+            last_updated
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.last_updated.is_some()
+    }
+
+    pub fn get_last_updated(&self) -> Option<SystemTime> {
+        self.last_updated
+    }
+
+    pub fn get_radiation(&self) -> f32 {
+        self.radiation
+    }
+}
+
+pub struct SolarRadiationPayload {
+    data: SolarData
+}
+
+impl SolarRadiationPayload {
+    pub fn new(data: SolarData) -> Self {
+        Self {
+            data
+        }
+    }
+}
+
+impl Payload for SolarRadiationPayload {
+    fn send_message(&self) {
+        // ...
+    }
+
+    fn update_data_fields(&self, data: &mut DataPoint, daytime_info: &mut DaytimeData, _rejected: &QcMask) {
+        if daytime_info.solar_max < self.data.radiation {
+            daytime_info.solar_max = self.data.radiation;
+        }
+
+        data.update_solar(self.data);
+        data.update_sensor_state(SENSOR_NAME, SensorState::Ok);
+    }
+}
+
+// Thermopile or photodiode pyranometer, the transfer function maps the (amplified) output voltage to W/m²
+pub struct Pyranometer {
+    mcp: MCP3008,
+    channel: u8,
+    transfer: TransferFunction,
+    payload_sender: Sender<Box<dyn Payload>>
+}
+
+impl Pyranometer {
+    pub fn new(mcp: MCP3008, channel: u8, transfer: TransferFunction, payload_sender: Sender<Box<dyn Payload>>) -> Self {
+        Self {
+            mcp,
+            channel,
+            transfer,
+            payload_sender
+        }
+    }
+
+    pub fn update_data(&mut self) {
+        match self.mcp.read_voltage(self.channel) {
+            Ok(voltage) => {
+                // Offsets can read slightly negative at night
+                let radiation = self.transfer.apply(voltage).max(0.0);
+
+                self.payload_sender.send(Box::new(SolarRadiationPayload::new(SolarData::new(radiation, Some(SystemTime::now()))))).unwrap();
+            },
+            Err(e) => {
+                println!("Failed to read from pyranometer! Error: {}", e);
+
+                self.payload_sender.send(Box::new(SensorStatusPayload::new(SENSOR_NAME, SensorState::Failing))).unwrap();
+            }
+        }
+    }
+}
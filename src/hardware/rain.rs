@@ -1,31 +1,44 @@
-use rppal::gpio::{ InputPin, Trigger };
+use rppal::gpio::{ InputPin, Trigger, Result };
 use std::time::{ SystemTime };
 use crossbeam_channel::{ Sender };
-use job_scheduler::{ Job };
 
 use super::events::{ Payload, Event, EventType };
+use super::status::{ SensorState };
+use crate::config::{ RainCalibration };
 use crate::data::process::{ DataPoint, DaytimeData };
+use crate::data::qc::{ QcMask };
+use crate::data::{ DatabaseType };
+use crate::data::types::{ Rain };
+use postgres::{ Client };
+use crate::units::{ mm_to_in };
 
-const COUNT_TO_MM: f32 = 0.2794;
 const CM_TO_MM: f32 = 10.0;
-const CM_TO_IN: f32 = 2.54;
+const SEC_TO_HR: f32 = 3600.0;
+
+pub const SENSOR_NAME: &str = "rain";
 
 #[derive(Debug, Clone, Copy)]
 pub struct RainData {
     total_ticks: u32,
     ticks_per_sec: f32,
+    calibration: RainCalibration,
     last_updated: Option<SystemTime>
 }
 
 impl RainData {
-    pub fn new(total_ticks: u32, ticks_per_sec: f32, last_updated: Option<SystemTime>) -> Self {
+    pub fn new(total_ticks: u32, ticks_per_sec: f32, calibration: RainCalibration, last_updated: Option<SystemTime>) -> Self {
         Self {
             total_ticks,
             ticks_per_sec,
+            calibration,
             last_updated
         }
     }
 
+    fn get_mm_per_tick(&self) -> f32 {
+        self.calibration.bucket_size_mm * self.calibration.multiplier
+    }
+
     pub fn is_valid(&self) -> bool {
         self.last_updated.is_some()
     }
@@ -34,28 +47,24 @@ impl RainData {
         self.last_updated
     }
 
-    pub fn get_amount_cm(&self) -> f32 {
-        (self.ticks_per_sec / COUNT_TO_MM) / CM_TO_MM
+    pub fn get_amount_mm(&self) -> f32 {
+        self.ticks_per_sec * self.get_mm_per_tick()
     }
 
-    pub fn get_amount_in(&self) -> f32 {
-        self.get_amount_cm() / CM_TO_IN
-    }
-
-    pub fn count_to_cm(&self) -> f32 {
-        (self.total_ticks as f32 / COUNT_TO_MM) / CM_TO_MM 
+    pub fn get_amount_cm(&self) -> f32 {
+        self.get_amount_mm() / CM_TO_MM
     }
 
-    pub fn count_to_in(&self) -> f32 {
-        self.count_to_cm() / CM_TO_IN
+    pub fn get_amount_in(&self) -> f32 {
+        mm_to_in(self.get_amount_mm())
     }
 
-    pub fn convert_to_cm(count: u32) -> f32 {
-        (count as f32 / COUNT_TO_MM) / CM_TO_MM
+    pub fn get_rate_mm_per_hour(&self) -> f32 {
+        self.get_amount_mm() * SEC_TO_HR
     }
 
-    pub fn convert_to_in(count: u32) -> f32 {
-        Self::convert_to_cm(count) / CM_TO_IN
+    pub fn count_to_mm(&self) -> f32 {
+        self.total_ticks as f32 * self.get_mm_per_tick()
     }
 }
 
@@ -64,9 +73,9 @@ pub struct RainPayload {
 }
 
 impl RainPayload {
-    pub fn new(total_ticks: u32, ticks_per_sec: f32, last_updated: Option<SystemTime>) -> Self {
+    pub fn new(total_ticks: u32, ticks_per_sec: f32, calibration: RainCalibration, last_updated: Option<SystemTime>) -> Self {
         Self {
-            data: RainData::new(total_ticks, ticks_per_sec, last_updated)
+            data: RainData::new(total_ticks, ticks_per_sec, calibration, last_updated)
         }
     }
 }
@@ -76,9 +85,17 @@ impl Payload for RainPayload {
         // ...
     }
 
-    fn update_data_fields(&self, data: &mut DataPoint, daytime_info: &mut DaytimeData) {
-        daytime_info.rain_total += self.data.total_ticks;
-        data.update_rain(self.data.clone());
+    fn update_data_fields(&self, data: &mut DataPoint, daytime_info: &mut DaytimeData, _rejected: &QcMask) {
+        daytime_info.rain_total += self.data.count_to_mm();
+        data.update_rain(self.data);
+        data.update_sensor_state(SENSOR_NAME, SensorState::Ok);
+    }
+
+    // One row per update that actually saw the bucket tip
+    fn save_to_database(&self, client: &mut Client, _rejected: &QcMask) {
+        if let (Some(last_updated), true) = (self.data.last_updated, self.data.total_ticks > 0) {
+            Rain::new(last_updated.into(), self.data.total_ticks).insert(client);
+        }
     }
 }
 
@@ -88,27 +105,29 @@ pub struct RainMeter {
     payload_sender: Sender<Box<dyn Payload>>,
     counter: i32,
     ticks_per_sec: f32,
+    calibration: RainCalibration,
     last_updated: SystemTime
 }
 
 impl RainMeter {
-    pub fn new(pin: InputPin, sender: Sender<Event>, payload_sender: Sender<Box<dyn Payload>>) -> Self {
+    pub fn new(pin: InputPin, sender: Sender<Event>, payload_sender: Sender<Box<dyn Payload>>, calibration: RainCalibration) -> Self {
         Self {
             pin,
             sender,
             payload_sender,
             counter: 0,
             ticks_per_sec: 0.0,
+            calibration,
             last_updated: SystemTime::now()
         }
     }
 
-    pub fn start(&mut self) {
+    pub fn start(&mut self) -> Result<()> {
         let copy_sender = self.sender.clone();
 
         self.pin.set_async_interrupt(Trigger::RisingEdge, move |_| {
             copy_sender.send(Event::new(EventType::RainCount)).unwrap();
-        }).unwrap();
+        })
     }
 
     pub fn increment_counter(&mut self) {
@@ -123,6 +142,32 @@ impl RainMeter {
         self.counter = 0;
         self.last_updated = SystemTime::now();
 
-        self.payload_sender.send(Box::new(RainPayload::new(total_count, self.ticks_per_sec, Some(self.last_updated)))).unwrap();
+        self.payload_sender.send(Box::new(RainPayload::new(total_count, self.ticks_per_sec, self.calibration, Some(self.last_updated)))).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::{ RainCalibration };
+    use crate::hardware::rain::{ RainData };
+
+    #[test]
+    fn test_calibrated_amount() {
+        let data = RainData::new(10, 0.01, RainCalibration::default(), None);
+
+        // Each tip of the stock bucket is 0.2794mm
+        assert!((data.count_to_mm() - 2.794).abs() < 0.0001);
+        assert!((data.get_amount_mm() - 0.002794).abs() < 0.000001);
+        assert!((data.get_amount_cm() - 0.0002794).abs() < 0.0000001);
+        assert!((data.get_rate_mm_per_hour() - 10.0584).abs() < 0.001);
+
+        let calibration = RainCalibration { multiplier: 1.5, bucket_size_mm: 0.5 };
+        let data = RainData::new(4, 0.0, calibration, None);
+
+        assert!((data.count_to_mm() - 3.0).abs() < 0.0001);
+        assert_eq!(data.get_amount_mm(), 0.0);
+
+        let data = RainData::new(0, 1.0, RainCalibration { multiplier: 1.0, bucket_size_mm: 25.4 }, None);
+        assert!((data.get_amount_in() - 1.0).abs() < 0.0001);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,101 @@
+use std::error::Error;
+use std::time::{ Duration, SystemTime };
+use crossbeam_channel::{ Sender };
+use serde::{ Serialize };
+
+use super::events::{ Payload };
+use crate::data::process::{ DataPoint, DaytimeData };
+use crate::data::qc::{ QcMask };
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SensorState {
+    Ok,
+    Disabled,
+    Absent,
+    Failing,
+    Stale
+}
+
+impl SensorState {
+    // A sensor that's up but hasn't been heard from within its expected interval is stale
+    pub fn with_staleness(self, last_seen: Option<SystemTime>, stale_after: Option<Duration>, now: SystemTime) -> Self {
+        match (self, last_seen, stale_after) {
+            (SensorState::Ok, Some(last_seen), Some(stale_after)) if now.duration_since(last_seen).unwrap_or_default() > stale_after => SensorState::Stale,
+            (state, _, _) => state
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        matches!(self, SensorState::Ok | SensorState::Disabled)
+    }
+}
+
+pub struct SensorStatusPayload {
+    sensor: &'static str,
+    state: SensorState
+}
+
+impl SensorStatusPayload {
+    pub fn new(sensor: &'static str, state: SensorState) -> Self {
+        Self {
+            sensor,
+            state
+        }
+    }
+}
+
+impl Payload for SensorStatusPayload {
+    fn send_message(&self) {
+        // ...
+    }
+
+    fn update_data_fields(&self, data: &mut DataPoint, _daytime_info: &mut DaytimeData, _rejected: &QcMask) {
+        data.update_sensor_state(self.sensor, self.state);
+    }
+}
+
+// Builds a sensor driver if it's enabled, reporting whether it came up so the station can run with whatever is attached
+pub fn init_sensor<T, F>(sensor: &'static str, enabled: bool, payload_sender: &Sender<Box<dyn Payload>>, init: F) -> Option<T>
+where
+    F: FnOnce() -> Result<T, Box<dyn Error>>
+{
+    let (driver, state) = if !enabled {
+        (None, SensorState::Disabled)
+    } else {
+        match init() {
+            Ok(driver) => (Some(driver), SensorState::Ok),
+            Err(e) => {
+                println!("Sensor \"{}\" is unavailable: {}", sensor, e);
+
+                (None, SensorState::Absent)
+            }
+        }
+    };
+
+    payload_sender.send(Box::new(SensorStatusPayload::new(sensor, state))).unwrap();
+
+    driver
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{ Duration, SystemTime };
+    use crate::hardware::status::{ SensorState };
+
+    #[test]
+    fn test_staleness() {
+        let now = SystemTime::now();
+        let stale_after = Some(Duration::from_secs(300));
+
+        assert_eq!(SensorState::Ok.with_staleness(Some(now - Duration::from_secs(60)), stale_after, now), SensorState::Ok);
+        assert_eq!(SensorState::Ok.with_staleness(Some(now - Duration::from_secs(600)), stale_after, now), SensorState::Stale);
+
+        // Sensors without an expected interval never go stale, and other states are left as they are
+        assert_eq!(SensorState::Ok.with_staleness(Some(now - Duration::from_secs(600)), None, now), SensorState::Ok);
+        assert_eq!(SensorState::Failing.with_staleness(Some(now - Duration::from_secs(600)), stale_after, now), SensorState::Failing);
+
+        assert!(!SensorState::Stale.is_healthy());
+        assert!(SensorState::Disabled.is_healthy());
+    }
+}
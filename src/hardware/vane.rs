@@ -1,14 +1,19 @@
-use std::time::{ SystemTime };
+use std::error::Error;
+use std::io::{ stdin, stdout, Write };
+use std::thread::{ sleep };
+use std::time::{ Duration, SystemTime };
 use crossbeam_channel::{ Sender };
 
 use super::events::{ Payload };
-use super::analog::{ MCP3008 };
+use super::analog::{ MCP3008, REFERENCE_VOLTAGE };
+use super::status::{ SensorState, SensorStatusPayload };
+use crate::config::{ VaneCalibration };
 use crate::data::process::{ DataPoint, DaytimeData };
+use crate::data::qc::{ QcMask, Quantity, SourceReadings };
 
-const BUFFER_SIZE: usize = 16;
-const INPUT_VOLTAGE: f32 = 3.3;
-const OUTPUT_RESISTANCE: u32 = 5100;
-const VANE_CALIBRATION_AMOUNT: f32 = 0.0;
+const CALIBRATION_SAMPLES: u32 = 10;
+
+pub const SENSOR_NAME: &str = "vane";
 
 const RESISTANCES: [u32; 16] = [
     33000, 6570, 8200, 891,
@@ -24,12 +29,25 @@ const DIRECTIONS: [&str; 16] = [
     "W", "WNW", "NW", "NNW"
 ];
 
-fn find_direction_by_voltage(voltage: f32) -> f32 {
+fn get_expected_voltages(calibration: &VaneCalibration) -> [f32; 16] {
+    if let Some(voltages) = calibration.voltages {
+        return voltages;
+    }
+
+    let mut voltages = [0.0f32; 16];
+
+    for (i, resistance) in RESISTANCES.iter().enumerate() {
+        voltages[i] = (REFERENCE_VOLTAGE * *resistance as f32) / (resistance + calibration.output_resistance) as f32;       // Series resistance formula
+    }
+
+    voltages
+}
+
+fn find_sector_by_voltage(voltage: f32, expected_voltages: &[f32; 16]) -> usize {
     let mut idx = 0;
     let mut min_diff = 999.0;
 
-    for i in 0..16 {
-        let calc_voltage = (INPUT_VOLTAGE * RESISTANCES[i] as f32) / (RESISTANCES[i] + OUTPUT_RESISTANCE) as f32;       // Series resistance formula
+    for (i, calc_voltage) in expected_voltages.iter().enumerate() {
         let diff = (voltage - calc_voltage).abs();
 
         if diff < min_diff {
@@ -38,13 +56,26 @@ fn find_direction_by_voltage(voltage: f32) -> f32 {
         }
     }
 
-    let mut dir = (idx as f32 * 22.5) + VANE_CALIBRATION_AMOUNT;
+    idx
+}
 
-    if dir >= 360.0 {
-        dir -= 360.0
-    }
+// Offset that turns the sector the vane reads while pointing at true north into 0°
+fn get_north_offset(north_idx: usize) -> f32 {
+    (360.0 - north_idx as f32 * 22.5).rem_euclid(360.0)
+}
+
+fn find_direction_by_voltage(voltage: f32, calibration: &VaneCalibration) -> f32 {
+    let idx = find_sector_by_voltage(voltage, &get_expected_voltages(calibration));
 
-    dir
+    ((idx as f32 * 22.5) + calibration.north_offset).rem_euclid(360.0)
+}
+
+fn wait_for_enter(prompt: &str) {
+    print!("{}", prompt);
+    stdout().flush().unwrap();
+
+    let mut line = String::new();
+    stdin().read_line(&mut line).unwrap();
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -56,8 +87,8 @@ pub struct WindVaneData {
 impl WindVaneData {
     pub fn new(direction: f32, last_updated: Option<SystemTime>) -> Self {
         Self {
-            direction: direction,
-            last_updated: last_updated
+            direction,
+            last_updated
         }
     }
 
@@ -73,8 +104,9 @@ impl WindVaneData {
         self.direction
     }
 
+    // Nearest of the 16 points, a north_offset that isn't a multiple of 22.5 leaves directions between them
     pub fn get_dir_as_string(&self) -> String {
-        DIRECTIONS[(self.get_direction() / 22.5) as usize].to_string()
+        DIRECTIONS[((self.get_direction() / 22.5).round() as usize) % 16].to_string()
     }
 }
 
@@ -95,8 +127,16 @@ impl Payload for WindVanePayload {
         // ...
     }
 
-    fn update_data_fields(&self, data: &mut DataPoint, daytime_info: &mut DaytimeData) {
-        data.update_direction(self.data.clone())
+    fn update_data_fields(&self, data: &mut DataPoint, _daytime_info: &mut DaytimeData, rejected: &QcMask) {
+        if !rejected.is_rejected(SENSOR_NAME, Quantity::WindDirection) {
+            data.update_direction(self.data);
+        }
+
+        data.update_sensor_state(SENSOR_NAME, SensorState::Ok);
+    }
+
+    fn get_readings(&self) -> Vec<SourceReadings> {
+        vec![ SourceReadings::new(SENSOR_NAME, vec![ (Quantity::WindDirection, self.data.get_direction()) ]) ]
     }
 }
 
@@ -106,36 +146,157 @@ pub struct WindVane {
     mcp: MCP3008,
     mcp_channel: u8,
     payload_sender: Sender<Box<dyn Payload>>,
-    buf: [u8; BUFFER_SIZE]
+    calibration: VaneCalibration
 }
 
 impl WindVane {
-    pub fn new(mcp: MCP3008, channel: u8, payload_sender: Sender<Box<dyn Payload>>) -> Self {
+    pub fn new(mcp: MCP3008, channel: u8, payload_sender: Sender<Box<dyn Payload>>, calibration: VaneCalibration) -> Self {
         Self {
             voltage: 0.0,
             direction: 0.0,
             mcp,
             mcp_channel: channel,
             payload_sender,
-            buf: [0u8; BUFFER_SIZE]
+            calibration
         }
     }
 
     pub fn update_data(&mut self) {
-        let bytes_read = self.mcp.read_from_channel(self.mcp_channel, &mut self.buf[..BUFFER_SIZE]);
+        match self.read_voltage() {
+            Ok(voltage) => {
+                self.voltage = voltage;
+                self.direction = find_direction_by_voltage(self.voltage, &self.calibration);
+
+                self.payload_sender.send(Box::new(WindVanePayload::new(self.direction, Some(SystemTime::now())))).unwrap();
+            },
+            Err(e) => {
+                println!("Failed to read from wind vane! Error: {}", e);
+
+                self.payload_sender.send(Box::new(SensorStatusPayload::new(SENSOR_NAME, SensorState::Failing))).unwrap();
+            }
+        }
+    }
+
+    // Walks the operator through each of the 16 labelled vane positions, then true north
+    pub fn calibrate(&mut self) -> Result<VaneCalibration, Box<dyn Error>> {
+        let mut voltages = [0.0f32; 16];
+
+        println!("Wind vane calibration -- hold the vane steady at each position and press Enter.");
+
+        for (i, label) in DIRECTIONS.iter().enumerate() {
+            wait_for_enter(&format!("Point the vane at its {} ({:.1}°) mark: ", label, i as f32 * 22.5));
+
+            voltages[i] = self.read_average_voltage()?;
+
+            println!("  {} -> {:.3}V", label, voltages[i]);
+        }
+
+        let mut calibration = VaneCalibration {
+            north_offset: 0.0,
+            voltages: Some(voltages),
+            ..self.calibration
+        };
+
+        wait_for_enter("Now point the vane at true north: ");
 
-        self.parse_bits(bytes_read);
+        let north_idx = find_sector_by_voltage(self.read_average_voltage()?, &voltages);
+        calibration.north_offset = get_north_offset(north_idx);
 
-        self.payload_sender.send(Box::new(WindVanePayload::new(self.direction, Some(SystemTime::now())))).unwrap();
+        println!("North offset: {:.1}°", calibration.north_offset);
+
+        self.calibration = calibration;
+
+        Ok(calibration)
+    }
+
+    fn read_average_voltage(&mut self) -> Result<f32, Box<dyn Error>> {
+        let mut total = 0.0;
+
+        for _ in 0..CALIBRATION_SAMPLES {
+            total += self.read_voltage()?;
+            sleep(Duration::from_millis(50));
+        }
+
+        Ok(total / CALIBRATION_SAMPLES as f32)
+    }
+
+    fn read_voltage(&mut self) -> Result<f32, Box<dyn Error>> {
+        self.mcp.read_voltage(self.mcp_channel)
     }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::{ VaneCalibration };
+    use crate::hardware::vane::{ WindVaneData, get_expected_voltages, find_sector_by_voltage, find_direction_by_voltage, get_north_offset };
+
+    #[test]
+    fn test_expected_voltages() {
+        let calibration = VaneCalibration::default();
+        let voltages = get_expected_voltages(&calibration);
 
-    fn parse_bits(&mut self, bytes_read: usize) {
-        let true_bytes = &self.buf[..bytes_read];
-        let mut ret_val = 0u16;
-        ret_val |= true_bytes[2] as u16;
-        ret_val |= ((true_bytes[1] & 3) as u16) << 8;
+        // 3.3V across each sector's resistor in series with the 5.1k output resistor
+        assert!((voltages[0] - 2.858).abs() < 0.001);       // N, 33k
+        assert!((voltages[4] - 0.541).abs() < 0.001);       // E, 1k
+        assert!((voltages[12] - 3.165).abs() < 0.001);      // W, 120k
 
-        self.voltage = (ret_val as f32 / 0x03FFu16 as f32) * INPUT_VOLTAGE;
-        self.direction = find_direction_by_voltage(self.voltage);
+        // Every sector has to be told apart from the rest
+        for (i, voltage) in voltages.iter().enumerate() {
+            assert_eq!(find_sector_by_voltage(*voltage, &voltages), i);
+            assert_eq!(find_sector_by_voltage(voltage + 0.01, &voltages), i);
+        }
+
+        let measured = [ 0.1, 0.3, 0.5, 0.7, 0.9, 1.1, 1.3, 1.5, 1.7, 1.9, 2.1, 2.3, 2.5, 2.7, 2.9, 3.1 ];
+        let calibration = VaneCalibration { voltages: Some(measured), ..VaneCalibration::default() };
+
+        assert_eq!(get_expected_voltages(&calibration), measured);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_north_offset() {
+        assert_eq!(get_north_offset(0), 0.0);
+        assert_eq!(get_north_offset(1), 337.5);
+        assert_eq!(get_north_offset(4), 270.0);
+        assert_eq!(get_north_offset(15), 22.5);
+
+        // Whichever sector reads at true north comes out as 0°, and the rest follow round from it
+        let voltages = get_expected_voltages(&VaneCalibration::default());
+
+        for north_idx in 0..16 {
+            let calibration = VaneCalibration { north_offset: get_north_offset(north_idx), ..VaneCalibration::default() };
+
+            assert_eq!(find_direction_by_voltage(voltages[north_idx], &calibration), 0.0);
+            assert_eq!(find_direction_by_voltage(voltages[(north_idx + 4) % 16], &calibration), 90.0);
+        }
+    }
+
+    #[test]
+    fn test_calibrated_direction() {
+        let voltages = get_expected_voltages(&VaneCalibration::default());
+
+        // A fixed offset turns every sector and wraps past north
+        let calibration = VaneCalibration { north_offset: 90.0, ..VaneCalibration::default() };
+
+        assert_eq!(find_direction_by_voltage(voltages[0], &calibration), 90.0);
+        assert_eq!(find_direction_by_voltage(voltages[15], &calibration), 67.5);
+
+        // Measured voltages are matched instead of the resistor table
+        let measured = [ 0.1, 0.3, 0.5, 0.7, 0.9, 1.1, 1.3, 1.5, 1.7, 1.9, 2.1, 2.3, 2.5, 2.7, 2.9, 3.1 ];
+        let calibration = VaneCalibration { north_offset: 180.0, voltages: Some(measured), ..VaneCalibration::default() };
+
+        assert_eq!(find_direction_by_voltage(0.31, &calibration), 202.5);
+        assert_eq!(find_direction_by_voltage(2.89, &calibration), 135.0);
+    }
+
+    #[test]
+    fn test_dir_as_string() {
+        let get_label = |direction| WindVaneData::new(direction, None).get_dir_as_string();
+
+        assert_eq!(get_label(0.0), "N");
+        assert_eq!(get_label(22.5), "NNE");
+        assert_eq!(get_label(32.5), "NNE");
+        assert_eq!(get_label(35.0), "NE");
+        assert_eq!(get_label(350.0), "N");
+        assert_eq!(get_label(337.5), "NNW");
+    }
+}
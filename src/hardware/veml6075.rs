@@ -0,0 +1,247 @@
+// Vishay VEML6075 UVA/UVB sensor over I2C, UV index calculation from the application note:
+// https://www.vishay.com/docs/84339/designingveml6075.pdf
+
+use rppal::i2c::{ I2c };
+use std::error::Error;
+use std::thread::{ sleep };
+use std::time::{ Duration, SystemTime };
+use crossbeam_channel::{ Sender };
+
+use super::events::{ Payload };
+use super::status::{ SensorState, SensorStatusPayload };
+use crate::config::{ VEML6075Config };
+use crate::data::process::{ DataPoint, DaytimeData };
+use crate::data::qc::{ QcMask, Quantity, SourceReadings };
+
+pub const ADDRESS: u16 = 0x10;
+
+// Registers
+const REG_UV_CONF: u8 = 0x00;
+const REG_UVA: u8 = 0x07;
+const REG_UVB: u8 = 0x09;
+const REG_UVCOMP1: u8 = 0x0A;
+const REG_UVCOMP2: u8 = 0x0B;
+const REG_ID: u8 = 0x0C;
+
+const DEVICE_ID: u16 = 0x26;
+const CONF_SHUTDOWN: u16 = 0x01;
+const CONF_TRIGGER: u16 = 0x04;
+const CONF_ACTIVE_FORCE: u16 = 0x02;
+
+// Visible and IR compensation coefficients for an open-air (no diffuser) sensor
+const UVA_VIS_COEF: f32 = 2.22;
+const UVA_IR_COEF: f32 = 1.33;
+const UVB_VIS_COEF: f32 = 2.95;
+const UVB_IR_COEF: f32 = 1.74;
+
+// UV index per count at the 100ms integration time
+const UVA_RESPONSIVITY: f32 = 0.001461;
+const UVB_RESPONSIVITY: f32 = 0.002591;
+const REFERENCE_INTEGRATION_MS: u16 = 100;
+
+pub const SENSOR_NAME: &str = "veml6075";
+
+pub fn get_integration_bits(integration_time_ms: u16) -> Option<u16> {
+    match integration_time_ms {
+        50 => Some(0),
+        100 => Some(1),
+        200 => Some(2),
+        400 => Some(3),
+        800 => Some(4),
+        _ => None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RawUV {
+    pub uva: u16,
+    pub uvb: u16,
+    pub comp_visible: u16,
+    pub comp_ir: u16
+}
+
+// Returns the compensated (UVA, UVB, UV index), longer integration times collect proportionally more counts
+pub fn calculate_uv(raw: RawUV, integration_time_ms: u16) -> (f32, f32, f32) {
+    let scale = REFERENCE_INTEGRATION_MS as f32 / integration_time_ms as f32;
+
+    let uva = (raw.uva as f32 - UVA_VIS_COEF * raw.comp_visible as f32 - UVA_IR_COEF * raw.comp_ir as f32).max(0.0);
+    let uvb = (raw.uvb as f32 - UVB_VIS_COEF * raw.comp_visible as f32 - UVB_IR_COEF * raw.comp_ir as f32).max(0.0);
+
+    let uv_index = (uva * UVA_RESPONSIVITY * scale + uvb * UVB_RESPONSIVITY * scale) / 2.0;
+
+    (uva, uvb, uv_index)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct UVData {
+    uva: f32,
+    uvb: f32,
+    uv_index: f32,
+    last_updated: Option<SystemTime>
+}
+
+impl UVData {
+    pub fn new(uva: f32, uvb: f32, uv_index: f32, last_updated: Option<SystemTime>) -> Self {
+        Self {
+            uva,
+            uvb,
+            uv_index,
+            last_updated
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.last_updated.is_some()
+    }
+
+    pub fn get_last_updated(&self) -> Option<SystemTime> {
+        self.last_updated
+    }
+
+    pub fn get_uva(&self) -> f32 {
+        self.uva
+    }
+
+    pub fn get_uvb(&self) -> f32 {
+        self.uvb
+    }
+
+    pub fn get_uv_index(&self) -> f32 {
+        self.uv_index
+    }
+}
+
+pub struct UVPayload {
+    data: UVData
+}
+
+impl UVPayload {
+    pub fn new(data: UVData) -> Self {
+        Self {
+            data
+        }
+    }
+}
+
+impl Payload for UVPayload {
+    fn send_message(&self) {
+        // ...
+    }
+
+    fn update_data_fields(&self, data: &mut DataPoint, daytime_info: &mut DaytimeData, rejected: &QcMask) {
+        if !rejected.is_rejected(SENSOR_NAME, Quantity::UvIndex) {
+            if daytime_info.uv_max < self.data.uv_index {
+                daytime_info.uv_max = self.data.uv_index;
+            }
+
+            data.update_uv(self.data);
+        }
+
+        data.update_sensor_state(SENSOR_NAME, SensorState::Ok);
+    }
+
+    fn get_readings(&self) -> Vec<SourceReadings> {
+        vec![ SourceReadings::new(SENSOR_NAME, vec![ (Quantity::UvIndex, self.data.uv_index) ]) ]
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+pub struct VEML6075 {
+    i2c: I2c,
+    config: VEML6075Config,
+    payload_sender: Sender<Box<dyn Payload>>
+}
+
+impl VEML6075 {
+    pub fn new(config: VEML6075Config, payload_sender: Sender<Box<dyn Payload>>) -> Result<Self, Box<dyn Error>> {
+        let mut i2c = I2c::with_bus(config.bus)?;
+        i2c.set_slave_address(ADDRESS)?;
+
+        let device_id = i2c.smbus_read_word(REG_ID)? & 0xFF;
+
+        if device_id != DEVICE_ID {
+            return Err(format!("Unknown device ID 0x{:02X} at address 0x{:02X}", device_id, ADDRESS).into());
+        }
+
+        let sensor = Self {
+            i2c,
+            config,
+            payload_sender
+        };
+
+        // Stay shut down between reads, each read triggers a single measurement
+        sensor.i2c.smbus_write_word(REG_UV_CONF, sensor.get_conf() | CONF_SHUTDOWN)?;
+
+        Ok(sensor)
+    }
+
+    fn get_conf(&self) -> u16 {
+        get_integration_bits(self.config.integration_time_ms).unwrap_or(1) << 4 | CONF_ACTIVE_FORCE
+    }
+
+    pub fn update_data(&mut self) {
+        match self.read_sensor() {
+            Ok(raw) => {
+                let (uva, uvb, uv_index) = calculate_uv(raw, self.config.integration_time_ms);
+
+                self.payload_sender.send(Box::new(UVPayload::new(UVData::new(uva, uvb, uv_index, Some(SystemTime::now()))))).unwrap();
+            },
+            Err(e) => {
+                println!("Failed to read from VEML6075! Error: {}", e);
+
+                self.payload_sender.send(Box::new(SensorStatusPayload::new(SENSOR_NAME, SensorState::Failing))).unwrap();
+            }
+        }
+    }
+
+    fn read_sensor(&mut self) -> Result<RawUV, Box<dyn Error>> {
+        let conf = self.get_conf();
+
+        self.i2c.smbus_write_word(REG_UV_CONF, conf)?;
+        self.i2c.smbus_write_word(REG_UV_CONF, conf | CONF_TRIGGER)?;
+
+        // Allow the integration to finish with some margin
+        sleep(Duration::from_millis(self.config.integration_time_ms as u64 * 2));
+
+        let raw = RawUV {
+            uva: self.i2c.smbus_read_word(REG_UVA)?,
+            uvb: self.i2c.smbus_read_word(REG_UVB)?,
+            comp_visible: self.i2c.smbus_read_word(REG_UVCOMP1)?,
+            comp_ir: self.i2c.smbus_read_word(REG_UVCOMP2)?
+        };
+
+        self.i2c.smbus_write_word(REG_UV_CONF, conf | CONF_SHUTDOWN)?;
+
+        Ok(raw)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hardware::veml6075::{ RawUV, calculate_uv, get_integration_bits };
+
+    #[test]
+    fn test_uv_index() {
+        let raw = RawUV { uva: 3000, uvb: 2400, comp_visible: 400, comp_ir: 300 };
+
+        let (uva, uvb, uv_index) = calculate_uv(raw, 100);
+
+        assert!((uva - 1713.0).abs() < 0.01, "UVA: {}", uva);
+        assert!((uvb - 698.0).abs() < 0.01, "UVB: {}", uvb);
+        assert!((uv_index - 2.1556).abs() < 0.001, "UV index: {}", uv_index);
+
+        // Twice the integration time collects twice the counts for the same light
+        let doubled = RawUV { uva: 6000, uvb: 4800, comp_visible: 800, comp_ir: 600 };
+        assert!((calculate_uv(doubled, 200).2 - uv_index).abs() < 0.001);
+
+        // Compensation can't push the reading below zero in the dark
+        assert_eq!(calculate_uv(RawUV { uva: 10, uvb: 10, comp_visible: 50, comp_ir: 50 }, 100), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_integration_bits() {
+        assert_eq!(get_integration_bits(100), Some(1));
+        assert_eq!(get_integration_bits(800), Some(4));
+        assert_eq!(get_integration_bits(150), None);
+    }
+}
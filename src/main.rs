@@ -15,51 +15,123 @@ mod db;
 mod hardware;
 mod data;
 mod api;
+mod schedule;
+mod units;
+mod notify;
 
-use hardware::dht::DHT;
+use hardware::dht::{ self, DHT };
 //use hardware::button::{ Button };
 use hardware::events::{ Event, EventType };
-use hardware::anemometer::{ Anemometer };
+use hardware::anemometer::{ self, Anemometer };
 use hardware::analog::{ MCP3008 };
-use hardware::vane::{ WindVane };
-use hardware::rain::{ RainMeter };
+use hardware::vane::{ self, WindVane };
+use hardware::rain::{ self, RainMeter };
+use hardware::bme280::{ self, BME280 };
+use hardware::analog_sensor::{ self, AnalogSensors };
+use hardware::veml6075::{ self, VEML6075 };
+use hardware::pyranometer::{ self, Pyranometer };
+use hardware::particulate::{ self, ParticulateSensor };
+use hardware::as3935::{ self, AS3935 };
+use hardware::ds18b20::{ self, DS18B20Probes };
+use hardware::status::{ init_sensor };
 
 use data::process::{ DataManager };
 
-use config::{ Config };
+use config::{ Config, AlertRule };
+
+use schedule::{ Scheduler, Trigger };
 
 use api::{ api_service };
+use api::tls::{ get_tls_acceptor, get_tls_incoming };
 
 use std::error::Error;
-use std::net::{ SocketAddr };
 use std::thread::{ sleep };
 use std::time::{ Duration };
 
 use rppal::gpio::{ Gpio, Mode };
-use rppal::spi::{ Bus, SlaveSelect, Mode as SPIMode };
-use job_scheduler::{ JobScheduler, Job };
+use rppal::spi::{ Mode as SPIMode };
 use crossbeam_channel as channel;
 use hyper::service::{ make_service_fn, service_fn };
 use hyper::{ Server };
+use tokio::signal::unix::{ signal, SignalKind };
+use tokio::net::{ TcpListener };
+use tokio::sync::{ oneshot };
 use lazy_static::lazy_static;
 
 lazy_static! {
     static ref CONFIG: Config = Config::retrieve_config();
 }
 
-const DHT_PIN: u8 = 4;
-const ANEMOMETER_PIN: u8 = 5;
-const RAIN_PIN: u8 = 6;
+// Resolves on the first SIGTERM or SIGINT, a second one gives up on shutting down cleanly
+async fn wait_for_signal() -> std::io::Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+
+    tokio::select! {
+        _ = terminate.recv() => println!("Received SIGTERM, shutting down"),
+        _ = interrupt.recv() => println!("Received SIGINT, shutting down")
+    }
+
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = terminate.recv() => {},
+            _ = interrupt.recv() => {}
+        }
+
+        println!("Received a second signal, exiting immediately");
+        std::process::exit(1);
+    });
+
+    Ok(())
+}
+
+// Signals go through the main loop like any other exit so everything stops in the same order.
+// Only called once the server is bound, otherwise a failed bind would leave the handlers
+// installed with nothing forwarding them and the station would ignore SIGTERM
+fn forward_signals(exit_tx: channel::Sender<Event>) {
+    tokio::spawn(async move {
+        match wait_for_signal().await {
+            Ok(_) => { let _ = exit_tx.send(Event::new(EventType::Exit)); },
+            Err(e) => println!("Unable to listen for shutdown signals: {}", e)
+        }
+    });
+}
 
 #[tokio::main]
-async fn tokio_main() -> Result<(), Box<dyn Error + Send + Sync>> {
-    let socket_addr = format!("0.0.0.0:{}", if CONFIG.is_prod_env() { 8080u16 } else { 3000u16 }).parse::<SocketAddr>().unwrap();
+async fn tokio_main(exit_tx: channel::Sender<Event>, shutdown_rx: oneshot::Receiver<()>) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let socket_addr = CONFIG.server.get_socket_addr(CONFIG.is_prod_env())?;
+
+    let shutdown = async {
+        let _ = shutdown_rx.await;
+    };
+
+    match CONFIG.server.get_tls_paths() {
+        Some((cert_path, key_path)) => {
+            let acceptor = get_tls_acceptor(cert_path, key_path)?;
+            let listener = TcpListener::bind(socket_addr).await?;
+
+            forward_signals(exit_tx);
 
-    let service = make_service_fn(|_| async { Ok::<_, hyper::Error>(service_fn(api_service)) }); 
-    
-    let server = Server::bind(&socket_addr).serve(service);
+            println!("Serving HTTPS on {}", socket_addr);
 
-    server.await?;
+            let service = make_service_fn(|_| async { Ok::<_, hyper::Error>(service_fn(api_service)) });
+
+            Server::builder(get_tls_incoming(listener, acceptor)).serve(service).with_graceful_shutdown(shutdown).await?;
+        },
+        None => {
+            let builder = Server::try_bind(&socket_addr)?;
+
+            forward_signals(exit_tx);
+
+            println!("Serving HTTP on {}", socket_addr);
+
+            let service = make_service_fn(|_| async { Ok::<_, hyper::Error>(service_fn(api_service)) });
+
+            builder.serve(service).with_graceful_shutdown(shutdown).await?;
+        }
+    }
+
+    println!("API server stopped");
 
     Ok(())
 }
@@ -69,8 +141,38 @@ fn main() -> Result<(), Box<dyn Error>> {
     let (tx, rx) = channel::unbounded();
     let (payload_tx, payload_rx) = channel::unbounded();
 
+    let hardware = &CONFIG.hardware;
+    hardware.validate()?;
+    CONFIG.schedule.validate()?;
+    CONFIG.qc.validate()?;
+    CONFIG.health.validate(&CONFIG.schedule)?;
+    CONFIG.temperature.validate(hardware)?;
+    AlertRule::validate_all(&CONFIG.get_alert_rules())?;
+    CONFIG.notifications.validate(&CONFIG.get_alert_rules())?;
+    CONFIG.admin.validate()?;
+    CONFIG.server.validate()?;
+
+    // The station's own files live in the working directory, the key is safer somewhere only its user can read
+    if std::env::current_dir().is_ok_and(|dir| CONFIG.server.is_tls_key_under(&dir)) {
+        println!("Warning: [server] tls_key is inside the working directory, consider moving it to e.g. /etc/pi-weather-station with mode 600");
+    }
+
+    // Interactive wind vane calibration, run with `pi-weather-station calibrate-vane`
+    if std::env::args().any(|arg| arg == "calibrate-vane") {
+        let mut wind_vane = WindVane::new(MCP3008::new(hardware.mcp3008.get_bus(), hardware.mcp3008.get_slave_select(), hardware.mcp3008.clock_speed, SPIMode::Mode0)?, hardware.vane.channel, payload_tx, CONFIG.calibration.vane);
+
+        let calibration = wind_vane.calibrate()?;
+        Config::save_vane_calibration(config::VANE_CALIBRATION_PATH, &calibration)?;
+
+        println!("Saved wind vane calibration to {}", config::VANE_CALIBRATION_PATH);
+
+        return Ok(());
+    }
+
     // Humidity and Temperature Init
-    let mut dht_sensor = DHT::new(Gpio::new()?.get(DHT_PIN)?.into_io(Mode::Input), tx.clone(), payload_tx.clone());
+    let mut dht_sensor = init_sensor(dht::SENSOR_NAME, hardware.dht.enabled, &payload_tx, || {
+        Ok(DHT::new(Gpio::new()?.get(hardware.dht.pin)?.into_io(Mode::Input), hardware.dht.model, hardware.dht.retries, payload_tx.clone(), CONFIG.calibration.dht))
+    });
 
     // let mut button = Button::new(Gpio::new()?.get(23)?.into_input(), tx.clone(), payload_tx.clone());
 
@@ -78,50 +180,141 @@ fn main() -> Result<(), Box<dyn Error>> {
     // let mut button_led = Gpio::new()?.get(24)?.into_output();
 
     // Anemometer Init
-    let mut anemometer = Anemometer::new(Gpio::new()?.get(ANEMOMETER_PIN)?.into_input_pullup(), tx.clone(), payload_tx.clone());
-    anemometer.start();
+    let mut anemometer = init_sensor(anemometer::SENSOR_NAME, hardware.anemometer.enabled, &payload_tx, || {
+        let mut anemometer = Anemometer::new(Gpio::new()?.get(hardware.anemometer.pin)?.into_input_pullup(), tx.clone(), payload_tx.clone(), CONFIG.calibration.anemometer);
+        anemometer.start()?;
+
+        Ok(anemometer)
+    });
 
     // Wind vane init
-    let mut wind_vane = WindVane::new(MCP3008::new(Bus::Spi0, SlaveSelect::Ss0, 1000000u32, SPIMode::Mode0), 0, payload_tx.clone());
+    let mut wind_vane = init_sensor(vane::SENSOR_NAME, hardware.vane.enabled, &payload_tx, || {
+        Ok(WindVane::new(MCP3008::new(hardware.mcp3008.get_bus(), hardware.mcp3008.get_slave_select(), hardware.mcp3008.clock_speed, SPIMode::Mode0)?, hardware.vane.channel, payload_tx.clone(), CONFIG.calibration.vane))
+    });
 
     // Rain guage init
-    let mut rain_guage = RainMeter::new(Gpio::new()?.get(RAIN_PIN)?.into_input_pullup(), tx.clone(), payload_tx.clone());
-    rain_guage.start();
+    let mut rain_guage = init_sensor(rain::SENSOR_NAME, hardware.rain.enabled, &payload_tx, || {
+        let mut rain_guage = RainMeter::new(Gpio::new()?.get(hardware.rain.pin)?.into_input_pullup(), tx.clone(), payload_tx.clone(), CONFIG.calibration.rain);
+        rain_guage.start()?;
+
+        Ok(rain_guage)
+    });
+
+    // Barometric pressure init
+    let mut pressure_sensor = init_sensor(bme280::SENSOR_NAME, hardware.bme280.enabled, &payload_tx, || {
+        BME280::new(hardware.bme280, payload_tx.clone())
+    });
+
+    // UV sensor init
+    let mut uv_sensor = init_sensor(veml6075::SENSOR_NAME, hardware.veml6075.enabled, &payload_tx, || {
+        VEML6075::new(hardware.veml6075, payload_tx.clone())
+    });
+
+    // Pyranometer init
+    let mut pyranometer = init_sensor(pyranometer::SENSOR_NAME, hardware.pyranometer.enabled, &payload_tx, || {
+        Ok(Pyranometer::new(MCP3008::new(hardware.mcp3008.get_bus(), hardware.mcp3008.get_slave_select(), hardware.mcp3008.clock_speed, SPIMode::Mode0)?, hardware.pyranometer.channel, hardware.pyranometer.transfer.clone(), payload_tx.clone()))
+    });
+
+    // Particulate matter init
+    let mut particulate_sensor = init_sensor(particulate::SENSOR_NAME, hardware.particulate.enabled, &payload_tx, || {
+        ParticulateSensor::new(hardware.particulate.clone(), payload_tx.clone())
+    });
+
+    // Lightning detector init
+    let mut lightning_sensor = init_sensor(as3935::SENSOR_NAME, hardware.as3935.enabled, &payload_tx, || {
+        let mut lightning_sensor = AS3935::new(hardware.as3935, Gpio::new()?.get(hardware.as3935.irq_pin)?.into_input_pulldown(), tx.clone(), payload_tx.clone())?;
+        lightning_sensor.start()?;
+
+        Ok(lightning_sensor)
+    });
+
+    // 1-Wire temperature probes init
+    let mut probes = init_sensor(ds18b20::SENSOR_NAME, hardware.ds18b20.enabled, &payload_tx, || {
+        DS18B20Probes::new(&hardware.ds18b20, payload_tx.clone())
+    });
+
+    // Generic analog sensors on the remaining MCP3008 channels
+    let analog_enabled = hardware.analog.iter().any(|sensor| sensor.enabled);
+    let mut analog_sensors = init_sensor(analog_sensor::SENSOR_NAME, analog_enabled, &payload_tx, || {
+        Ok(AnalogSensors::new(MCP3008::new(hardware.mcp3008.get_bus(), hardware.mcp3008.get_slave_select(), hardware.mcp3008.clock_speed, SPIMode::Mode0)?, hardware.analog.clone(), payload_tx.clone()))
+    });
 
     // Data Manager init
     let (time_tx, time_rx) = channel::unbounded();
-    let manager = DataManager::new(tx.clone(), payload_rx.clone(), time_rx.clone(), CONFIG.clone())?;
-    manager.start();
+    let manager = DataManager::new(payload_rx.clone(), time_rx.clone(), CONFIG.clone())?;
+    let manager_handle = manager.start();
 
-    std::thread::spawn(move || {
-        let res = tokio_main();
+    // The admin API reads sensors and exits through the main loop, everything else goes to the data manager
+    api::admin::register_senders(tx.clone(), time_tx.clone());
+
+    if CONFIG.admin.is_enabled() {
+        println!("Admin API enabled under /api/admin");
+    }
+
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+    let exit_tx = tx.clone();
+
+    let server_handle = std::thread::spawn(move || {
+        if let Err(e) = tokio_main(exit_tx, shutdown_rx) {
+            println!("API server error: {}", e);
+        }
     });
 
-    let mut schedule = JobScheduler::new();
+    let mut schedule = Scheduler::new();
 
     // Rain job
     let rain_job_sender = tx.clone();
-    schedule.add(Job::new("0 0/5 * * * *".parse().unwrap(), move || {
+    schedule.add(Trigger::parse(&CONFIG.schedule.rain)?, move || {
         rain_job_sender.send(Event::new(EventType::UpdateRain)).unwrap();
-    }));
+    });
 
     // Wind job
     let wind_job_sender = tx.clone();
-    schedule.add(Job::new("0 0/5 * * * *".parse().unwrap(), move || {
+    schedule.add(Trigger::parse(&CONFIG.schedule.wind)?, move || {
         wind_job_sender.send(Event::new(EventType::UpdateWind)).unwrap();
-    }));
+    });
 
     // Temp job
     let temp_job_sender = tx.clone();
-    schedule.add(Job::new("0 0/1 * * * *".parse().unwrap(), move || {
+    schedule.add(Trigger::parse(&CONFIG.schedule.temp)?, move || {
         temp_job_sender.send(Event::new(EventType::UpdateTemp)).unwrap();
-    }));
+    });
+
+    // Pressure job
+    let pressure_job_sender = tx.clone();
+    schedule.add(Trigger::parse(&CONFIG.schedule.pressure)?, move || {
+        pressure_job_sender.send(Event::new(EventType::UpdatePressure)).unwrap();
+    });
+
+    // Analog job
+    let analog_job_sender = tx.clone();
+    schedule.add(Trigger::parse(&CONFIG.schedule.analog)?, move || {
+        analog_job_sender.send(Event::new(EventType::UpdateAnalog)).unwrap();
+    });
+
+    // Solar radiation and UV job
+    let solar_job_sender = tx.clone();
+    schedule.add(Trigger::parse(&CONFIG.schedule.solar)?, move || {
+        solar_job_sender.send(Event::new(EventType::UpdateSolar)).unwrap();
+    });
+
+    // Particulate matter job
+    let particulate_job_sender = tx.clone();
+    schedule.add(Trigger::parse(&CONFIG.schedule.particulate)?, move || {
+        particulate_job_sender.send(Event::new(EventType::UpdateParticulate)).unwrap();
+    });
+
+    // 1-Wire probes job
+    let probes_job_sender = tx.clone();
+    schedule.add(Trigger::parse(&CONFIG.schedule.probes)?, move || {
+        probes_job_sender.send(Event::new(EventType::UpdateProbes)).unwrap();
+    });
 
     // Data refresh job
     let data_refresh_sender = time_tx.clone();
-    schedule.add(Job::new("0 0 0 * * *".parse().unwrap(), move || {
+    schedule.add(Trigger::parse(&CONFIG.schedule.midnight_refresh)?, move || {
         data_refresh_sender.send(Event::new(EventType::MidnightRefresh)).unwrap();
-    }));
+    });
 
     loop {
         schedule.tick();
@@ -129,26 +322,39 @@ fn main() -> Result<(), Box<dyn Error>> {
         if let Ok(event) = rx.try_recv() {
             match event.get_event_type() {
                 EventType::AnemometerCount => {
-                    anemometer.increment_counter();
+                    if let Some(anemometer) = anemometer.as_mut() { anemometer.increment_counter(); }
                 },
                 EventType::RainCount => {
-                    rain_guage.increment_counter();
+                    if let Some(rain_guage) = rain_guage.as_mut() { rain_guage.increment_counter(); }
                 },
-                EventType::UpdateData => {
-                    // button.update_data();
-                    // anemometer.update_data();
-                    // rain_guage.update_data();
-                    // wind_vane.update_data();
+                EventType::LightningInterrupt => {
+                    if let Some(lightning_sensor) = lightning_sensor.as_mut() { lightning_sensor.handle_interrupt(); }
                 },
                 EventType::UpdateRain => {
-                    rain_guage.update_data();
+                    if let Some(rain_guage) = rain_guage.as_mut() { rain_guage.update_data(); }
                 },
                 EventType::UpdateWind => {
-                    anemometer.update_data();
-                    wind_vane.update_data();
+                    if let Some(anemometer) = anemometer.as_mut() { anemometer.update_data(); }
+                    if let Some(wind_vane) = wind_vane.as_mut() { wind_vane.update_data(); }
                 },
                 EventType::UpdateTemp => {
-                    dht_sensor.update_data();
+                    if let Some(dht_sensor) = dht_sensor.as_mut() { dht_sensor.update_data(); }
+                },
+                EventType::UpdatePressure => {
+                    if let Some(pressure_sensor) = pressure_sensor.as_mut() { pressure_sensor.update_data(); }
+                },
+                EventType::UpdateSolar => {
+                    if let Some(uv_sensor) = uv_sensor.as_mut() { uv_sensor.update_data(); }
+                    if let Some(pyranometer) = pyranometer.as_mut() { pyranometer.update_data(); }
+                },
+                EventType::UpdateParticulate => {
+                    if let Some(particulate_sensor) = particulate_sensor.as_mut() { particulate_sensor.update_data(); }
+                },
+                EventType::UpdateProbes => {
+                    if let Some(probes) = probes.as_mut() { probes.update_data(); }
+                },
+                EventType::UpdateAnalog => {
+                    if let Some(analog_sensors) = analog_sensors.as_mut() { analog_sensors.update_data(); }
                 },
                 EventType::Exit => {
                     println!("Exiting program!");
@@ -161,5 +367,23 @@ fn main() -> Result<(), Box<dyn Error>> {
         sleep(Duration::from_millis(100));
     }
 
+    // Nothing new gets scheduled past this point, the data manager drains what the sensors already sent
+    drop(schedule);
+
+    time_tx.send(Event::new(EventType::Exit))?;
+
+    if manager_handle.join().is_err() {
+        println!("Data manager stopped unexpectedly");
+    }
+
+    // In-flight requests finish before the server stops
+    let _ = shutdown_tx.send(());
+
+    if server_handle.join().is_err() {
+        println!("API server stopped unexpectedly");
+    }
+
+    println!("Station stopped");
+
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,231 @@
+use std::collections::{ HashMap };
+use std::error::Error;
+use std::time::{ Duration };
+use reqwest::blocking::{ Client, RequestBuilder };
+use serde_json::json;
+
+use super::{ Notification };
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn send_request(request: RequestBuilder) -> Result<(), Box<dyn Error>> {
+    let response = request.timeout(TIMEOUT).send()?;
+
+    if !response.status().is_success() {
+        return Err(format!("Server responded with {}", response.status()).into());
+    }
+
+    Ok(())
+}
+
+pub fn send_webhook(url: &str, headers: &HashMap<String, String>, notification: &Notification) -> Result<(), Box<dyn Error>> {
+    let mut request = Client::new().post(url).json(&json!({
+        "title": notification.title,
+        "message": notification.message,
+        "event": notification.event
+    }));
+
+    for (name, value) in headers.iter() {
+        request = request.header(name.as_str(), value.as_str());
+    }
+
+    send_request(request)
+}
+
+// Header values have to be visible ASCII, anything else (a "°C" in the title) goes as an RFC 2047 encoded word
+pub fn encode_header_value(value: &str) -> String {
+    if value.chars().all(|c| c == ' ' || c.is_ascii_graphic()) {
+        value.to_string()
+    } else {
+        format!("=?UTF-8?B?{}?=", base64::encode(value))
+    }
+}
+
+// https://docs.ntfy.sh/publish/, which decodes RFC 2047 headers
+pub fn send_ntfy(url: &str, token: Option<&str>, priority: Option<u8>, notification: &Notification) -> Result<(), Box<dyn Error>> {
+    let mut request = Client::new().post(url)
+        .header("Title", encode_header_value(&notification.title))
+        .header("Tags", "warning")
+        .body(notification.message.clone());
+
+    if let Some(priority) = priority {
+        request = request.header("Priority", priority.to_string());
+    }
+
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+
+    send_request(request)
+}
+
+// https://gotify.net/api-docs#/message/createMessage
+pub fn send_gotify(url: &str, token: &str, priority: Option<u8>, notification: &Notification) -> Result<(), Box<dyn Error>> {
+    let request = Client::new().post(format!("{}/message", url.trim_end_matches('/')))
+        .header("X-Gotify-Key", token)
+        .json(&json!({
+            "title": notification.title,
+            "message": notification.message,
+            "priority": priority.unwrap_or(5)
+        }));
+
+    send_request(request)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{ HashMap };
+    use std::io::{ BufRead, BufReader, Read, Write };
+    use std::net::{ TcpListener };
+    use std::thread::{ spawn, JoinHandle };
+    use std::time::{ Duration, Instant };
+    use serde_json::json;
+    use crate::config::{ NotificationsConfig, SinkConfig };
+    use crate::notify::{ Notification, Notifier, send_with_retries };
+    use crate::notify::http::{ encode_header_value };
+
+    // Answers one request per status with that status, handing back what was received
+    fn start_http_stand_in(statuses: Vec<u16>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let handle = spawn(move || {
+            statuses.into_iter().map(|status| {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request = String::new();
+                let mut content_length = 0;
+
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+
+                    if let Some(length) = line.to_lowercase().strip_prefix("content-length:") {
+                        content_length = length.trim().parse().unwrap();
+                    }
+
+                    request.push_str(&line);
+
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+
+                let mut body = vec![ 0u8; content_length ];
+                reader.read_exact(&mut body).unwrap();
+                request.push_str(&String::from_utf8(body).unwrap());
+
+                write!(reader.get_mut(), "HTTP/1.1 {} Stand-in\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status).unwrap();
+
+                request
+            }).collect()
+        });
+
+        (url, handle)
+    }
+
+    fn notification(sink: &str) -> Notification {
+        Notification {
+            sink: sink.to_string(),
+            title: "Weather alert: frost raised".to_string(),
+            message: "frost raised, temperature is -1.5".to_string(),
+            event: json!({ "rule": "frost" })
+        }
+    }
+
+    #[test]
+    fn test_webhook_retries() {
+        let (url, handle) = start_http_stand_in(vec![ 500, 200 ]);
+        let mut headers = HashMap::new();
+        headers.insert("X-Station".to_string(), "garden".to_string());
+
+        let sink = SinkConfig::Webhook { url, headers };
+
+        assert!(send_with_retries(&sink, &notification("hook"), 1, Duration::from_millis(10)).is_ok());
+
+        let requests = handle.join().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].to_lowercase().contains("x-station: garden"));
+        assert!(requests[1].contains("\"rule\":\"frost\""));
+    }
+
+    #[test]
+    fn test_ntfy_and_gotify() {
+        let (url, handle) = start_http_stand_in(vec![ 200 ]);
+        let sink = SinkConfig::Ntfy { url: format!("{}/station", url), token: Some("tk_secret".to_string()), priority: Some(4) };
+
+        assert!(send_with_retries(&sink, &notification("phone"), 0, Duration::from_millis(0)).is_ok());
+
+        let request = handle.join().unwrap().remove(0);
+        assert!(request.starts_with("POST /station "));
+        assert!(request.contains("authorization: Bearer tk_secret"));
+        assert!(request.contains("priority: 4"));
+        assert!(request.ends_with("frost raised, temperature is -1.5"));
+
+        let (url, handle) = start_http_stand_in(vec![ 401 ]);
+        let sink = SinkConfig::Gotify { url: format!("{}/", url), token: "app_token".to_string(), priority: None };
+
+        assert!(send_with_retries(&sink, &notification("gotify"), 0, Duration::from_millis(0)).is_err());
+
+        let request = handle.join().unwrap().remove(0);
+        assert!(request.starts_with("POST /message "));
+        assert!(request.contains("x-gotify-key: app_token"));
+    }
+
+    #[test]
+    fn test_sinks_send_independently() {
+        let (failing_url, failing_handle) = start_http_stand_in(vec![ 500, 500 ]);
+        let (url, handle) = start_http_stand_in(vec![ 200 ]);
+
+        let mut config = NotificationsConfig { retries: 1, retry_delay_secs: 3, ..NotificationsConfig::default() };
+        config.sinks.insert("down".to_string(), SinkConfig::Webhook { url: failing_url, headers: HashMap::new() });
+        config.sinks.insert("hook".to_string(), SinkConfig::Webhook { url, headers: HashMap::new() });
+
+        let notifier = Notifier::start(config);
+        let start = Instant::now();
+
+        notifier.send(notification("down"));
+        notifier.send(notification("hook"));
+
+        // The working sink doesn't wait out the other one's retry delay
+        assert_eq!(handle.join().unwrap().len(), 1);
+        assert!(start.elapsed() < Duration::from_secs(2), "Took {:?}", start.elapsed());
+
+        assert_eq!(failing_handle.join().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_notifier_shutdown() {
+        let (url, handle) = start_http_stand_in(vec![ 200 ]);
+        let (failing_url, failing_handle) = start_http_stand_in(vec![ 500 ]);
+
+        let mut config = NotificationsConfig { retries: 1, retry_delay_secs: 30, ..NotificationsConfig::default() };
+        config.sinks.insert("hook".to_string(), SinkConfig::Webhook { url, headers: HashMap::new() });
+        config.sinks.insert("down".to_string(), SinkConfig::Webhook { url: failing_url, headers: HashMap::new() });
+
+        let notifier = Notifier::start(config);
+        notifier.send(notification("hook"));
+        notifier.send(notification("down"));
+
+        // Whatever was queued still goes out, and the one stuck in its retry delay doesn't hold up the stop
+        let start = Instant::now();
+        notifier.shutdown(Duration::from_millis(500));
+
+        assert!(start.elapsed() < Duration::from_secs(2), "Took {:?}", start.elapsed());
+        assert_eq!(handle.join().unwrap().len(), 1);
+        assert_eq!(failing_handle.join().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_ntfy_unicode_title() {
+        assert_eq!(encode_header_value("Weather alert: frost raised"), "Weather alert: frost raised");
+        assert_eq!(encode_header_value("-1.5°C"), "=?UTF-8?B?LTEuNcKwQw==?=");
+
+        let (url, handle) = start_http_stand_in(vec![ 200 ]);
+        let sink = SinkConfig::Ntfy { url, token: None, priority: None };
+        let notification = Notification { title: "Frost: -1.5°C".to_string(), ..notification("phone") };
+
+        assert!(send_with_retries(&sink, &notification, 0, Duration::from_millis(0)).is_ok());
+        assert!(handle.join().unwrap()[0].contains("title: =?UTF-8?B?RnJvc3Q6IC0xLjXCsEM=?="));
+    }
+}
@@ -0,0 +1,210 @@
+// Sends alert notifications to the sinks configured in [notifications], each sink on a thread of its own
+// so retries and slow servers don't hold up the data manager or the other sinks
+
+pub mod http;
+pub mod smtp;
+
+use std::collections::{ BTreeMap, HashMap, VecDeque };
+use std::error::Error;
+use std::thread::{ sleep, spawn, JoinHandle };
+use std::time::{ Duration, Instant };
+use crossbeam_channel::{ self as channel, Sender };
+use serde_json::{ Value };
+
+use crate::config::{ NotificationsConfig, SinkConfig };
+
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60 * 60);
+const SHUTDOWN_POLL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub sink: String,
+    pub title: String,
+    pub message: String,
+    pub event: Value                // The alert event and readings, sent as-is to webhooks
+}
+
+// Replaces each {key} with its value, placeholders without a value are left as they are
+pub fn render_template(template: &str, values: &BTreeMap<String, String>) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+
+        match rest[start..].find('}').map(|end| &rest[start + 1..start + end]) {
+            Some(key) if values.contains_key(key) => {
+                rendered.push_str(&values[key]);
+                rest = &rest[start + key.len() + 2..];
+            },
+            _ => {
+                rendered.push('{');
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+
+    rendered.push_str(rest);
+
+    rendered
+}
+
+pub struct RateLimiter {
+    max_per_window: u32,
+    sent: HashMap<String, VecDeque<Instant>>
+}
+
+impl RateLimiter {
+    pub fn new(max_per_window: u32) -> Self {
+        Self {
+            max_per_window,
+            sent: HashMap::new()
+        }
+    }
+
+    // Counts the notification against the sink's budget if there's room for it
+    pub fn try_acquire(&mut self, sink: &str, now: Instant) -> bool {
+        let sent = self.sent.entry(sink.to_string()).or_default();
+
+        while sent.front().is_some_and(|time| now.duration_since(*time) >= RATE_LIMIT_WINDOW) {
+            sent.pop_front();
+        }
+
+        if sent.len() >= self.max_per_window as usize {
+            return false;
+        }
+
+        sent.push_back(now);
+
+        true
+    }
+}
+
+pub fn send(sink: &SinkConfig, notification: &Notification) -> Result<(), Box<dyn Error>> {
+    match sink {
+        SinkConfig::Webhook { url, headers } => http::send_webhook(url, headers, notification),
+        SinkConfig::Ntfy { url, token, priority } => http::send_ntfy(url, token.as_deref(), *priority, notification),
+        SinkConfig::Gotify { url, token, priority } => http::send_gotify(url, token, *priority, notification),
+        SinkConfig::Email { .. } => smtp::send_email(sink, notification)
+    }
+}
+
+pub fn send_with_retries(sink: &SinkConfig, notification: &Notification, retries: u32, retry_delay: Duration) -> Result<(), Box<dyn Error>> {
+    let mut attempt = 0;
+
+    loop {
+        match send(sink, notification) {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt < retries => {
+                println!("Failed to notify \"{}\" (attempt {} of {}), retrying! Error: {}", notification.sink, attempt + 1, retries + 1, e);
+
+                attempt += 1;
+                sleep(retry_delay);
+            },
+            Err(e) => return Err(e)
+        }
+    }
+}
+
+pub struct Notifier {
+    senders: HashMap<String, Sender<Notification>>,
+    workers: Vec<(String, JoinHandle<()>)>
+}
+
+impl Notifier {
+    pub fn start(config: NotificationsConfig) -> Self {
+        let mut senders = HashMap::new();
+        let mut workers = Vec::new();
+
+        for (name, sink) in config.sinks.iter() {
+            let (sender, receiver) = channel::unbounded::<Notification>();
+            let (sink, config) = (sink.clone(), config.clone());
+
+            let worker = spawn(move || {
+                let mut rate_limiter = RateLimiter::new(config.rate_limit_per_hour);
+
+                for notification in receiver.iter() {
+                    if !rate_limiter.try_acquire(&notification.sink, Instant::now()) {
+                        println!("Dropped notification \"{}\" to \"{}\", over {} per hour", notification.title, notification.sink, config.rate_limit_per_hour);
+                        continue;
+                    }
+
+                    if let Err(e) = send_with_retries(&sink, &notification, config.retries, Duration::from_secs(config.retry_delay_secs)) {
+                        println!("Failed to notify \"{}\", giving up! Error: {}", notification.sink, e);
+                    }
+                }
+            });
+
+            senders.insert(name.clone(), sender);
+            workers.push((name.clone(), worker));
+        }
+
+        Self {
+            senders,
+            workers
+        }
+    }
+
+    // Queues it for its sink's worker, notifications for sinks that aren't configured are dropped
+    pub fn send(&self, notification: Notification) {
+        if let Some(sender) = self.senders.get(&notification.sink) {
+            let sink = notification.sink.clone();
+
+            if sender.send(notification).is_err() {
+                println!("Notifier for \"{}\" has stopped, dropped a notification", sink);
+            }
+        }
+    }
+
+    // Closes every queue so the workers stop once they've sent what's left, waiting at most timeout for them.
+    // A worker still retrying after that is left behind, the process exiting ends it
+    pub fn shutdown(self, timeout: Duration) {
+        drop(self.senders);
+
+        let deadline = Instant::now() + timeout;
+
+        while self.workers.iter().any(|(_, worker)| !worker.is_finished()) && Instant::now() < deadline {
+            sleep(SHUTDOWN_POLL);
+        }
+
+        for (sink, worker) in self.workers {
+            if !worker.is_finished() {
+                println!("Gave up waiting on notifications to \"{}\"", sink);
+            } else if worker.join().is_err() {
+                println!("Notifier for \"{}\" panicked", sink);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{ BTreeMap };
+    use std::time::{ Duration, Instant };
+    use crate::notify::{ RateLimiter, render_template };
+
+    #[test]
+    fn test_render_template() {
+        let mut values = BTreeMap::new();
+        values.insert("rule".to_string(), "frost".to_string());
+        values.insert("value".to_string(), "-1.5".to_string());
+
+        assert_eq!(render_template("{rule}: {value}°C", &values), "frost: -1.5°C");
+        assert_eq!(render_template("{rule} {unknown} {", &values), "frost {unknown} {");
+        assert_eq!(render_template("{{rule}}", &values), "{frost}");
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let start = Instant::now();
+        let mut rate_limiter = RateLimiter::new(2);
+
+        assert!(rate_limiter.try_acquire("phone", start));
+        assert!(rate_limiter.try_acquire("phone", start + Duration::from_secs(60)));
+        assert!(!rate_limiter.try_acquire("phone", start + Duration::from_secs(120)));
+
+        // Each sink has its own budget, and it frees up an hour after each send
+        assert!(rate_limiter.try_acquire("email", start + Duration::from_secs(120)));
+        assert!(rate_limiter.try_acquire("phone", start + Duration::from_secs(60 * 60)));
+    }
+}
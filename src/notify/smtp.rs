@@ -0,0 +1,266 @@
+// Just enough SMTP (RFC 5321) to hand an alert to a mail server, with optional STARTTLS/TLS and AUTH PLAIN
+
+use std::error::Error;
+use std::io::{ BufRead, BufReader, Read, Write };
+use std::net::{ TcpStream, ToSocketAddrs };
+use std::time::{ Duration };
+use chrono::{ Local };
+use openssl::ssl::{ SslConnector, SslMethod };
+
+use super::{ Notification };
+use super::http::{ encode_header_value };
+use crate::config::{ SinkConfig, SmtpSecurity };
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+trait Stream: Read + Write {}
+impl<T: Read + Write> Stream for T {}
+
+struct SmtpConnection {
+    reader: BufReader<Box<dyn Stream>>
+}
+
+impl SmtpConnection {
+    fn new(stream: Box<dyn Stream>) -> Self {
+        Self {
+            reader: BufReader::new(stream)
+        }
+    }
+
+    // Replies can span several lines ("250-..."), the last one has a space after the code
+    fn read_reply(&mut self) -> Result<(u16, String), Box<dyn Error>> {
+        let mut reply = String::new();
+
+        loop {
+            let mut line = String::new();
+
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err("Connection closed by the server".into());
+            }
+
+            reply.push_str(&line);
+
+            if line.len() < 4 || line.as_bytes()[3] != b'-' {
+                let code = line.get(..3).and_then(|code| code.parse().ok()).ok_or_else(|| format!("Invalid reply: {}", line.trim_end()))?;
+
+                return Ok((code, reply));
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: u16) -> Result<String, Box<dyn Error>> {
+        let (code, reply) = self.read_reply()?;
+
+        if code != expected {
+            return Err(format!("Expected {}, got: {}", expected, reply.trim_end()).into());
+        }
+
+        Ok(reply)
+    }
+
+    fn send_line(&mut self, line: &str) -> Result<(), Box<dyn Error>> {
+        write!(self.reader.get_mut(), "{}\r\n", line)?;
+        self.reader.get_mut().flush()?;
+
+        Ok(())
+    }
+
+    fn command(&mut self, command: &str, expected: u16) -> Result<String, Box<dyn Error>> {
+        self.send_line(command)?;
+
+        self.expect(expected)
+    }
+}
+
+fn connect_tls(server: &str, stream: TcpStream) -> Result<Box<dyn Stream>, Box<dyn Error>> {
+    let connector = SslConnector::builder(SslMethod::tls())?.build();
+
+    Ok(Box::new(connector.connect(server, stream)?))
+}
+
+// Lines starting with a dot get another one so they can't end the DATA section early
+fn format_body(message: &str) -> String {
+    message.lines()
+        .map(|line| if line.starts_with('.') { format!(".{}", line) } else { line.to_string() })
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+pub fn send_email(sink: &SinkConfig, notification: &Notification) -> Result<(), Box<dyn Error>> {
+    let (server, port, security, username, password, from, to) = match sink {
+        SinkConfig::Email { server, port, security, username, password, from, to } => (server, *port, *security, username, password, from, to),
+        _ => return Err("Not an email sink".into())
+    };
+
+    let address = (server.as_str(), port).to_socket_addrs()?.next().ok_or("Mail server address didn't resolve")?;
+    let tcp = TcpStream::connect_timeout(&address, TIMEOUT)?;
+    tcp.set_read_timeout(Some(TIMEOUT))?;
+    tcp.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut connection = match security {
+        SmtpSecurity::Tls => SmtpConnection::new(connect_tls(server, tcp.try_clone()?)?),
+        _ => SmtpConnection::new(Box::new(tcp.try_clone()?))
+    };
+
+    connection.expect(220)?;
+    connection.command("EHLO pi-weather-station", 250)?;
+
+    if security == SmtpSecurity::StartTls {
+        connection.command("STARTTLS", 220)?;
+
+        // The plain connection is done with, the handshake happens on the same socket
+        drop(connection);
+        connection = SmtpConnection::new(connect_tls(server, tcp)?);
+        connection.command("EHLO pi-weather-station", 250)?;
+    }
+
+    if let (Some(username), Some(password)) = (username, password) {
+        let credentials = base64::encode(format!("\0{}\0{}", username, password));
+
+        connection.command(&format!("AUTH PLAIN {}", credentials), 235)?;
+    }
+
+    connection.command(&format!("MAIL FROM:<{}>", from), 250)?;
+
+    for recipient in to.iter() {
+        connection.send_line(&format!("RCPT TO:<{}>", recipient))?;
+
+        let (code, reply) = connection.read_reply()?;
+
+        // 251 is "user not local, will forward"
+        if code != 250 && code != 251 {
+            return Err(format!("Recipient {} was refused: {}", recipient, reply.trim_end()).into());
+        }
+    }
+
+    connection.command("DATA", 354)?;
+
+    let email = format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}\r\n.",
+        from, to.join(", "), encode_header_value(&notification.title), Local::now().to_rfc2822(), format_body(&notification.message)
+    );
+
+    connection.command(&email, 250)?;
+    connection.command("QUIT", 221)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{ BufRead, BufReader, Write };
+    use std::net::{ TcpListener };
+    use std::thread::{ spawn };
+    use serde_json::json;
+    use crate::config::{ SinkConfig, SmtpSecurity };
+    use crate::notify::{ Notification };
+    use crate::notify::smtp::{ send_email };
+
+    // A mail server that accepts everything and hands back the session transcript
+    fn start_smtp_stand_in() -> (u16, std::thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut transcript = Vec::new();
+            let mut in_data = false;
+
+            write!(reader.get_mut(), "220 stand-in ESMTP\r\n").unwrap();
+
+            loop {
+                let mut line = String::new();
+
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+
+                let line = line.trim_end().to_string();
+                transcript.push(line.clone());
+
+                let reply = if in_data {
+                    if line == "." { in_data = false; "250 queued" } else { continue }
+                } else if line.starts_with("EHLO") {
+                    "250-stand-in\r\n250 AUTH PLAIN"
+                } else if line.starts_with("AUTH") {
+                    "235 ok"
+                } else if line == "DATA" {
+                    in_data = true;
+                    "354 go ahead"
+                } else if line == "QUIT" {
+                    write!(reader.get_mut(), "221 bye\r\n").unwrap();
+                    break;
+                } else {
+                    "250 ok"
+                };
+
+                write!(reader.get_mut(), "{}\r\n", reply).unwrap();
+            }
+
+            transcript
+        });
+
+        (port, handle)
+    }
+
+    #[test]
+    fn test_send_email() {
+        let (port, handle) = start_smtp_stand_in();
+
+        let sink = SinkConfig::Email {
+            server: "127.0.0.1".to_string(),
+            port,
+            security: SmtpSecurity::None,
+            username: Some("station".to_string()),
+            password: Some("hunter2".to_string()),
+            from: "station@example.com".to_string(),
+            to: vec![ "me@example.com".to_string(), "you@example.com".to_string() ]
+        };
+
+        let notification = Notification {
+            sink: "email".to_string(),
+            title: "Weather alert: frost raised".to_string(),
+            message: "frost raised\n.hidden line".to_string(),
+            event: json!({})
+        };
+
+        send_email(&sink, &notification).unwrap();
+
+        let transcript = handle.join().unwrap();
+
+        // "\0station\0hunter2"
+        assert!(transcript.contains(&"AUTH PLAIN AHN0YXRpb24AaHVudGVyMg==".to_string()));
+        assert!(transcript.contains(&"RCPT TO:<you@example.com>".to_string()));
+        assert!(transcript.contains(&"Subject: Weather alert: frost raised".to_string()));
+        assert!(transcript.contains(&"..hidden line".to_string()));
+        assert_eq!(transcript.last().unwrap(), "QUIT");
+    }
+
+    #[test]
+    fn test_encoded_subject() {
+        let (port, handle) = start_smtp_stand_in();
+
+        let sink = SinkConfig::Email {
+            server: "127.0.0.1".to_string(),
+            port,
+            security: SmtpSecurity::None,
+            username: None,
+            password: None,
+            from: "station@example.com".to_string(),
+            to: vec![ "me@example.com".to_string() ]
+        };
+
+        let notification = Notification {
+            sink: "email".to_string(),
+            title: "-1.5°C".to_string(),
+            message: "frost raised".to_string(),
+            event: json!({})
+        };
+
+        send_email(&sink, &notification).unwrap();
+
+        // Headers are 7-bit, anything else goes out as an RFC 2047 encoded word
+        assert!(handle.join().unwrap().contains(&"Subject: =?UTF-8?B?LTEuNcKwQw==?=".to_string()));
+    }
+}
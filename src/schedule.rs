@@ -0,0 +1,150 @@
+use std::str::FromStr;
+use std::time::{ Duration, Instant };
+use chrono::{ Utc };
+use job_scheduler::{ JobScheduler, Job, Schedule };
+
+// Enough upcoming runs to see the longest gap of any sensible cron expression, e.g. hourly with a pause overnight
+const PERIOD_SAMPLE_RUNS: usize = 48;
+
+#[derive(Clone)]
+pub enum Trigger {
+    Cron(Schedule),
+    Interval(Duration)
+}
+
+impl Trigger {
+    // Accepts either a 6-field cron expression ("0 0/5 * * * *") or an interval ("every 30s", "5m", "1h")
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let expression = expression.trim();
+
+        if let Some(interval) = parse_interval(expression) {
+            return interval.map(Trigger::Interval);
+        }
+
+        Schedule::from_str(expression)
+            .map(Trigger::Cron)
+            .map_err(|e| format!("\"{}\" is not a valid cron expression or interval: {}", expression, e))
+    }
+
+    // The longest wait between two runs, for cron that's the biggest gap among the next few runs
+    pub fn get_period(&self) -> Duration {
+        match self {
+            Trigger::Interval(interval) => *interval,
+            Trigger::Cron(schedule) => {
+                let runs: Vec<_> = schedule.upcoming(Utc).take(PERIOD_SAMPLE_RUNS).collect();
+
+                runs.windows(2)
+                    .filter_map(|pair| (pair[1] - pair[0]).to_std().ok())
+                    .max()
+                    .unwrap_or_default()
+            }
+        }
+    }
+}
+
+fn parse_interval(expression: &str) -> Option<Result<Duration, String>> {
+    let value = expression.strip_prefix("every ").unwrap_or(expression).trim();
+
+    let (amount, unit) = value.split_at(value.find(|c: char| !c.is_ascii_digit())?);
+
+    if amount.is_empty() || value.contains(' ') {
+        return None;
+    }
+
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        _ => return None
+    };
+
+    Some(match amount.parse::<u64>() {
+        Ok(0) => Err(format!("\"{}\" must be at least one second", expression)),
+        Ok(amount) => amount.checked_mul(multiplier)
+            .map(Duration::from_secs)
+            .ok_or_else(|| format!("\"{}\" is too long an interval", expression)),
+        Err(e) => Err(format!("\"{}\" is not a valid interval: {}", expression, e))
+    })
+}
+
+struct IntervalJob<'a> {
+    interval: Duration,
+    last_run: Instant,
+    run: Box<dyn FnMut() + 'a>
+}
+
+// JobScheduler only understands cron, so fixed intervals are tracked alongside it
+pub struct Scheduler<'a> {
+    cron: JobScheduler<'a>,
+    intervals: Vec<IntervalJob<'a>>
+}
+
+impl<'a> Scheduler<'a> {
+    pub fn new() -> Self {
+        Self {
+            cron: JobScheduler::new(),
+            intervals: Vec::new()
+        }
+    }
+
+    pub fn add<F>(&mut self, trigger: Trigger, run: F)
+    where
+        F: FnMut() + 'a
+    {
+        match trigger {
+            Trigger::Cron(schedule) => {
+                self.cron.add(Job::new(schedule, run));
+            },
+            Trigger::Interval(interval) => {
+                self.intervals.push(IntervalJob {
+                    interval,
+                    last_run: Instant::now(),
+                    run: Box::new(run)
+                });
+            }
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.cron.tick();
+
+        for job in self.intervals.iter_mut() {
+            if job.last_run.elapsed() >= job.interval {
+                job.last_run = Instant::now();
+
+                (job.run)();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use crate::schedule::{ Trigger };
+
+    #[test]
+    fn test_parse_triggers() {
+        assert!(matches!(Trigger::parse("0 0/5 * * * *"), Ok(Trigger::Cron(_))));
+        assert!(matches!(Trigger::parse("every 30s"), Ok(Trigger::Interval(d)) if d == Duration::from_secs(30)));
+        assert!(matches!(Trigger::parse("5m"), Ok(Trigger::Interval(d)) if d == Duration::from_secs(300)));
+        assert!(matches!(Trigger::parse("1h"), Ok(Trigger::Interval(d)) if d == Duration::from_secs(3600)));
+
+        assert!(Trigger::parse("0s").is_err());
+        assert!(Trigger::parse("99999999999999999h").is_err());
+        assert!(Trigger::parse("99999999999999999999s").is_err());
+        assert!(Trigger::parse("every so often").is_err());
+        assert!(Trigger::parse("* * *").is_err());
+    }
+
+    #[test]
+    fn test_period() {
+        assert_eq!(Trigger::parse("every 30s").unwrap().get_period(), Duration::from_secs(30));
+        assert_eq!(Trigger::parse("0 0/5 * * * *").unwrap().get_period(), Duration::from_secs(300));
+        assert_eq!(Trigger::parse("0 0 0 * * *").unwrap().get_period(), Duration::from_secs(86400));
+
+        // Uneven schedules are judged by their longest gap
+        assert_eq!(Trigger::parse("0 0 6,18 * * *").unwrap().get_period(), Duration::from_secs(12 * 3600));
+        assert_eq!(Trigger::parse("0 0 8-20 * * *").unwrap().get_period(), Duration::from_secs(12 * 3600));
+    }
+}
\ No newline at end of file
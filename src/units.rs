@@ -0,0 +1,238 @@
+// Readings are kept in metric (°C, km/h, mm, hPa) everywhere else, conversions for display happen here
+
+use serde::{ Serialize, Deserialize };
+
+const KM_TO_MI: f32 = 1.609344;
+const KPH_TO_MPS: f32 = 3.6;
+const KPH_TO_KNOTS: f32 = 1.852;
+const MM_TO_IN: f32 = 25.4;
+const HPA_TO_INHG: f32 = 33.863_89;
+const HPA_TO_MMHG: f32 = 1.333_224;
+
+// Upper bound (km/h, exclusive) of Beaufort forces 0-11, anything faster is force 12
+const BEAUFORT_LIMITS: [f32; 12] = [ 2.0, 6.0, 12.0, 20.0, 29.0, 39.0, 50.0, 62.0, 75.0, 89.0, 103.0, 118.0 ];
+
+pub fn celsius_to_fahrenheit(temp_c: f32) -> f32 {
+    temp_c * 9.0 / 5.0 + 32.0
+}
+
+pub fn kph_to_mph(kph: f32) -> f32 {
+    kph / KM_TO_MI
+}
+
+pub fn kph_to_mps(kph: f32) -> f32 {
+    kph / KPH_TO_MPS
+}
+
+pub fn kph_to_knots(kph: f32) -> f32 {
+    kph / KPH_TO_KNOTS
+}
+
+pub fn kph_to_beaufort(kph: f32) -> u8 {
+    BEAUFORT_LIMITS.iter().position(|limit| kph < *limit).unwrap_or(BEAUFORT_LIMITS.len()) as u8
+}
+
+pub fn mm_to_in(mm: f32) -> f32 {
+    mm / MM_TO_IN
+}
+
+pub fn hpa_to_inhg(hpa: f32) -> f32 {
+    hpa / HPA_TO_INHG
+}
+
+pub fn hpa_to_mmhg(hpa: f32) -> f32 {
+    hpa / HPA_TO_MMHG
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TemperatureUnit {
+    Celsius,
+    Fahrenheit
+}
+
+impl TemperatureUnit {
+    pub fn convert_celsius(&self, temp_c: f32) -> f32 {
+        match self {
+            TemperatureUnit::Celsius => temp_c,
+            TemperatureUnit::Fahrenheit => celsius_to_fahrenheit(temp_c)
+        }
+    }
+
+    pub fn get_symbol(&self) -> &'static str {
+        match self {
+            TemperatureUnit::Celsius => "°C",
+            TemperatureUnit::Fahrenheit => "°F"
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SpeedUnit {
+    Kph,
+    Mph,
+    Mps,
+    Knots,
+    Beaufort
+}
+
+impl SpeedUnit {
+    pub fn convert_kph(&self, kph: f32) -> f32 {
+        match self {
+            SpeedUnit::Kph => kph,
+            SpeedUnit::Mph => kph_to_mph(kph),
+            SpeedUnit::Mps => kph_to_mps(kph),
+            SpeedUnit::Knots => kph_to_knots(kph),
+            SpeedUnit::Beaufort => kph_to_beaufort(kph) as f32
+        }
+    }
+
+    pub fn get_symbol(&self) -> &'static str {
+        match self {
+            SpeedUnit::Kph => "km/h",
+            SpeedUnit::Mph => "mph",
+            SpeedUnit::Mps => "m/s",
+            SpeedUnit::Knots => "kn",
+            SpeedUnit::Beaufort => "Bft"
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RainUnit {
+    Mm,
+    In
+}
+
+impl RainUnit {
+    pub fn convert_mm(&self, mm: f32) -> f32 {
+        match self {
+            RainUnit::Mm => mm,
+            RainUnit::In => mm_to_in(mm)
+        }
+    }
+
+    pub fn get_symbol(&self) -> &'static str {
+        match self {
+            RainUnit::Mm => "mm",
+            RainUnit::In => "in"
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PressureUnit {
+    Hpa,
+    Inhg,
+    Mmhg
+}
+
+impl PressureUnit {
+    pub fn convert_hpa(&self, hpa: f32) -> f32 {
+        match self {
+            PressureUnit::Hpa => hpa,
+            PressureUnit::Inhg => hpa_to_inhg(hpa),
+            PressureUnit::Mmhg => hpa_to_mmhg(hpa)
+        }
+    }
+
+    pub fn get_symbol(&self) -> &'static str {
+        match self {
+            PressureUnit::Hpa => "hPa",
+            PressureUnit::Inhg => "inHg",
+            PressureUnit::Mmhg => "mmHg"
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Units {
+    pub temperature: TemperatureUnit,
+    pub speed: SpeedUnit,
+    pub rain: RainUnit,
+    pub pressure: PressureUnit
+}
+
+impl Units {
+    // Symbols for each quantity, so API consumers know what the unit-neutral fields are in
+    pub fn get_symbols(&self) -> [(&'static str, &'static str); 4] {
+        [
+            ("temperature", self.temperature.get_symbol()),
+            ("speed", self.speed.get_symbol()),
+            ("rain", self.rain.get_symbol()),
+            ("pressure", self.pressure.get_symbol())
+        ]
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UnitSystem {
+    Metric,             // °C, km/h, mm, hPa
+    Imperial,           // °F, mph, in, inHg
+    Si                  // °C, m/s, mm, hPa
+}
+
+impl UnitSystem {
+    pub fn parse(system: &str) -> Option<Self> {
+        match system {
+            "metric" => Some(UnitSystem::Metric),
+            "imperial" => Some(UnitSystem::Imperial),
+            "si" => Some(UnitSystem::Si),
+            _ => None
+        }
+    }
+
+    pub fn get_units(&self) -> Units {
+        match self {
+            UnitSystem::Metric => Units { temperature: TemperatureUnit::Celsius, speed: SpeedUnit::Kph, rain: RainUnit::Mm, pressure: PressureUnit::Hpa },
+            UnitSystem::Imperial => Units { temperature: TemperatureUnit::Fahrenheit, speed: SpeedUnit::Mph, rain: RainUnit::In, pressure: PressureUnit::Inhg },
+            UnitSystem::Si => Units { temperature: TemperatureUnit::Celsius, speed: SpeedUnit::Mps, rain: RainUnit::Mm, pressure: PressureUnit::Hpa }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::units::{ UnitSystem, SpeedUnit, PressureUnit, kph_to_beaufort, celsius_to_fahrenheit, mm_to_in };
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 0.01, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_close(celsius_to_fahrenheit(-40.0), -40.0);
+        assert_close(celsius_to_fahrenheit(100.0), 212.0);
+        assert_close(mm_to_in(25.4), 1.0);
+
+        assert_close(SpeedUnit::Mph.convert_kph(100.0), 62.14);
+        assert_close(SpeedUnit::Mps.convert_kph(36.0), 10.0);
+        assert_close(SpeedUnit::Knots.convert_kph(18.52), 10.0);
+
+        assert_close(PressureUnit::Inhg.convert_hpa(1013.25), 29.92);
+        assert_close(PressureUnit::Mmhg.convert_hpa(1013.25), 760.0);
+    }
+
+    #[test]
+    fn test_beaufort() {
+        assert_eq!(kph_to_beaufort(0.0), 0);
+        assert_eq!(kph_to_beaufort(1.9), 0);
+        assert_eq!(kph_to_beaufort(2.0), 1);
+        assert_eq!(kph_to_beaufort(35.0), 5);
+        assert_eq!(kph_to_beaufort(117.9), 11);
+        assert_eq!(kph_to_beaufort(150.0), 12);
+    }
+
+    #[test]
+    fn test_systems() {
+        assert_eq!(UnitSystem::parse("imperial"), Some(UnitSystem::Imperial));
+        assert_eq!(UnitSystem::parse("furlongs"), None);
+
+        assert_eq!(UnitSystem::Si.get_units().speed, SpeedUnit::Mps);
+        assert_eq!(UnitSystem::Imperial.get_units().pressure, PressureUnit::Inhg);
+    }
+}
@@ -50,6 +50,47 @@ const fetchLatest = () => {
 		updateData(true)
 	})
 	.catch(err => console.log(err))
+
+	fetch("/api/probes")
+	.then(res => res.json())
+	.then(data => updateProbes(data))
+	.catch(err => console.log(err))
+}
+
+// Draws the last day of each probe's readings as a line
+const probeGraph = (samples, width=300, height=60) => {
+	const temps = samples.map(sample => sample[1])
+	const min = Math.min(...temps)
+	const range = Math.max(Math.max(...temps) - min, 0.1)
+
+	const points = temps.map((temp, i) => {
+		const x = samples.length > 1 ? (i / (samples.length - 1)) * width : 0
+		const y = height - ((temp - min) / range) * height
+
+		return `${x.toFixed(1)},${y.toFixed(1)}`
+	}).join(" ")
+
+	return `<svg width="${width}" height="${height}"><polyline fill="none" stroke="currentColor" stroke-width="2" points="${points}"/></svg>`
+}
+
+const updateProbes = (history) => {
+	const names = Object.keys(history)
+
+	if(names.length == 0) {
+		return
+	}
+
+	const probes = apiData.probes || {}
+
+	const html = names.map(name => {
+		const latest = probes[name]
+		const reading = latest ? `${latest.temp_f.toFixed(1)}°F (${latest.temp_c.toFixed(1)}°C)` : "???"
+
+		return `<h4 class="w-auto h-auto mb-2 pb-0"><em>${name}: </em>${reading}</h4>${probeGraph(history[name])}`
+	}).join("")
+
+	$("#probe_list").html(html)
+	$("#probes").attr("style", "")
 }
 
 const updateData = (fullUpdate=false) => {